@@ -11,6 +11,7 @@ pub mod table {
         data: Vec<Vec<String>>,
         constraints: Vec<Constraint>,
         selected_row: i32,
+        marked_rows: Vec<usize>,
         title: String,
     }
 
@@ -22,6 +23,7 @@ pub mod table {
                 data: Vec::new(),
                 constraints,
                 selected_row: 0,
+                marked_rows: Vec::new(),
                 title: String::new(),
             }
         }
@@ -31,6 +33,8 @@ pub mod table {
         }
 
         pub fn set_data(&mut self, data: Vec<Vec<String>>) {
+            // marks are row indices, they mean nothing for other data
+            self.marked_rows.clear();
             self.data = data;
         }
 
@@ -50,6 +54,25 @@ pub mod table {
             self.selected_row as usize
         }
 
+        pub fn toggle_mark(&mut self) {
+            let row = self.selected_row();
+            if row >= self.data.len() {
+                return;
+            }
+            match self.marked_rows.iter().position(|r| *r == row) {
+                Some(i) => {
+                    self.marked_rows.remove(i);
+                }
+                None => self.marked_rows.push(row),
+            }
+        }
+
+        pub fn marked_rows(&self) -> Vec<usize> {
+            let mut rows = self.marked_rows.clone();
+            rows.sort();
+            rows
+        }
+
         pub fn render(&self, frame: &mut Frame, area: Rect) {
             let mut v = vec![Row::new(self.header.clone()).style(Style::default().fg(Color::Gray))];
 
@@ -63,6 +86,8 @@ pub mod table {
                                 Color::Gray
                             })
                             .fg(Color::White)
+                    } else if self.marked_rows.contains(&i) {
+                        Style::default().fg(Color::Magenta)
                     } else {
                         Style::default()
                     }),
@@ -87,3 +112,49 @@ pub mod table {
         }
     }
 }
+
+pub mod cover {
+    use image::imageops::FilterType;
+    use image::RgbaImage;
+    use ratatui::layout::Rect;
+    use ratatui::prelude::{Color, Line, Span, Style};
+    use ratatui::widgets::{Block, Borders, Paragraph};
+    use ratatui::Frame;
+
+    /// Draws a picture with half blocks, every cell shows two pixels stacked on each other.
+    pub fn render(frame: &mut Frame, area: Rect, image: &RgbaImage, title: &str) {
+        let block = Block::default().borders(Borders::ALL).title(title);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        // terminal cells are about twice as high as wide, which half blocks even out
+        let side = (inner.width as u32).min(inner.height as u32 * 2);
+        if side < 2 {
+            return;
+        }
+        let scaled = image::imageops::resize(image, side, side, FilterType::Triangle);
+
+        let color = |x: u32, y: u32| {
+            let [r, g, b, _] = scaled.get_pixel(x, y).0;
+            Color::Rgb(r, g, b)
+        };
+        let lines: Vec<Line> = (0..side / 2)
+            .map(|row| {
+                Line::from(
+                    (0..side)
+                        .map(|x| {
+                            Span::styled(
+                                "▀",
+                                Style::default()
+                                    .fg(color(x, row * 2))
+                                    .bg(color(x, row * 2 + 1)),
+                            )
+                        })
+                        .collect::<Vec<Span>>(),
+                )
+            })
+            .collect();
+
+        frame.render_widget(Paragraph::new(lines).centered(), inner);
+    }
+}
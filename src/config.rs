@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn get_configs_dir() -> PathBuf {
@@ -14,9 +14,40 @@ pub fn get_temp_dl_dir() -> PathBuf {
     p
 }
 
-pub fn clear_temp_dl_dir() {
-    let path = get_temp_dl_dir();
-    let _ = std::fs::remove_dir_all(path);
+/// Clears the temporary downloads, except the directories in `keep`.
+pub fn clear_temp_dl_dir(keep: &[PathBuf]) {
+    clear_dir_except(&get_temp_dl_dir(), keep);
+}
+
+fn clear_dir_except(dir: &Path, keep: &[PathBuf]) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_e) => return,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if keep.contains(&path) {
+            continue;
+        }
+        if keep.iter().any(|k| k.starts_with(&path)) {
+            clear_dir_except(&path, keep);
+        } else if path.is_dir() {
+            let _ = std::fs::remove_dir_all(path);
+        } else {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Removes the transcoded and lyrics copies, leaving alone the directories queued downloads are still using.
+pub fn clear_temp_files() {
+    let dir = get_temp_dl_dir();
+    clear_dir_except(&dir, &[dir.join("queue")]);
+}
+
+pub fn get_transcode_cache_dir() -> PathBuf {
+    let mut p = get_configs_dir();
+    p.push("transcode_cache");
+    p
 }
 
 pub fn get_config_path() -> PathBuf {
@@ -60,10 +91,254 @@ pub struct SoundCloudConfiguration {
     pub user_id: u64,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TranscodeConfiguration {
+    pub ffmpeg_path: String,
+    // target for FLAC: "alac", "aac" or "mp3"
+    pub lossless_format: String,
+    // target for Ogg Vorbis, Opus and WMA: "aac" or "mp3"
+    pub lossy_format: String,
+    // kbps, ignored for ALAC
+    pub bitrate: u32,
+    // kbps, with the space saving profile lossless tracks and anything above this get re-encoded
+    pub space_saving_threshold: u32,
+    // "aac" or "mp3"
+    pub space_saving_format: String,
+    // kbps
+    pub space_saving_bitrate: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum TranscodeProfile {
+    #[default]
+    Original,
+    SpaceSaving,
+}
+
+impl TranscodeProfile {
+    pub fn toggle(self) -> Self {
+        match self {
+            TranscodeProfile::Original => TranscodeProfile::SpaceSaving,
+            TranscodeProfile::SpaceSaving => TranscodeProfile::Original,
+        }
+    }
+}
+
+impl From<TranscodeProfile> for String {
+    fn from(value: TranscodeProfile) -> Self {
+        match value {
+            TranscodeProfile::Original => "ORIGINAL",
+            TranscodeProfile::SpaceSaving => "SPACE SAVING",
+        }
+        .to_string()
+    }
+}
+
+impl Default for TranscodeConfiguration {
+    fn default() -> Self {
+        Self {
+            ffmpeg_path: "ffmpeg".to_string(),
+            lossless_format: "alac".to_string(),
+            lossy_format: "aac".to_string(),
+            bitrate: 256,
+            space_saving_threshold: 288,
+            space_saving_format: "aac".to_string(),
+            space_saving_bitrate: 256,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct SoundCheckConfiguration {
+    // measure EBU R128 loudness of tracks that carry no ReplayGain or iTunNORM tags
+    pub analyze_loudness: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AcoustIdConfiguration {
+    pub fpcalc_path: String,
+    // anything speaking the AcoustID v2 API, e.g. a local mirror
+    pub base_url: String,
+    pub api_key: String,
+}
+
+impl Default for AcoustIdConfiguration {
+    fn default() -> Self {
+        Self {
+            fpcalc_path: "fpcalc".to_string(),
+            base_url: "https://api.acoustid.org/v2".to_string(),
+            api_key: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MusicBrainzConfiguration {
+    // any MusicBrainz-compatible web service, e.g. a local mirror
+    pub base_url: String,
+    // musicbrainz.org allows one request per second
+    pub min_interval_ms: u64,
+}
+
+impl Default for MusicBrainzConfiguration {
+    fn default() -> Self {
+        Self {
+            base_url: "https://musicbrainz.org/ws/2".to_string(),
+            min_interval_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CoverArtConfiguration {
+    // Cover Art Archive-compatible service, covers are looked up by MusicBrainz release id.
+    // Leave empty to only use embedded pictures and cover files next to the tracks.
+    pub base_url: String,
+    // how covers which aren't square are fitted: "crop", "letterbox" or "smart"
+    pub fit: String,
+    // "#rrggbb", fills the bars around letterboxed covers
+    pub background: String,
+    // "none", "ordered" or "floyd-steinberg"
+    pub dither: String,
+}
+
+/// How a cover is made to fit the square thumbnails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverFit {
+    // the centre square
+    Crop,
+    // the whole picture, with bars in the background colour
+    Letterbox,
+    // cuts black bars off and keeps the busiest square, for video thumbnails
+    Smart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    Ordered,
+    FloydSteinberg,
+}
+
+impl CoverArtConfiguration {
+    pub fn get_fit(&self) -> CoverFit {
+        match self.fit.as_str() {
+            "letterbox" => CoverFit::Letterbox,
+            "smart" => CoverFit::Smart,
+            _ => CoverFit::Crop,
+        }
+    }
+
+    pub fn get_dither(&self) -> Dither {
+        match self.dither.as_str() {
+            "none" => Dither::None,
+            "ordered" => Dither::Ordered,
+            _ => Dither::FloydSteinberg,
+        }
+    }
+
+    pub fn get_background(&self) -> [u8; 3] {
+        let hex = self.background.trim_start_matches('#');
+        match u32::from_str_radix(hex, 16) {
+            Ok(rgb) if hex.len() == 6 => [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8],
+            _ => [0, 0, 0],
+        }
+    }
+}
+
+impl Default for CoverArtConfiguration {
+    fn default() -> Self {
+        Self {
+            base_url: "https://coverartarchive.org".to_string(),
+            fit: "crop".to_string(),
+            background: "#000000".to_string(),
+            dither: "floyd-steinberg".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DownloaderConfiguration {
+    // downloads running at once, the rest waits in the queue
+    pub concurrency: usize,
+    pub binary_path: String,
+    // passed to yt-dlp as they are, before the URL
+    pub extra_args: Vec<String>,
+    // Netscape cookies file for members only or age restricted videos, empty for none
+    pub cookies_file: String,
+    // e.g. "socks5://127.0.0.1:1080", empty for none
+    pub proxy: String,
+    // bytes per second, e.g. "2M", empty for no limit
+    pub rate_limit: String,
+    // "mp3", or "m4a" which keeps the AAC YouTube serves without re-encoding it
+    pub format: String,
+    // a single upload with chapters, such as a full album, becomes one track per chapter
+    pub split_chapters: bool,
+}
+
+/// The audio format yt-dlp extracts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadFormat {
+    Mp3,
+    M4a,
+}
+
+impl DownloadFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DownloadFormat::Mp3 => "mp3",
+            DownloadFormat::M4a => "m4a",
+        }
+    }
+}
+
+impl DownloaderConfiguration {
+    pub fn get_format(&self) -> DownloadFormat {
+        match self.format.as_str() {
+            "m4a" => DownloadFormat::M4a,
+            _ => DownloadFormat::Mp3,
+        }
+    }
+}
+
+impl Default for DownloaderConfiguration {
+    fn default() -> Self {
+        Self {
+            concurrency: 2,
+            binary_path: "yt-dlp".to_string(),
+            extra_args: Vec::new(),
+            cookies_file: String::new(),
+            proxy: String::new(),
+            rate_limit: String::new(),
+            format: "mp3".to_string(),
+            split_chapters: false,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct LyricaConfiguration {
     soundcloud: SoundCloudConfiguration,
     youtube: YouTubeConfiguration,
+    #[serde(default)]
+    transcode: TranscodeConfiguration,
+    #[serde(default)]
+    soundcheck: SoundCheckConfiguration,
+    #[serde(default)]
+    acoustid: AcoustIdConfiguration,
+    #[serde(default)]
+    musicbrainz: MusicBrainzConfiguration,
+    #[serde(default)]
+    coverart: CoverArtConfiguration,
+    #[serde(default)]
+    downloader: DownloaderConfiguration,
 }
 
 impl LyricaConfiguration {
@@ -74,4 +349,40 @@ impl LyricaConfiguration {
     pub fn get_youtube(&self) -> &YouTubeConfiguration {
         &self.youtube
     }
+
+    pub fn get_transcode(&self) -> &TranscodeConfiguration {
+        &self.transcode
+    }
+
+    pub fn get_soundcheck(&self) -> &SoundCheckConfiguration {
+        &self.soundcheck
+    }
+
+    pub fn get_acoustid(&self) -> &AcoustIdConfiguration {
+        &self.acoustid
+    }
+
+    pub fn get_musicbrainz(&self) -> &MusicBrainzConfiguration {
+        &self.musicbrainz
+    }
+
+    pub fn get_coverart(&self) -> &CoverArtConfiguration {
+        &self.coverart
+    }
+
+    pub fn get_downloader(&self) -> &DownloaderConfiguration {
+        &self.downloader
+    }
+}
+
+pub fn load_config() -> LyricaConfiguration {
+    let p = get_config_path();
+    if !p.exists() {
+        let config = LyricaConfiguration::default();
+        let cfg_str = toml::to_string_pretty(&config).unwrap();
+        let _ = std::fs::write(&p, cfg_str);
+        return config;
+    }
+    let content = std::fs::read_to_string(p).unwrap_or_default();
+    toml::from_str(&content).unwrap()
 }
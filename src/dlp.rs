@@ -1,13 +1,15 @@
 use ratatui::style::Color;
 use regex::Regex;
 use serde::Deserialize;
-use std::{io, path::PathBuf, process::Stdio};
+use std::{io, path::Path, process::Stdio, sync::LazyLock};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     process::Command,
     sync::mpsc::Sender,
 };
 
+use crate::config::{load_config, DownloadFormat, DownloaderConfiguration};
+use crate::sync::metadata::{MetadataChanges, MetadataField};
 use crate::sync::sync_util::AppEvent;
 
 #[derive(Debug, Deserialize)]
@@ -17,138 +19,155 @@ pub struct DownloadProgress {
     pub eta: String,
 }
 
-pub async fn download_track_from_youtube(
-    track_url: &str,
-    download_dir: &PathBuf,
-    sender: Sender<AppEvent>,
-) -> io::Result<()> {
-    let _ = sender
-        .send(AppEvent::SwitchScreen(crate::AppState::LoadingScreen))
-        .await;
-
-    if download_dir.exists() {
-        let _ = std::fs::remove_dir_all(download_dir);
-    }
-    let _ = std::fs::create_dir_all(download_dir);
+/// What yt-dlp knows about a URL or a downloaded item, a playlist lists its entries.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MediaInfo {
+    // flat playlist entries of unavailable videos can come without one
+    #[serde(default)]
+    pub id: String,
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    pub channel: Option<String>,
+    // "Youtube", "Bandcamp", "Soundcloud", ...
+    pub extractor_key: Option<String>,
+    pub entries: Option<Vec<MediaInfo>>,
+    // music metadata, YouTube Music and Bandcamp fill these in
+    pub artist: Option<String>,
+    pub track: Option<String>,
+    pub album: Option<String>,
+    pub release_year: Option<u32>,
+    // YYYYMMDD
+    pub release_date: Option<String>,
+    pub upload_date: Option<String>,
+    pub description: Option<String>,
+    pub playlist_index: Option<u32>,
+    // seconds
+    pub duration: Option<f64>,
+    pub chapters: Option<Vec<Chapter>>,
+}
 
-    let args = &[
-        "-f",
-        "bestaudio",
-        "-x",
-        "--audio-format",
-        "mp3",
-        "--audio-quality",
-        "0",
-        "-o",
-        "%(id)s.%(ext)s",
-        "--ignore-errors",
-        "--newline",
-        "--progress-template",
-        "{\"progress_percentage\":\"%(progress._percent_str)s\",\"progress_total\":\"%(progress._total_bytes_str)s\",\"speed\":\"%(progress._speed_str)s\",\"eta\":\"%(progress._eta_str)s\"}",
-        "--write-thumbnail",
-        &*["https://youtube.com/watch?v=", track_url].concat()
-    ];
+#[derive(Debug, Clone, Deserialize)]
+pub struct Chapter {
+    // seconds
+    pub start_time: f64,
+    pub end_time: f64,
+    pub title: Option<String>,
+}
 
-    let mut command = Command::new("yt-dlp");
-    command.args(args);
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::null());
-    command.current_dir(download_dir);
+impl MediaInfo {
+    pub fn artist(&self) -> Option<String> {
+        self.artist
+            .clone()
+            .or_else(|| self.uploader.clone())
+            .or_else(|| self.channel.clone())
+    }
 
-    let mut child = command.spawn()?;
+    pub fn year(&self) -> Option<u32> {
+        self.release_year.or_else(|| {
+            [&self.release_date, &self.upload_date]
+                .into_iter()
+                .flatten()
+                .find_map(|date| date.get(..4)?.parse().ok())
+        })
+    }
 
-    let stdout = child.stdout.take().unwrap();
-    let mut reader = BufReader::new(stdout).lines();
+    /// The tags the info carries, on top of what the importer already set from the file and the site.
+    pub fn changes(&self) -> MetadataChanges {
+        let mut changes = MetadataChanges::new();
+        let mut set = |field, value: Option<String>| {
+            if let Some(value) = value.filter(|v| !v.trim().is_empty()) {
+                changes.insert(field, value);
+            }
+        };
+        set(MetadataField::Title, self.track.clone());
+        set(MetadataField::Artist, self.artist.clone());
+        set(MetadataField::Album, self.album.clone());
+        set(MetadataField::Year, self.year().map(|y| y.to_string()));
+        set(MetadataField::Comment, self.description.clone());
+        set(
+            MetadataField::TrackNumber,
+            self.playlist_index.map(|i| i.to_string()),
+        );
+        changes
+    }
+}
 
-    while let Ok(Some(line)) = reader.next_line().await {
-        if line.starts_with("{") {
-            let progress: DownloadProgress = serde_json::from_str(&line).unwrap();
-            let _ = sender
-                .send(AppEvent::OverallProgress((0, 1, Color::Green)))
-                .await;
-            let _ = sender.send(AppEvent::CurrentProgress(progress)).await;
-        }
+// entries without an id have nothing to download or import, they are left out
+fn parse_info(data: &[u8]) -> Option<MediaInfo> {
+    let mut info: MediaInfo = serde_json::from_slice(data).ok()?;
+    if let Some(entries) = info.entries.as_mut() {
+        entries.retain(|e| !e.id.is_empty());
     }
-    let _ = sender
-        .send(AppEvent::OverallProgress((1, 1, Color::Green)))
-        .await;
-    Ok(())
+    Some(info)
 }
 
-pub async fn download_track_from_soundcloud(
-    track_url: &str,
-    download_dir: &PathBuf,
-    sender: Sender<AppEvent>,
-) -> io::Result<()> {
-    let _ = sender
-        .send(AppEvent::SwitchScreen(crate::AppState::LoadingScreen))
-        .await;
+/// Reads the `<id>.info.json` yt-dlp wrote next to a download.
+pub fn read_info(download_dir: &Path, id: &str) -> Option<MediaInfo> {
+    let path = download_dir.join([id, ".info.json"].concat());
+    parse_info(&std::fs::read(path).ok()?)
+}
 
-    if download_dir.exists() {
-        let _ = std::fs::remove_dir_all(download_dir);
+// the binary and the connection settings every run shares
+fn command(config: &DownloaderConfiguration) -> Command {
+    let mut command = Command::new(&config.binary_path);
+    if !config.cookies_file.is_empty() {
+        command.args(["--cookies", &config.cookies_file]);
     }
-    let _ = std::fs::create_dir_all(download_dir);
+    if !config.proxy.is_empty() {
+        command.args(["--proxy", &config.proxy]);
+    }
+    command
+}
 
-    let args = &[
-        "-f",
-        "mp3",
+/// Resolves the title and, for a playlist, the entries of any URL yt-dlp supports without downloading it.
+pub async fn resolve(url: &str) -> io::Result<MediaInfo> {
+    let config = load_config();
+    let mut command = command(config.get_downloader());
+    command.args([
+        "--dump-single-json",
+        "--flat-playlist",
         "--ignore-errors",
-        "--newline",
-        "--progress-template",
-        "{\"progress_percentage\":\"%(progress._percent_str)s\",\"progress_total\":\"%(progress._total_bytes_str)s\",\"speed\":\"%(progress._speed_str)s\",\"eta\":\"%(progress._eta_str)s\"}",
-        "-o",
-        "%(id)i.%(ext)s",
-        "--write-thumbnail",
-        track_url
-    ];
-
-    let mut command = Command::new("yt-dlp");
-    command.args(args);
-    command.stdout(Stdio::piped());
+        url,
+    ]);
     command.stderr(Stdio::null());
-    command.current_dir(download_dir);
-
-    let mut child = command.spawn()?;
-
-    let stdout = child.stdout.take().unwrap();
-    let mut reader = BufReader::new(stdout).lines();
-
-    while let Ok(Some(line)) = reader.next_line().await {
-        if line.starts_with("{") {
-            let progress: DownloadProgress = serde_json::from_str(&line).unwrap();
-            let _ = sender
-                .send(AppEvent::OverallProgress((0, 1, Color::Green)))
-                .await;
-            let _ = sender.send(AppEvent::CurrentProgress(progress)).await;
-        }
-    }
-    let _ = sender
-        .send(AppEvent::OverallProgress((1, 1, Color::Green)))
-        .await;
-    Ok(())
+    command.kill_on_drop(true);
+
+    let output = command.output().await?;
+    parse_info(&output.stdout).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the URL isn't supported or can't be reached",
+        )
+    })
 }
 
-pub async fn download_from_youtube(
-    playlist_url: &str,
-    download_dir: &PathBuf,
+static PLAYLIST_ITEM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[download\] Downloading item \d+ of \d+").unwrap());
+
+/// Downloads a track or a whole playlist into `download_dir` as `<id>.<format>` next to its thumbnail,
+/// otherwise the way the `[downloader]` section says.
+/// Progress goes to `sender`, the position in a playlist as `OverallProgress`.
+pub async fn download(
+    url: &str,
+    download_dir: &Path,
+    format: DownloadFormat,
     sender: Sender<AppEvent>,
 ) -> io::Result<()> {
-    let _ = sender
-        .send(AppEvent::SwitchScreen(crate::AppState::LoadingScreen))
-        .await;
-    let dl_rx: Regex = Regex::new(r"\[download\] Downloading item \d+ of \d+").unwrap();
+    let config = load_config();
+    let config = config.get_downloader();
+    let format = format.extension();
 
-    if download_dir.exists() {
-        let _ = std::fs::remove_dir_all(download_dir);
-    }
+    // what is already there was left by the last run, yt-dlp carries on from it
     let _ = std::fs::create_dir_all(download_dir);
 
-    let args = &[
+    let mut command = command(config);
+    // a source already in the format is only remuxed, anything else gets converted
+    command.args([
         "-f",
-        "bestaudio",
+        &format!("bestaudio[ext={}]/bestaudio", format),
         "-x",
         "--audio-format",
-        "mp3",
+        format,
         "--audio-quality",
         "0",
         "-o",
@@ -158,77 +177,18 @@ pub async fn download_from_youtube(
         "--progress-template",
         "{\"progress_percentage\":\"%(progress._percent_str)s\",\"progress_total\":\"%(progress._total_bytes_str)s\",\"speed\":\"%(progress._speed_str)s\",\"eta\":\"%(progress._eta_str)s\"}",
         "--write-thumbnail",
-        &*["https://youtube.com", playlist_url].concat()
-    ];
-
-    let mut command = Command::new("yt-dlp");
-    command.args(args);
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::null());
-    command.current_dir(download_dir);
-
-    let mut child = command.spawn()?;
-
-    let stdout = child.stdout.take().unwrap();
-    let mut reader = BufReader::new(stdout).lines();
-
-    while let Ok(Some(line)) = reader.next_line().await {
-        match dl_rx.find(&line) {
-            Some(m) => {
-                let mut s = m.as_str();
-                s = s.split("Downloading item ").last().unwrap();
-                let s: Vec<&str> = s.split(' ').collect();
-                let cur = s.first().unwrap().trim().parse().unwrap();
-                let max = s.last().unwrap().trim().parse().unwrap();
-                let _ = sender
-                    .send(AppEvent::OverallProgress((cur, max, Color::Green)))
-                    .await;
-            }
-            None => {
-                if line.starts_with("{") {
-                    let progress: DownloadProgress = serde_json::from_str(&line).unwrap();
-                    let _ = sender.send(AppEvent::CurrentProgress(progress)).await;
-                }
-            }
-        }
+        "--write-info-json",
+    ]);
+    if !config.rate_limit.is_empty() {
+        command.args(["--limit-rate", &config.rate_limit]);
     }
-
-    Ok(())
-}
-
-pub async fn download_from_soundcloud(
-    playlist_url: &str,
-    download_dir: &PathBuf,
-    sender: Sender<AppEvent>,
-) -> io::Result<()> {
-    let _ = sender
-        .send(AppEvent::SwitchScreen(crate::AppState::LoadingScreen))
-        .await;
-    let dl_rx: Regex = Regex::new(r"\[download\] Downloading item \d+ of \d+").unwrap();
-
-    if download_dir.exists() {
-        let _ = std::fs::remove_dir_all(download_dir);
-    }
-    let _ = std::fs::create_dir_all(download_dir);
-
-    let args = &[
-            "-f",
-            "mp3",
-            "--ignore-errors", 
-            "--newline", 
-            "--progress-template", 
-            "{\"progress_percentage\":\"%(progress._percent_str)s\",\"progress_total\":\"%(progress._total_bytes_str)s\",\"speed\":\"%(progress._speed_str)s\",\"eta\":\"%(progress._eta_str)s\"}", 
-            "-o", 
-            "%(id)i.%(ext)s", 
-            "--write-thumbnail", 
-            playlist_url
-    ];
-
-    let mut command = Command::new("yt-dlp");
-    command.args(args);
+    command.args(&config.extra_args);
+    command.arg(url);
     command.stdout(Stdio::piped());
     command.stderr(Stdio::null());
     command.current_dir(download_dir);
+    // a cancelled queue entry drops the future, which has to take yt-dlp down with it
+    command.kill_on_drop(true);
 
     let mut child = command.spawn()?;
 
@@ -236,7 +196,7 @@ pub async fn download_from_soundcloud(
     let mut reader = BufReader::new(stdout).lines();
 
     while let Ok(Some(line)) = reader.next_line().await {
-        match dl_rx.find(&line) {
+        match PLAYLIST_ITEM.find(&line) {
             Some(m) => {
                 let mut s = m.as_str();
                 s = s.split("Downloading item ").last().unwrap();
@@ -249,12 +209,18 @@ pub async fn download_from_soundcloud(
             }
             None => {
                 if line.starts_with("{") {
-                    let progress: DownloadProgress = serde_json::from_str(&line).unwrap();
-                    let _ = sender.send(AppEvent::CurrentProgress(progress)).await;
+                    if let Ok(progress) = serde_json::from_str::<DownloadProgress>(&line) {
+                        let _ = sender.send(AppEvent::CurrentProgress(progress)).await;
+                    }
                 }
             }
         }
     }
+    let status = child.wait().await?;
+    // with --ignore-errors this also means some item of a playlist couldn't be downloaded
+    if !status.success() {
+        return Err(io::Error::other(status.to_string()));
+    }
 
     Ok(())
 }
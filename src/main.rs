@@ -15,8 +15,14 @@ use ratatui::{
     Frame, Terminal,
 };
 use screens::file_system::FileSystem;
+use screens::identify_screen::IdentifyScreen;
+use screens::import_report::ImportReport;
 use screens::loading_screen::LoadingScreen;
+use screens::lookup_screen::LookupScreen;
 use screens::main_screen::MainScreen;
+use screens::metadata_editor::MetadataEditor;
+use screens::plan_screen::PlanScreen;
+use screens::queue_screen::QueueScreen;
 use screens::search_util::SearchScreen;
 use screens::wait_screen::WaitScreen;
 use screens::AppScreen;
@@ -40,6 +46,12 @@ enum AppState {
     LoadingScreen,
     FileSystem,
     SearchScreen,
+    MetadataEditor,
+    PlanScreen,
+    ImportReport,
+    IdentifyScreen,
+    LookupScreen,
+    QueueScreen,
 }
 
 pub struct App {
@@ -50,13 +62,17 @@ pub struct App {
     token: CancellationToken,
 }
 
-impl Default for App {
-    fn default() -> Self {
+impl App {
+    pub fn new(dry_run: bool) -> Self {
+        if dry_run {
+            sync::device::enable_dry_run();
+        }
+
         let (tx, rx) = mpsc::channel(10);
         let (jx, jr) = mpsc::unbounded_channel();
         let token = CancellationToken::new();
 
-        sync::initialize_async_service(tx, jr, token.clone());
+        sync::initialize_async_service(tx, jr, jx.clone(), token.clone());
 
         let _ = jx.send(AppEvent::SearchIPod);
 
@@ -69,6 +85,27 @@ impl Default for App {
             AppState::SearchScreen,
             Box::new(SearchScreen::new(jx.clone())),
         );
+        screens.insert(
+            AppState::MetadataEditor,
+            Box::new(MetadataEditor::new(jx.clone())),
+        );
+        screens.insert(AppState::PlanScreen, Box::new(PlanScreen::new(jx.clone())));
+        screens.insert(
+            AppState::ImportReport,
+            Box::new(ImportReport::new(jx.clone())),
+        );
+        screens.insert(
+            AppState::IdentifyScreen,
+            Box::new(IdentifyScreen::new(jx.clone())),
+        );
+        screens.insert(
+            AppState::LookupScreen,
+            Box::new(LookupScreen::new(jx.clone())),
+        );
+        screens.insert(
+            AppState::QueueScreen,
+            Box::new(QueueScreen::new(jx.clone())),
+        );
 
         Self {
             receiver: rx,
@@ -78,9 +115,7 @@ impl Default for App {
             screens,
         }
     }
-}
 
-impl App {
     pub async fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
         let mut reader = EventStream::new();
         while !self.token.is_cancelled() {
@@ -122,8 +157,12 @@ impl App {
                         screen.set_playlists(TabType::Youtube, TabContent::Youtube(playlists));
                     },
                     AppEvent::OverallProgress((c, max, color)) => {
+                        let starting = self.state != AppState::LoadingScreen;
                         self.state = AppState::LoadingScreen;
                         let screen: &mut LoadingScreen = self.get_screen(&AppState::LoadingScreen);
+                        if starting {
+                            screen.cancelling = false;
+                        }
                         screen.progress = Some((c, max, color));
                         screen.artwork_progress = None;
                     },
@@ -138,6 +177,10 @@ impl App {
                         screen.s_progress = None;
                     },
                     AppEvent::SwitchScreen(screen) => {
+                        if screen == AppState::LoadingScreen && self.state != screen {
+                            let loading: &mut LoadingScreen = self.get_screen(&AppState::LoadingScreen);
+                            loading.cancelling = false;
+                        }
                         self.state = screen;
                     }
                     AppEvent::SearchShow(entries) => {
@@ -145,6 +188,64 @@ impl App {
                         screen.show_search(entries);
                         self.state = AppState::SearchScreen;
                     }
+                    AppEvent::LyricsGot((id, lyrics)) => {
+                        let screen: &mut MainScreen = self.get_screen(&AppState::MainScreen);
+                        screen.set_lyrics(id, lyrics);
+                    }
+                    AppEvent::CoverGot((id, cover)) => {
+                        let screen: &mut MainScreen = self.get_screen(&AppState::MainScreen);
+                        screen.set_cover(id, cover);
+                    }
+                    AppEvent::CoversExported((count, dir)) => {
+                        let screen: &mut MainScreen = self.get_screen(&AppState::MainScreen);
+                        screen.set_exported_covers(count, dir);
+                    }
+                    AppEvent::DryRunReport(report) => {
+                        let screen: &mut PlanScreen = self.get_screen(&AppState::PlanScreen);
+                        screen.show_report(report);
+                        self.state = AppState::PlanScreen;
+                    }
+                    AppEvent::ImportSummary(summary) => {
+                        let screen: &mut FileSystem = self.get_screen(&AppState::FileSystem);
+                        screen.set_summary(summary.clone());
+                        let screen: &mut MainScreen = self.get_screen(&AppState::MainScreen);
+                        screen.set_summary(summary.clone());
+                        let screen: &mut ImportReport = self.get_screen(&AppState::ImportReport);
+                        screen.show_summary(summary);
+                        self.state = AppState::ImportReport;
+                    }
+                    AppEvent::IdentifyShow(identified) => {
+                        let screen: &mut IdentifyScreen = self.get_screen(&AppState::IdentifyScreen);
+                        screen.show_identified(identified);
+                        self.state = AppState::IdentifyScreen;
+                    }
+                    AppEvent::MusicBrainzCandidates((ids, candidates)) => {
+                        let screen: &mut LookupScreen = self.get_screen(&AppState::LookupScreen);
+                        screen.show_candidates(ids, candidates);
+                        self.state = AppState::LookupScreen;
+                    }
+                    AppEvent::MusicBrainzProposal(proposal) => {
+                        let screen: &mut LookupScreen = self.get_screen(&AppState::LookupScreen);
+                        screen.show_proposal(proposal);
+                        self.state = AppState::LookupScreen;
+                    }
+                    AppEvent::PickArtwork(ids) => {
+                        let screen: &mut FileSystem = self.get_screen(&AppState::FileSystem);
+                        screen.pick_artwork(ids);
+                        self.state = AppState::FileSystem;
+                    }
+                    AppEvent::QueueChanged(entries) => {
+                        let screen: &mut QueueScreen = self.get_screen(&AppState::QueueScreen);
+                        screen.set_entries(entries);
+                        let active = screen.active();
+                        let screen: &mut MainScreen = self.get_screen(&AppState::MainScreen);
+                        screen.set_queue_active(active);
+                    }
+                    AppEvent::MetadataShow(tracks) => {
+                        let screen: &mut MetadataEditor = self.get_screen(&AppState::MetadataEditor);
+                        screen.show_tracks(tracks);
+                        self.state = AppState::MetadataEditor;
+                    }
                     _ => {}
                 }
             }
@@ -182,6 +283,8 @@ impl App {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let dry_run = std::env::args().any(|a| a == "--dry-run");
+
     enable_raw_mode()?;
     let mut stderr = io::stdout();
     execute!(stderr, EnterAlternateScreen, EnableMouseCapture)?;
@@ -189,7 +292,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let mut terminal = Terminal::new(backend)?;
 
     // create app and run it
-    let mut app = App::default();
+    let mut app = App::new(dry_run);
     let _ = app.run(&mut terminal).await;
 
     // restore terminal
@@ -4,8 +4,14 @@ use crossterm::event::KeyEvent;
 use ratatui::Frame;
 
 pub mod file_system;
+pub mod identify_screen;
+pub mod import_report;
 pub mod loading_screen;
+pub mod lookup_screen;
 pub mod main_screen;
+pub mod metadata_editor;
+pub mod plan_screen;
+pub mod queue_screen;
 pub mod search_util;
 pub mod wait_screen;
 
@@ -1,5 +1,7 @@
 use crate::component::table::SmartTable;
-use crate::sync::sync_util::AppEvent;
+use crate::config::TranscodeProfile;
+use crate::sync::sync_util::{AppEvent, ImportSummary};
+use crate::util::format_bytes;
 use crate::{screens::AppScreen, AppState};
 use chrono::{DateTime, Utc};
 use crossterm::event::KeyCode;
@@ -20,12 +22,34 @@ pub struct FileSystem {
     current_path: PathBuf,
     table: SmartTable,
     sender: UnboundedSender<AppEvent>,
+    profile: TranscodeProfile,
+    summary: Option<ImportSummary>,
+    // tracks on the iPod waiting for an image to be picked as their artwork
+    artwork_for: Option<Vec<u32>>,
 }
 
 fn check_extension_compatibility(ext: &str) -> bool {
     matches!(
         ext.to_lowercase().as_str(),
-        "mp3" | "m4a" | "wav" | "aiff" | "aif"
+        "mp3"
+            | "m4a"
+            | "wav"
+            | "aiff"
+            | "aif"
+            | "aa"
+            | "aax"
+            | "flac"
+            | "ogg"
+            | "oga"
+            | "opus"
+            | "wma"
+    )
+}
+
+fn is_image(ext: &str) -> bool {
+    matches!(
+        ext.to_lowercase().as_str(),
+        "jpg" | "jpeg" | "png" | "webp" | "bmp"
     )
 }
 
@@ -83,12 +107,19 @@ impl AppScreen for FileSystem {
             KeyCode::Up => self.table.previous_row(),
             KeyCode::Down => self.table.next_row(),
             KeyCode::Esc => {
+                if self.artwork_for.take().is_some() {
+                    self.get_path(self.current_path.clone());
+                }
                 let _ = self
                     .sender
                     .send(AppEvent::SwitchScreen(AppState::MainScreen));
             }
+            KeyCode::F(5) if self.artwork_for.is_some() => self.set_artwork(false),
+            KeyCode::Enter if self.artwork_for.is_some() => self.set_artwork(true),
+            _ if self.artwork_for.is_some() => {}
             KeyCode::F(5) => self.download_as_is(),
             KeyCode::F(6) => self.download_as_playlist(),
+            KeyCode::F(7) => self.profile = self.profile.toggle(),
             KeyCode::Enter => self.enter_directory(),
             _ => {}
         }
@@ -99,24 +130,53 @@ impl AppScreen for FileSystem {
             .direction(Direction::Vertical)
             .constraints([
                 Constraint::Min(0),    // Main content area
+                Constraint::Length(1), // Import summary
                 Constraint::Length(1), // Status bar
             ])
             .split(frame.area());
 
         self.render_main(frame, chunks[0]);
 
+        if let Some(summary) = &self.summary {
+            let mut line = format!("Imported {} tracks", summary.imported);
+            if summary.shrunk > 0 {
+                line.push_str(&format!(
+                    ", {} re-encoded, {} saved",
+                    summary.shrunk,
+                    format_bytes(summary.bytes_saved)
+                ));
+            }
+            frame.render_widget(Paragraph::new(line.dark_gray()).centered(), chunks[1]);
+        }
+
         // Render Status Bar
-        let status_bar = Paragraph::new(Line::from(vec![
-            "<ESC> GO BACK".bold(),
-            " | ".dark_gray(),
-            "<F5> SAVE AS IS".bold(),
-            " | ".dark_gray(),
-            "<F6> SAVE AS PLAYLIST".bold(),
-            " | ".dark_gray(),
-            "<F10> QUIT".bold(),
-        ]))
+        let status_bar = Paragraph::new(Line::from(if self.artwork_for.is_some() {
+            vec![
+                "<ESC> CANCEL".bold(),
+                " | ".dark_gray(),
+                "<ENTER> USE IMAGE".bold(),
+                " | ".dark_gray(),
+                "<F5> FETCH ONLINE".bold(),
+                " | ".dark_gray(),
+                "<F10> QUIT".bold(),
+            ]
+        } else {
+            vec![
+                "<ESC> GO BACK".bold(),
+                " | ".dark_gray(),
+                "<F5> SAVE AS IS".bold(),
+                " | ".dark_gray(),
+                "<F6> SAVE AS PLAYLIST".bold(),
+                " | ".dark_gray(),
+                ["<F7> PROFILE: ".to_string(), String::from(self.profile)]
+                    .concat()
+                    .bold(),
+                " | ".dark_gray(),
+                "<F10> QUIT".bold(),
+            ]
+        }))
         .centered();
-        frame.render_widget(status_bar, chunks[1]); // Render into third chunk
+        frame.render_widget(status_bar, chunks[2]); // Render into third chunk
     }
 
     fn as_any(&mut self) -> &mut dyn std::any::Any {
@@ -144,6 +204,9 @@ impl FileSystem {
             sender,
             files: Vec::new(),
             current_path: dirs::document_dir().unwrap(),
+            profile: TranscodeProfile::default(),
+            summary: None,
+            artwork_for: None,
         };
         a.get_path(dirs::document_dir().unwrap());
         a
@@ -155,10 +218,13 @@ impl FileSystem {
         let mut dir = paths
             .filter_map(|res| res.ok())
             .filter(|p| {
-                p.path()
-                    .extension()
-                    .is_some_and(|s| check_extension_compatibility(s.to_str().unwrap_or("none")))
-                    || p.path().is_dir()
+                p.path().extension().is_some_and(|s| {
+                    let ext = s.to_str().unwrap_or("none");
+                    match self.artwork_for {
+                        Some(_) => is_image(ext),
+                        None => check_extension_compatibility(ext),
+                    }
+                }) || p.path().is_dir()
             })
             .collect::<Vec<DirEntry>>();
         dir.sort_by(|a, b| {
@@ -211,9 +277,13 @@ impl FileSystem {
             let entry = self.files.get(self.table.selected_row() - 1).unwrap();
             if entry.path().is_dir() {
                 let files = list_files_recursively(entry.path());
-                let _ = self.sender.send(AppEvent::LoadFromFSVec(files));
+                let _ = self
+                    .sender
+                    .send(AppEvent::LoadFromFSVec((files, self.profile)));
             } else {
-                let _ = self.sender.send(AppEvent::LoadFromFS(entry.path()));
+                let _ = self
+                    .sender
+                    .send(AppEvent::LoadFromFS((entry.path(), self.profile)));
             }
         }
     }
@@ -232,11 +302,41 @@ impl FileSystem {
                         .to_str()
                         .unwrap()
                         .to_string(),
+                    self.profile,
                 )));
             }
         }
     }
 
+    pub fn pick_artwork(&mut self, ids: Vec<u32>) {
+        self.artwork_for = Some(ids);
+        self.get_path(self.current_path.clone());
+    }
+
+    fn set_artwork(&mut self, from_file: bool) {
+        let image = match from_file {
+            true => match self.table.selected_row() {
+                0 => return self.move_up(),
+                row => {
+                    let entry = self.files.get(row - 1).unwrap();
+                    if entry.path().is_dir() {
+                        return self.get_path(entry.path());
+                    }
+                    Some(entry.path())
+                }
+            },
+            false => None,
+        };
+        if let Some(ids) = self.artwork_for.take() {
+            self.get_path(self.current_path.clone());
+            let _ = self.sender.send(AppEvent::SetArtwork((ids, image)));
+        }
+    }
+
+    pub fn set_summary(&mut self, summary: ImportSummary) {
+        self.summary = Some(summary);
+    }
+
     fn move_up(&mut self) {
         let p = self.current_path.parent();
         if p.is_none() {
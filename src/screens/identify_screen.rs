@@ -0,0 +1,197 @@
+use crate::component::table::SmartTable;
+use crate::screens::AppScreen;
+use crate::sync::acoustid::Identification;
+use crate::sync::metadata::{MetadataChanges, MetadataField};
+use crate::sync::sync_util::AppEvent;
+use crate::AppState;
+use crossterm::event::KeyEventKind::Press;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::{Line, Stylize};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::any::Any;
+use strum::IntoEnumIterator;
+use tokio::sync::mpsc::UnboundedSender;
+
+fn table() -> SmartTable {
+    SmartTable::new(
+        ["", "Track", "Proposed", "Score"]
+            .iter_mut()
+            .map(|s| s.to_string())
+            .collect(),
+        vec![
+            Constraint::Length(3),
+            Constraint::Percentage(35),
+            Constraint::Min(0),
+            Constraint::Length(6),
+        ],
+    )
+}
+
+// "Title: Foo, Album: Bar" in the order of the editor
+fn describe(changes: &MetadataChanges) -> String {
+    MetadataField::iter()
+        .filter_map(|field| {
+            changes
+                .get(&field)
+                .map(|value| [String::from(field), ": ".to_string(), value.clone()].concat())
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub struct IdentifyScreen {
+    table: SmartTable,
+    sender: UnboundedSender<AppEvent>,
+    identified: Vec<Identification>,
+    accepted: Vec<bool>,
+    write_tags: bool,
+}
+
+impl AppScreen for IdentifyScreen {
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if key_event.kind != Press {
+            return;
+        }
+        match key_event.code {
+            KeyCode::Up => self.table.previous_row(),
+            KeyCode::Down => self.table.next_row(),
+            KeyCode::Insert | KeyCode::Char(' ') => {
+                self.toggle_selected();
+                self.table.next_row();
+            }
+            KeyCode::F(5) => self.apply(),
+            KeyCode::F(6) => self.write_tags = !self.write_tags,
+            KeyCode::Esc => {
+                let _ = self
+                    .sender
+                    .send(AppEvent::SwitchScreen(AppState::MainScreen));
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),    // Main content area
+                Constraint::Length(1), // Status bar
+            ])
+            .split(frame.area());
+
+        self.render_main(frame, chunks[0]);
+
+        // Render Status Bar
+        let status_bar = Paragraph::new(Line::from(vec![
+            "<ESC> GO BACK".bold(),
+            " | ".dark_gray(),
+            "<INS> ACCEPT".bold(),
+            " | ".dark_gray(),
+            "<F5> APPLY".bold(),
+            " | ".dark_gray(),
+            if self.write_tags {
+                "<F6> WRITE FILE TAGS: ON".bold()
+            } else {
+                "<F6> WRITE FILE TAGS: OFF".bold()
+            },
+            " | ".dark_gray(),
+            "<F10> QUIT".bold(),
+        ]))
+        .centered();
+        frame.render_widget(status_bar, chunks[1]);
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl IdentifyScreen {
+    pub fn new(sender: UnboundedSender<AppEvent>) -> Self {
+        Self {
+            table: table(),
+            sender,
+            identified: Vec::new(),
+            accepted: Vec::new(),
+            write_tags: true,
+        }
+    }
+
+    pub fn show_identified(&mut self, identified: Vec<Identification>) {
+        self.table = table();
+        // every match starts accepted, the ones which look wrong get unticked
+        self.accepted = identified
+            .iter()
+            .map(|i| i.proposal.as_ref().is_ok_and(|c| !c.is_empty()))
+            .collect();
+        let matched = self.accepted.iter().filter(|a| **a).count();
+        self.table.set_title(format!(
+            " {} of {} tracks have new metadata ",
+            matched,
+            identified.len()
+        ));
+        self.identified = identified;
+        self.update_table();
+    }
+
+    fn toggle_selected(&mut self) {
+        let row = self.table.selected_row();
+        let proposed = self
+            .identified
+            .get(row)
+            .is_some_and(|i| i.proposal.as_ref().is_ok_and(|c| !c.is_empty()));
+        if proposed {
+            self.accepted[row] = !self.accepted[row];
+            self.update_table();
+        }
+    }
+
+    fn update_table(&mut self) {
+        let data = self
+            .identified
+            .iter()
+            .zip(self.accepted.iter())
+            .map(|(i, accepted)| {
+                let track = [i.track.get_artist(), " - ".to_string(), i.track.get_title()].concat();
+                let (proposed, score) = match &i.proposal {
+                    Ok(changes) if changes.is_empty() => {
+                        ("already up to date".to_string(), format!("{:.2}", i.score))
+                    }
+                    Ok(changes) => (describe(changes), format!("{:.2}", i.score)),
+                    Err(reason) => (reason.clone(), String::new()),
+                };
+                let mark = if *accepted { "[x]" } else { "[ ]" };
+                vec![mark.to_string(), track, proposed, score]
+            })
+            .collect();
+        self.table.set_data(data);
+    }
+
+    fn apply(&mut self) {
+        let edits: Vec<(u32, MetadataChanges)> = self
+            .identified
+            .iter()
+            .zip(self.accepted.iter())
+            .filter(|(_, accepted)| **accepted)
+            .filter_map(|(i, _)| {
+                let changes = i.proposal.as_ref().ok()?;
+                Some((i.track.data.unique_id, changes.clone()))
+            })
+            .collect();
+        if edits.is_empty() {
+            let _ = self
+                .sender
+                .send(AppEvent::SwitchScreen(AppState::MainScreen));
+            return;
+        }
+        let _ = self
+            .sender
+            .send(AppEvent::UpdateTracksMetadata((edits, self.write_tags)));
+    }
+
+    fn render_main(&self, frame: &mut Frame, area: Rect) {
+        self.table.render(frame, area);
+    }
+}
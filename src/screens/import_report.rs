@@ -0,0 +1,216 @@
+use crate::component::table::SmartTable;
+use crate::config::get_configs_dir;
+use crate::screens::AppScreen;
+use crate::sync::sync_util::{AppEvent, ImportOutcome, ImportResult, ImportSummary};
+use crate::util::format_bytes;
+use crate::AppState;
+use chrono::Local;
+use crossterm::event::KeyEventKind::Press;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::{Line, Stylize};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::any::Any;
+use std::path::PathBuf;
+use tokio::sync::mpsc::UnboundedSender;
+
+fn table() -> SmartTable {
+    SmartTable::new(
+        ["Result", "Item", "Reason"]
+            .iter_mut()
+            .map(|s| s.to_string())
+            .collect(),
+        vec![
+            Constraint::Length(10),
+            Constraint::Percentage(40),
+            Constraint::Min(0),
+        ],
+    )
+}
+
+fn outcome_row(result: &ImportResult) -> (String, String) {
+    match &result.outcome {
+        ImportOutcome::Imported => (
+            "IMPORTED".to_string(),
+            result.note.clone().unwrap_or_default(),
+        ),
+        ImportOutcome::Duplicate => ("DUPLICATE".to_string(), "already on the iPod".to_string()),
+        ImportOutcome::Failed(reason) => ("FAILED".to_string(), reason.clone()),
+    }
+}
+
+pub struct ImportReport {
+    table: SmartTable,
+    summary: ImportSummary,
+    exported: Option<PathBuf>,
+    sender: UnboundedSender<AppEvent>,
+}
+
+impl AppScreen for ImportReport {
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if key_event.kind != Press {
+            return;
+        }
+        match key_event.code {
+            KeyCode::Up => self.table.previous_row(),
+            KeyCode::Down => self.table.next_row(),
+            KeyCode::F(5) => self.retry(),
+            KeyCode::F(6) => self.export(),
+            KeyCode::Esc => {
+                let _ = self.sender.send(AppEvent::SwitchScreen(self.go_back()));
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),    // Main content area
+                Constraint::Length(1), // Export path
+                Constraint::Length(1), // Status bar
+            ])
+            .split(frame.area());
+
+        self.render_main(frame, chunks[0]);
+
+        if let Some(path) = &self.exported {
+            let line = Paragraph::new(format!("Saved to {}", path.display()))
+                .dark_gray()
+                .centered();
+            frame.render_widget(line, chunks[1]);
+        }
+
+        // Render Status Bar
+        let status_bar = Paragraph::new(Line::from(vec![
+            "<F5> RETRY FAILED".bold(),
+            " | ".dark_gray(),
+            "<F6> EXPORT".bold(),
+            " | ".dark_gray(),
+            "<ESC> GO BACK".bold(),
+            " | ".dark_gray(),
+            "<F10> QUIT".bold(),
+        ]))
+        .centered();
+        frame.render_widget(status_bar, chunks[2]);
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl ImportReport {
+    pub fn new(sender: UnboundedSender<AppEvent>) -> Self {
+        Self {
+            table: table(),
+            summary: ImportSummary::default(),
+            exported: None,
+            sender,
+        }
+    }
+
+    pub fn show_summary(&mut self, summary: ImportSummary) {
+        self.table = table();
+        self.exported = None;
+
+        let duplicates = summary
+            .results
+            .iter()
+            .filter(|r| r.outcome == ImportOutcome::Duplicate)
+            .count();
+        let mut title = format!(
+            " Imported {}, {} duplicates, {} failed ",
+            summary.imported,
+            duplicates,
+            summary.failures().len()
+        );
+        if summary.shrunk > 0 {
+            title = format!(
+                "{}| {} re-encoded, {} saved ",
+                title,
+                summary.shrunk,
+                format_bytes(summary.bytes_saved)
+            );
+        }
+        self.table.set_title(title);
+
+        // failures first, they are what this screen is for
+        let mut results: Vec<_> = summary.results.iter().collect();
+        results.sort_by_key(|r| !matches!(r.outcome, ImportOutcome::Failed(_)));
+
+        let data = results
+            .iter()
+            .map(|r| {
+                let (result, reason) = outcome_row(r);
+                vec![result, r.item.clone(), reason]
+            })
+            .collect();
+
+        self.table.set_data(data);
+        self.summary = summary;
+    }
+
+    // local files go back to the file browser, downloads to the main screen
+    fn go_back(&self) -> AppState {
+        if self.summary.results.iter().any(|r| r.path.is_some()) {
+            AppState::FileSystem
+        } else {
+            AppState::MainScreen
+        }
+    }
+
+    fn retry(&mut self) {
+        let files: Vec<PathBuf> = self
+            .summary
+            .failures()
+            .iter()
+            .filter_map(|r| r.path.clone())
+            .collect();
+        if files.is_empty() {
+            return;
+        }
+        let profile = self.summary.profile;
+        let _ = self.sender.send(match &self.summary.playlist {
+            Some(title) => AppEvent::LoadFromFSPL((files, title.clone(), profile)),
+            None => AppEvent::LoadFromFSVec((files, profile)),
+        });
+    }
+
+    fn export(&mut self) {
+        let mut path = get_configs_dir();
+        path.push(format!(
+            "import-report-{}.txt",
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+
+        let lines: Vec<String> = self
+            .summary
+            .results
+            .iter()
+            .map(|r| {
+                let (result, reason) = outcome_row(r);
+                let item = match &r.path {
+                    Some(p) => p.display().to_string(),
+                    None => r.item.clone(),
+                };
+                if reason.is_empty() {
+                    [result, item].join("\t")
+                } else {
+                    [result, item, reason].join("\t")
+                }
+            })
+            .collect();
+
+        let _ = std::fs::create_dir_all(get_configs_dir());
+        if std::fs::write(&path, lines.join("\n") + "\n").is_ok() {
+            self.exported = Some(path);
+        }
+    }
+
+    fn render_main(&self, frame: &mut Frame, area: Rect) {
+        self.table.render(frame, area);
+    }
+}
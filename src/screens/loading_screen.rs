@@ -6,17 +6,26 @@ use ratatui::{
     Frame,
 };
 
-use crate::{dlp::DownloadProgress, screens::AppScreen};
+use crossterm::event::{KeyCode, KeyEventKind};
+
+use crate::{dlp::DownloadProgress, screens::AppScreen, sync::cancel};
 
 #[derive(Default)]
 pub struct LoadingScreen {
     pub progress: Option<(u32, u32, ratatui::style::Color)>,
     pub artwork_progress: Option<(u32, u32)>,
     pub s_progress: Option<DownloadProgress>,
+    // Esc was pressed, the operation stops after the current file
+    pub cancelling: bool,
 }
 
 impl AppScreen for LoadingScreen {
-    fn handle_key_event(&mut self, _key_event: crossterm::event::KeyEvent) {}
+    fn handle_key_event(&mut self, key_event: crossterm::event::KeyEvent) {
+        if key_event.kind == KeyEventKind::Press && key_event.code == KeyCode::Esc {
+            cancel::request();
+            self.cancelling = true;
+        }
+    }
 
     fn render(&self, frame: &mut ratatui::Frame) {
         let chunks = Layout::default()
@@ -30,7 +39,12 @@ impl AppScreen for LoadingScreen {
         self.render_progress(frame, chunks[0]);
 
         // Render Status Bar
-        let status_bar = Paragraph::new(Line::from(vec!["<F10> QUIT".bold()])).centered();
+        let status_bar = Paragraph::new(Line::from(vec![
+            "<ESC> CANCEL".bold(),
+            " | ".dark_gray(),
+            "<F10> QUIT".bold(),
+        ]))
+        .centered();
         frame.render_widget(status_bar, chunks[1]); // Render into third chunk
     }
 
@@ -50,11 +64,19 @@ impl LoadingScreen {
             ])
             .split(area);
 
-        let main_content = Paragraph::new("Please wait").block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Downloading has started!"),
-        );
+        let main_content = if self.cancelling {
+            Paragraph::new("Cancelling, what is finished will be kept").block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Finishing the current file"),
+            )
+        } else {
+            Paragraph::new("Please wait").block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Downloading has started!"),
+            )
+        };
 
         frame.render_widget(main_content, chunks[0]);
 
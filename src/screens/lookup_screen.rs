@@ -0,0 +1,259 @@
+use crate::component::table::SmartTable;
+use crate::screens::AppScreen;
+use crate::sync::metadata::{self, MetadataChanges, MetadataField};
+use crate::sync::musicbrainz::{ReleaseCandidate, ReleaseProposal};
+use crate::sync::sync_util::AppEvent;
+use crate::AppState;
+use crossterm::event::KeyEventKind::Press;
+use crossterm::event::{KeyCode, KeyEvent};
+use itunesdb::xobjects::XTrackItem;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::{Line, Stylize};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::any::Any;
+use tokio::sync::mpsc::UnboundedSender;
+
+fn candidates_table() -> SmartTable {
+    SmartTable::new(
+        ["Release", "Artist", "Date", "Country", "Tracks", "Score"]
+            .iter_mut()
+            .map(|s| s.to_string())
+            .collect(),
+        vec![
+            Constraint::Percentage(35),
+            Constraint::Min(0),
+            Constraint::Length(10),
+            Constraint::Length(7),
+            Constraint::Length(6),
+            Constraint::Length(5),
+        ],
+    )
+}
+
+fn proposal_table() -> SmartTable {
+    SmartTable::new(
+        ["Current tags", "MusicBrainz"]
+            .iter_mut()
+            .map(|s| s.to_string())
+            .collect(),
+        vec![Constraint::Percentage(50), Constraint::Percentage(50)],
+    )
+}
+
+// "1-03. Artist - Title | Album (2001)", the proposed changes laid over the current tags
+fn describe(track: &XTrackItem, changes: &MetadataChanges) -> String {
+    let field = |f: MetadataField| {
+        changes
+            .get(&f)
+            .cloned()
+            .unwrap_or_else(|| metadata::get_field(track, f))
+    };
+    format!(
+        "{}-{:0>2}. {} - {} | {} ({})",
+        field(MetadataField::DiscNumber),
+        field(MetadataField::TrackNumber),
+        field(MetadataField::Artist),
+        field(MetadataField::Title),
+        field(MetadataField::Album),
+        field(MetadataField::Year)
+    )
+}
+
+pub struct LookupScreen {
+    table: SmartTable,
+    sender: UnboundedSender<AppEvent>,
+    track_ids: Vec<u32>,
+    candidates: Vec<ReleaseCandidate>,
+    proposal: Option<ReleaseProposal>,
+    write_tags: bool,
+}
+
+impl AppScreen for LookupScreen {
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if key_event.kind != Press {
+            return;
+        }
+        match key_event.code {
+            KeyCode::Up => self.table.previous_row(),
+            KeyCode::Down => self.table.next_row(),
+            KeyCode::Enter if self.proposal.is_none() => self.select_release(),
+            KeyCode::F(5) if self.proposal.is_some() => self.apply(),
+            KeyCode::F(6) => self.write_tags = !self.write_tags,
+            KeyCode::Esc => match self.proposal {
+                Some(_) => {
+                    self.proposal = None;
+                    self.show_candidates_table(" Choose a release ".to_string());
+                }
+                None => {
+                    let _ = self
+                        .sender
+                        .send(AppEvent::SwitchScreen(AppState::MainScreen));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),    // Main content area
+                Constraint::Length(1), // Status bar
+            ])
+            .split(frame.area());
+
+        self.render_main(frame, chunks[0]);
+
+        // Render Status Bar
+        let mut status = vec!["<ESC> GO BACK".bold(), " | ".dark_gray()];
+        match self.proposal {
+            Some(_) => status.append(&mut vec!["<F5> APPLY".bold(), " | ".dark_gray()]),
+            None => status.append(&mut vec!["<ENTER> COMPARE".bold(), " | ".dark_gray()]),
+        }
+        status.append(&mut vec![
+            if self.write_tags {
+                "<F6> WRITE FILE TAGS: ON".bold()
+            } else {
+                "<F6> WRITE FILE TAGS: OFF".bold()
+            },
+            " | ".dark_gray(),
+            "<F10> QUIT".bold(),
+        ]);
+        let status_bar = Paragraph::new(Line::from(status)).centered();
+        frame.render_widget(status_bar, chunks[1]);
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl LookupScreen {
+    pub fn new(sender: UnboundedSender<AppEvent>) -> Self {
+        Self {
+            table: candidates_table(),
+            sender,
+            track_ids: Vec::new(),
+            candidates: Vec::new(),
+            proposal: None,
+            write_tags: true,
+        }
+    }
+
+    pub fn show_candidates(
+        &mut self,
+        track_ids: Vec<u32>,
+        candidates: Result<Vec<ReleaseCandidate>, String>,
+    ) {
+        self.track_ids = track_ids;
+        self.proposal = None;
+        let title = match candidates {
+            Ok(candidates) => {
+                let title = if candidates.is_empty() {
+                    " Nothing found on MusicBrainz ".to_string()
+                } else {
+                    format!(" {} releases found, choose one ", candidates.len())
+                };
+                self.candidates = candidates;
+                title
+            }
+            Err(reason) => {
+                self.candidates = Vec::new();
+                [" Lookup failed: ", &reason, " "].concat()
+            }
+        };
+        self.show_candidates_table(title);
+    }
+
+    pub fn show_proposal(&mut self, proposal: Result<ReleaseProposal, String>) {
+        let proposal = match proposal {
+            Ok(proposal) => proposal,
+            Err(reason) => {
+                self.show_candidates_table([" Lookup failed: ", &reason, " "].concat());
+                return;
+            }
+        };
+
+        self.table = proposal_table();
+        let matched = proposal
+            .tracks
+            .iter()
+            .filter(|t| t.changes.is_some())
+            .count();
+        self.table.set_title(format!(
+            " {} by {} {}: {} of {} tracks matched ",
+            proposal.release.title,
+            proposal.release.artist,
+            proposal.release.date,
+            matched,
+            proposal.tracks.len()
+        ));
+
+        let data = proposal
+            .tracks
+            .iter()
+            .map(|t| {
+                let current = describe(&t.track, &MetadataChanges::new());
+                let proposed = match &t.changes {
+                    Some(changes) if changes.is_empty() => "no changes".to_string(),
+                    Some(changes) => describe(&t.track, changes),
+                    None => "no matching track on this release".to_string(),
+                };
+                vec![current, proposed]
+            })
+            .collect();
+        self.table.set_data(data);
+        self.proposal = Some(proposal);
+    }
+
+    fn show_candidates_table(&mut self, title: String) {
+        self.table = candidates_table();
+        self.table.set_title(title);
+        let data = self
+            .candidates
+            .iter()
+            .map(|c| {
+                vec![
+                    c.title.clone(),
+                    c.artist.clone(),
+                    c.date.clone(),
+                    c.country.clone(),
+                    c.track_count.to_string(),
+                    c.score.to_string(),
+                ]
+            })
+            .collect();
+        self.table.set_data(data);
+    }
+
+    fn select_release(&mut self) {
+        if let Some(candidate) = self.candidates.get(self.table.selected_row()) {
+            let _ = self.sender.send(AppEvent::MusicBrainzRelease((
+                self.track_ids.clone(),
+                candidate.clone(),
+            )));
+        }
+    }
+
+    fn apply(&mut self) {
+        let proposal = match self.proposal.take() {
+            Some(proposal) => proposal,
+            None => return,
+        };
+        let edits: Vec<_> = proposal
+            .tracks
+            .into_iter()
+            .filter_map(|t| Some((t.track.data.unique_id, t.changes?, t.ids)))
+            .collect();
+        self.show_candidates_table(" Choose a release ".to_string());
+        let _ = self
+            .sender
+            .send(AppEvent::MusicBrainzApply((edits, self.write_tags)));
+    }
+
+    fn render_main(&self, frame: &mut Frame, area: Rect) {
+        self.table.render(frame, area);
+    }
+}
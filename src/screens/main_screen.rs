@@ -1,21 +1,27 @@
 use chrono::{DateTime, TimeZone, Utc};
 use crossterm::event::KeyEventKind::Press;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use image::RgbaImage;
 use ratatui::layout::Position;
 use ratatui::text::Text;
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style, Stylize},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph, Tabs},
+    widgets::{Block, Borders, Paragraph, Tabs, Wrap},
     Frame,
 };
 use std::collections::HashMap;
+use std::path::PathBuf;
 use strum::{EnumCount, IntoEnumIterator};
 use tokio::sync::mpsc::UnboundedSender;
 
+use crate::component::cover;
 use crate::component::table::SmartTable;
+use crate::config::TranscodeProfile;
 use crate::screens::main_screen::util::{TabContent, TabType};
+use crate::sync::sync_util::ImportSummary;
+use crate::util::format_bytes;
 use crate::{screens::AppScreen, sync::sync_util::AppEvent, AppState};
 
 pub(crate) mod util;
@@ -29,6 +35,14 @@ pub struct MainScreen {
     sender: UnboundedSender<AppEvent>,
     popup_input: String,
     char_index: usize,
+    show_lyrics: bool,
+    lyrics: Option<(u32, Option<String>)>,
+    cover: Option<(u32, Option<RgbaImage>)>,
+    exported_covers: Option<(usize, PathBuf)>,
+    // downloads waiting or running
+    queue_active: usize,
+    profile: TranscodeProfile,
+    summary: Option<ImportSummary>,
 }
 
 impl AppScreen for MainScreen {
@@ -58,8 +72,30 @@ impl AppScreen for MainScreen {
             KeyCode::Up => self.previous_row(),
             KeyCode::Down => self.next_row(),
             KeyCode::F(5) => self.download_row(),
+            KeyCode::F(6) if TabType::from(self.selected_tab) != TabType::Playlists => {
+                self.profile = self.profile.toggle()
+            }
+            KeyCode::F(6) => self.pick_artwork(),
             KeyCode::F(8) => self.remove_row(),
             KeyCode::F(9) => self.remove_completely(),
+            KeyCode::F(2) => self.edit_metadata(),
+            KeyCode::F(7) => self.identify(),
+            KeyCode::F(1) => self.lookup(),
+            KeyCode::F(12) => self.export_covers(),
+            KeyCode::F(11) => {
+                let _ = self
+                    .sender
+                    .send(AppEvent::SwitchScreen(AppState::QueueScreen));
+            }
+            KeyCode::F(3) => {
+                self.show_lyrics = !self.show_lyrics;
+                self.request_lyrics();
+                self.request_cover();
+            }
+            KeyCode::Insert if self.mode => {
+                self.song_table.toggle_mark();
+                self.song_table.next_row();
+            }
             KeyCode::Tab => self.switch_mode(),
             KeyCode::F(4) => {
                 let _ = self
@@ -81,9 +117,14 @@ impl AppScreen for MainScreen {
                 }
             }
             KeyCode::Enter => {
-                let _ = self
-                    .sender
-                    .send(AppEvent::SearchFor(self.popup_input.clone()));
+                let input = self.popup_input.clone();
+                let _ = match self.is_url() {
+                    true => self.sender.send(AppEvent::DownloadUrl((
+                        input.trim().to_string(),
+                        self.profile,
+                    ))),
+                    false => self.sender.send(AppEvent::SearchFor(input)),
+                };
                 self.popup_input = String::default();
                 self.char_index = 0;
             }
@@ -104,12 +145,30 @@ impl AppScreen for MainScreen {
             ])
             .split(frame.area());
 
+        let mut block = Block::default().borders(Borders::ALL);
+        if let Some(summary) = &self.summary {
+            let mut title = format!(" Imported {} tracks", summary.imported);
+            if summary.shrunk > 0 {
+                title.push_str(&format!(
+                    ", {} re-encoded, {} saved",
+                    summary.shrunk,
+                    format_bytes(summary.bytes_saved)
+                ));
+            }
+            title.push(' ');
+            block = block.title_top(Line::from(title).right_aligned());
+        }
+        if let Some((count, dir)) = &self.exported_covers {
+            let title = format!(" Saved {} covers to {} ", count, dir.display());
+            block = block.title_top(Line::from(title).left_aligned());
+        }
+
         let tabs = Tabs::new(
             TabType::iter()
                 .map(|t| Span::raw(String::from(t).clone()))
                 .collect::<Vec<Span>>(),
         )
-        .block(Block::default().borders(Borders::ALL))
+        .block(block)
         .highlight_style(
             Style::default()
                 .fg(Color::LightBlue)
@@ -122,6 +181,11 @@ impl AppScreen for MainScreen {
 
         self.render_tab(frame, chunks[1]);
 
+        let queue_label = match self.queue_active {
+            0 => "<F11> QUEUE".to_string(),
+            n => format!("<F11> QUEUE ({})", n),
+        };
+
         // Render Status Bar
         let status_bar = Paragraph::new(Line::from(match TabType::from(self.selected_tab) {
             TabType::Youtube | TabType::Soundcloud => {
@@ -130,13 +194,33 @@ impl AppScreen for MainScreen {
                     " | ".dark_gray(),
                     "<F5> DOWNLOAD".bold(),
                     " | ".dark_gray(),
+                    queue_label.clone().bold(),
+                    " | ".dark_gray(),
+                    ["<F6> PROFILE: ".to_string(), String::from(self.profile)]
+                        .concat()
+                        .bold(),
+                    " | ".dark_gray(),
                     "<F10> QUIT".bold(),
                 ]
             }
             TabType::Playlists => {
                 vec![
+                    "<F2> EDIT".bold(),
+                    " | ".dark_gray(),
+                    "<INS> MARK".bold(),
+                    " | ".dark_gray(),
+                    "<F3> LYRICS & COVER".bold(),
+                    " | ".dark_gray(),
+                    "<F7> IDENTIFY".bold(),
+                    " | ".dark_gray(),
+                    "<F1> LOOKUP".bold(),
+                    " | ".dark_gray(),
                     "<F4> IMPORT".bold(),
                     " | ".dark_gray(),
+                    "<F6> ARTWORK".bold(),
+                    " | ".dark_gray(),
+                    "<F12> SAVE COVERS".bold(),
+                    " | ".dark_gray(),
                     "<F8> REMOVE".bold(),
                     " | ".dark_gray(),
                     "<F9> DELETE".bold(),
@@ -147,9 +231,14 @@ impl AppScreen for MainScreen {
         }))
         .centered();
 
+        // a link typed or pasted into the search is downloaded instead
+        let prompt = match self.is_url() {
+            true => "Open URL>",
+            false => "Search>",
+        };
         let input_text = Paragraph::new(
             Text::from(Line::from(vec![
-                "Search>".bold().into(),
+                prompt.bold(),
                 self.popup_input.clone().into(),
             ]))
             .patch_style(Style::default().add_modifier(Modifier::RAPID_BLINK)),
@@ -158,7 +247,7 @@ impl AppScreen for MainScreen {
         frame.render_widget(input_text, chunks[2]);
 
         frame.set_cursor_position(Position::new(
-            chunks[2].x + (self.char_index as u16) + 7,
+            chunks[2].x + (self.char_index as u16) + prompt.len() as u16,
             chunks[2].y,
         ));
 
@@ -181,6 +270,13 @@ impl MainScreen {
             popup_input: String::default(),
             tab_content: HashMap::new(),
             char_index: 0,
+            show_lyrics: false,
+            lyrics: None,
+            cover: None,
+            exported_covers: None,
+            queue_active: 0,
+            profile: TranscodeProfile::default(),
+            summary: None,
         }
     }
 
@@ -215,6 +311,8 @@ impl MainScreen {
                 self.update_songs();
             }
         }
+        self.request_lyrics();
+        self.request_cover();
     }
 
     fn next_row(&mut self) {
@@ -225,6 +323,71 @@ impl MainScreen {
                 self.update_songs();
             }
         }
+        self.request_lyrics();
+        self.request_cover();
+    }
+
+    fn selected_track_id(&self) -> Option<u32> {
+        match self.tab_content.get(&TabType::from(self.selected_tab)) {
+            Some(TabContent::Playlists(playlists)) => playlists
+                .get(self.pl_table.selected_row())?
+                .tracks
+                .get(self.song_table.selected_row())
+                .map(|t| t.data.unique_id),
+            _ => None,
+        }
+    }
+
+    fn request_lyrics(&mut self) {
+        if !self.show_lyrics {
+            return;
+        }
+        match self.selected_track_id() {
+            Some(id) => {
+                if self.lyrics.as_ref().is_some_and(|(i, _)| *i == id) {
+                    return;
+                }
+                self.lyrics = None;
+                let _ = self.sender.send(AppEvent::ShowLyrics(id));
+            }
+            None => self.lyrics = None,
+        }
+    }
+
+    pub fn set_lyrics(&mut self, id: u32, lyrics: Option<String>) {
+        if self.selected_track_id() == Some(id) {
+            self.lyrics = Some((id, lyrics));
+        }
+    }
+
+    fn request_cover(&mut self) {
+        if !self.show_lyrics {
+            return;
+        }
+        match self.selected_track_id() {
+            Some(id) => {
+                if self.cover.as_ref().is_some_and(|(i, _)| *i == id) {
+                    return;
+                }
+                self.cover = None;
+                let _ = self.sender.send(AppEvent::ShowCover(id));
+            }
+            None => self.cover = None,
+        }
+    }
+
+    pub fn set_cover(&mut self, id: u32, cover: Option<RgbaImage>) {
+        if self.selected_track_id() == Some(id) {
+            self.cover = Some((id, cover));
+        }
+    }
+
+    pub fn set_queue_active(&mut self, active: usize) {
+        self.queue_active = active;
+    }
+
+    pub fn set_exported_covers(&mut self, count: usize, dir: PathBuf) {
+        self.exported_covers = Some((count, dir));
     }
 
     fn remove_row(&mut self) {
@@ -280,13 +443,81 @@ impl MainScreen {
         }
     }
 
+    // the marked tracks, the one under the cursor or the whole playlist
+    fn selected_track_ids(&self) -> Vec<u32> {
+        if let Some(TabContent::Playlists(playlists)) =
+            self.tab_content.get(&TabType::from(self.selected_tab))
+        {
+            let tracks = match playlists.get(self.pl_table.selected_row()) {
+                Some(playlist) => &playlist.tracks,
+                None => return Vec::new(),
+            };
+            return match self.mode {
+                false => tracks.iter().map(|t| t.data.unique_id).collect(),
+                true => {
+                    let mut rows = self.song_table.marked_rows();
+                    if rows.is_empty() {
+                        rows.push(self.song_table.selected_row());
+                    }
+                    rows.iter()
+                        .filter_map(|r| tracks.get(*r))
+                        .map(|t| t.data.unique_id)
+                        .collect::<Vec<u32>>()
+                }
+            };
+        }
+        Vec::new()
+    }
+
+    fn edit_metadata(&mut self) {
+        let ids = self.selected_track_ids();
+        if !ids.is_empty() {
+            let _ = self.sender.send(AppEvent::EditMetadata(ids));
+        }
+    }
+
+    fn identify(&mut self) {
+        let ids = self.selected_track_ids();
+        if !ids.is_empty() {
+            let _ = self.sender.send(AppEvent::IdentifyTracks(ids));
+        }
+    }
+
+    fn lookup(&mut self) {
+        let ids = self.selected_track_ids();
+        if !ids.is_empty() {
+            let _ = self.sender.send(AppEvent::MusicBrainzLookup(ids));
+        }
+    }
+
+    fn export_covers(&mut self) {
+        let ids = self.selected_track_ids();
+        if !ids.is_empty() {
+            let _ = self.sender.send(AppEvent::ExportCovers(ids));
+        }
+    }
+
+    fn is_url(&self) -> bool {
+        let input = self.popup_input.trim_start();
+        input.starts_with("https://") || input.starts_with("http://")
+    }
+
+    fn pick_artwork(&mut self) {
+        let ids = self.selected_track_ids();
+        if !ids.is_empty() {
+            let _ = self.sender.send(AppEvent::PickArtwork(ids));
+        }
+    }
+
     fn download_row(&mut self) {
         match self.tab_content.get(&TabType::from(self.selected_tab)) {
             Some(TabContent::Youtube(youtube)) => match self.mode {
                 false => {
                     let playlist = youtube.get(self.pl_table.selected_row()).unwrap().clone();
 
-                    let _ = self.sender.send(AppEvent::DownloadYTPlaylist(playlist));
+                    let _ = self
+                        .sender
+                        .send(AppEvent::DownloadYTPlaylist((playlist, self.profile)));
                 }
                 true => {
                     let track = youtube
@@ -306,7 +537,9 @@ impl MainScreen {
                         .get(self.pl_table.selected_row())
                         .unwrap()
                         .clone();
-                    let _ = self.sender.send(AppEvent::DownloadPlaylist(playlist));
+                    let _ = self
+                        .sender
+                        .send(AppEvent::DownloadPlaylist((playlist, self.profile)));
                 }
                 true => {
                     let track = soundcloud
@@ -323,6 +556,10 @@ impl MainScreen {
         }
     }
 
+    pub fn set_summary(&mut self, summary: ImportSummary) {
+        self.summary = Some(summary);
+    }
+
     pub fn set_playlists(&mut self, tab: TabType, content: TabContent) {
         self.tab_content.insert(tab, content);
         if TabType::from(self.selected_tab) == tab {
@@ -512,6 +749,42 @@ impl MainScreen {
             .split(area);
 
         self.pl_table.render(frame, chunks[0]);
-        self.song_table.render(frame, chunks[1]);
+
+        if !self.show_lyrics || TabType::from(self.selected_tab) != TabType::Playlists {
+            self.song_table.render(frame, chunks[1]);
+            return;
+        }
+
+        let song_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+            .split(chunks[1]);
+
+        self.song_table.render(frame, song_chunks[0]);
+
+        let mut lyrics_area = song_chunks[1];
+        if let Some((_, Some(cover))) = &self.cover {
+            let side_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(song_chunks[1].width / 2 + 1), // Cover
+                    Constraint::Min(0),                               // Lyrics
+                ])
+                .split(song_chunks[1]);
+            cover::render(frame, side_chunks[0], cover, " Cover ");
+            lyrics_area = side_chunks[1];
+        }
+
+        let text = match &self.lyrics {
+            Some((_, Some(lyrics))) => lyrics.as_str(),
+            Some((_, None)) => "No lyrics",
+            None => "",
+        };
+        let lyrics = Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL).title(" Lyrics "))
+            .style(Style::default().fg(Color::Black));
+
+        frame.render_widget(lyrics, lyrics_area);
     }
 }
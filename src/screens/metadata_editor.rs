@@ -0,0 +1,191 @@
+use crate::component::table::SmartTable;
+use crate::screens::AppScreen;
+use crate::sync::metadata::{self, MetadataChanges, MetadataField};
+use crate::sync::sync_util::AppEvent;
+use crate::AppState;
+use crossterm::event::KeyEventKind::Press;
+use crossterm::event::{KeyCode, KeyEvent};
+use itunesdb::xobjects::XTrackItem;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::{Line, Stylize};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::any::Any;
+use strum::IntoEnumIterator;
+use tokio::sync::mpsc::UnboundedSender;
+
+fn table() -> SmartTable {
+    SmartTable::new(
+        ["Field", "Value"]
+            .iter_mut()
+            .map(|s| s.to_string())
+            .collect(),
+        vec![Constraint::Length(20), Constraint::Min(0)],
+    )
+}
+
+pub struct MetadataEditor {
+    table: SmartTable,
+    sender: UnboundedSender<AppEvent>,
+    track_ids: Vec<u32>,
+    // None means the selected tracks disagree on this field
+    values: Vec<(MetadataField, Option<String>)>,
+    changes: MetadataChanges,
+    write_tags: bool,
+}
+
+impl AppScreen for MetadataEditor {
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if key_event.kind != Press {
+            return;
+        }
+        match key_event.code {
+            KeyCode::Up => self.table.previous_row(),
+            KeyCode::Down => self.table.next_row(),
+            KeyCode::Esc => {
+                let _ = self
+                    .sender
+                    .send(AppEvent::SwitchScreen(AppState::MainScreen));
+            }
+            KeyCode::F(5) => self.save(),
+            KeyCode::F(6) => {
+                self.write_tags = !self.write_tags;
+                self.update_table();
+            }
+            KeyCode::Char(c) => {
+                let field = self.selected_field();
+                if field.is_numeric() && !c.is_ascii_digit() {
+                    return;
+                }
+                self.current_value().push(c);
+                self.update_table();
+            }
+            KeyCode::Backspace => {
+                self.current_value().pop();
+                self.update_table();
+            }
+            KeyCode::Delete => {
+                self.current_value().clear();
+                self.update_table();
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),    // Main content area
+                Constraint::Length(1), // Status bar
+            ])
+            .split(frame.area());
+
+        self.render_main(frame, chunks[0]);
+
+        // Render Status Bar
+        let status_bar = Paragraph::new(Line::from(vec![
+            "<ESC> GO BACK".bold(),
+            " | ".dark_gray(),
+            "<F5> SAVE".bold(),
+            " | ".dark_gray(),
+            if self.write_tags {
+                "<F6> WRITE FILE TAGS: ON".bold()
+            } else {
+                "<F6> WRITE FILE TAGS: OFF".bold()
+            },
+            " | ".dark_gray(),
+            "<DEL> CLEAR".bold(),
+            " | ".dark_gray(),
+            "<F10> QUIT".bold(),
+        ]))
+        .centered();
+        frame.render_widget(status_bar, chunks[1]);
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl MetadataEditor {
+    pub fn new(sender: UnboundedSender<AppEvent>) -> Self {
+        Self {
+            table: table(),
+            sender,
+            track_ids: Vec::new(),
+            values: Vec::new(),
+            changes: MetadataChanges::new(),
+            write_tags: true,
+        }
+    }
+
+    pub fn show_tracks(&mut self, tracks: Vec<XTrackItem>) {
+        self.table = table();
+        self.changes = MetadataChanges::new();
+        self.track_ids = tracks.iter().map(|t| t.data.unique_id).collect();
+        self.values = MetadataField::iter()
+            .map(|field| {
+                let mut values = tracks.iter().map(|t| metadata::get_field(t, field));
+                let first = values.next().unwrap_or_default();
+                if values.all(|v| v == first) {
+                    (field, Some(first))
+                } else {
+                    (field, None)
+                }
+            })
+            .collect();
+
+        self.table.set_title(if tracks.len() == 1 {
+            " Edit track ".to_string()
+        } else {
+            format!(" Edit {} tracks ", tracks.len())
+        });
+        self.update_table();
+    }
+
+    fn selected_field(&self) -> MetadataField {
+        self.values[self.table.selected_row()].0
+    }
+
+    fn current_value(&mut self) -> &mut String {
+        let (field, value) = &self.values[self.table.selected_row()];
+        let initial = value.clone().unwrap_or_default();
+        self.changes.entry(*field).or_insert(initial)
+    }
+
+    fn update_table(&mut self) {
+        let data = self
+            .values
+            .iter()
+            .map(|(field, value)| {
+                let shown = match (self.changes.get(field), value) {
+                    (Some(changed), _) => [changed.as_str(), " *"].concat(),
+                    (None, Some(value)) => value.clone(),
+                    (None, None) => "<multiple values>".to_string(),
+                };
+                vec![String::from(*field), shown]
+            })
+            .collect();
+        self.table.set_data(data);
+    }
+
+    fn save(&mut self) {
+        let changes = std::mem::take(&mut self.changes);
+        if changes.is_empty() {
+            let _ = self
+                .sender
+                .send(AppEvent::SwitchScreen(AppState::MainScreen));
+            return;
+        }
+        let _ = self.sender.send(AppEvent::UpdateMetadata((
+            self.track_ids.clone(),
+            changes,
+            self.write_tags,
+        )));
+    }
+
+    fn render_main(&self, frame: &mut Frame, area: Rect) {
+        self.table.render(frame, area);
+    }
+}
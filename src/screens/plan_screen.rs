@@ -0,0 +1,120 @@
+use crate::component::table::SmartTable;
+use crate::screens::AppScreen;
+use crate::sync::device::DryRunReport;
+use crate::sync::sync_util::AppEvent;
+use crate::util::format_bytes;
+use crate::AppState;
+use crossterm::event::KeyEventKind::Press;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::{Line, Stylize};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::any::Any;
+use tokio::sync::mpsc::UnboundedSender;
+
+fn table() -> SmartTable {
+    SmartTable::new(
+        ["Change", "Item", "Size"]
+            .iter_mut()
+            .map(|s| s.to_string())
+            .collect(),
+        vec![
+            Constraint::Length(10),
+            Constraint::Min(0),
+            Constraint::Length(12),
+        ],
+    )
+}
+
+pub struct PlanScreen {
+    table: SmartTable,
+    sender: UnboundedSender<AppEvent>,
+}
+
+impl AppScreen for PlanScreen {
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if key_event.kind != Press {
+            return;
+        }
+        match key_event.code {
+            KeyCode::Up => self.table.previous_row(),
+            KeyCode::Down => self.table.next_row(),
+            KeyCode::Esc => {
+                let _ = self
+                    .sender
+                    .send(AppEvent::SwitchScreen(AppState::MainScreen));
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),    // Main content area
+                Constraint::Length(1), // Status bar
+            ])
+            .split(frame.area());
+
+        self.render_main(frame, chunks[0]);
+
+        // Render Status Bar
+        let status_bar = Paragraph::new(Line::from(vec![
+            "DRY RUN, NOTHING WAS WRITTEN".bold(),
+            " | ".dark_gray(),
+            "<ESC> GO BACK".bold(),
+            " | ".dark_gray(),
+            "<F10> QUIT".bold(),
+        ]))
+        .centered();
+        frame.render_widget(status_bar, chunks[1]);
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl PlanScreen {
+    pub fn new(sender: UnboundedSender<AppEvent>) -> Self {
+        Self {
+            table: table(),
+            sender,
+        }
+    }
+
+    pub fn show_report(&mut self, report: DryRunReport) {
+        self.table = table();
+
+        self.table.set_title(format!(
+            " {} changes, {} to copy, {} to delete ",
+            report.changes.len(),
+            format_bytes(report.bytes_copied),
+            format_bytes(report.bytes_deleted)
+        ));
+
+        let data = report
+            .changes
+            .iter()
+            .map(|c| {
+                vec![
+                    String::from(c.kind),
+                    c.item.clone(),
+                    if c.bytes > 0 {
+                        format_bytes(c.bytes)
+                    } else {
+                        String::new()
+                    },
+                ]
+            })
+            .collect();
+
+        self.table.set_data(data);
+    }
+
+    fn render_main(&self, frame: &mut Frame, area: Rect) {
+        self.table.render(frame, area);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::component::table::SmartTable;
+use crate::screens::AppScreen;
+use crate::sync::queue::{QueueEntry, QueueStatus};
+use crate::sync::sync_util::AppEvent;
+use crate::AppState;
+use crossterm::event::KeyEventKind::Press;
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::{Line, Stylize};
+use ratatui::widgets::Paragraph;
+use ratatui::Frame;
+use std::any::Any;
+use tokio::sync::mpsc::UnboundedSender;
+
+fn table() -> SmartTable {
+    SmartTable::new(
+        ["Item", "Source", "Status", "Progress"]
+            .iter_mut()
+            .map(|s| s.to_string())
+            .collect(),
+        vec![
+            Constraint::Percentage(40),
+            Constraint::Length(20),
+            Constraint::Length(12),
+            Constraint::Min(0),
+        ],
+    )
+}
+
+fn status_row(status: &QueueStatus) -> (String, String) {
+    match status {
+        QueueStatus::Queued => ("WAITING".to_string(), String::new()),
+        QueueStatus::Downloading(progress) => ("DOWNLOADING".to_string(), progress.clone()),
+        QueueStatus::Importing => ("IMPORTING".to_string(), String::new()),
+        QueueStatus::Done(summary) => {
+            let failed = summary.failures().len();
+            let mut progress = format!("{} imported", summary.imported);
+            if failed > 0 {
+                progress.push_str(&format!(", {} failed", failed));
+            }
+            ("DONE".to_string(), progress)
+        }
+        QueueStatus::Failed(reason) => ("FAILED".to_string(), reason.clone()),
+    }
+}
+
+pub struct QueueScreen {
+    table: SmartTable,
+    entries: Vec<QueueEntry>,
+    sender: UnboundedSender<AppEvent>,
+}
+
+impl AppScreen for QueueScreen {
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if key_event.kind != Press {
+            return;
+        }
+        match key_event.code {
+            KeyCode::Up => self.table.previous_row(),
+            KeyCode::Down => self.table.next_row(),
+            KeyCode::Enter => self.show_report(),
+            KeyCode::F(5) => self.send_selected(AppEvent::QueueRetry),
+            KeyCode::F(8) => self.send_selected(AppEvent::QueueRemove),
+            KeyCode::Esc => {
+                let _ = self
+                    .sender
+                    .send(AppEvent::SwitchScreen(AppState::MainScreen));
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),    // Main content area
+                Constraint::Length(1), // Status bar
+            ])
+            .split(frame.area());
+
+        self.render_main(frame, chunks[0]);
+
+        // Render Status Bar
+        let status_bar = Paragraph::new(Line::from(vec![
+            "<ENTER> REPORT".bold(),
+            " | ".dark_gray(),
+            "<F5> RETRY".bold(),
+            " | ".dark_gray(),
+            "<F8> CANCEL / REMOVE".bold(),
+            " | ".dark_gray(),
+            "<ESC> GO BACK".bold(),
+            " | ".dark_gray(),
+            "<F10> QUIT".bold(),
+        ]))
+        .centered();
+        frame.render_widget(status_bar, chunks[1]);
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl QueueScreen {
+    pub fn new(sender: UnboundedSender<AppEvent>) -> Self {
+        let mut table = table();
+        table.set_title(" Download queue ".to_string());
+        Self {
+            table,
+            entries: Vec::new(),
+            sender,
+        }
+    }
+
+    pub fn set_entries(&mut self, entries: Vec<QueueEntry>) {
+        let data = entries
+            .iter()
+            .map(|e| {
+                let (status, progress) = status_row(&e.status);
+                vec![e.job.title(), e.job.source(), status, progress]
+            })
+            .collect();
+        self.table.set_data(data);
+        // the last entry may have been removed
+        if self.table.selected_row() >= entries.len() {
+            self.table.previous_row();
+        }
+        self.entries = entries;
+
+        self.table.set_title(format!(
+            " Download queue: {} of {} to go ",
+            self.active(),
+            self.entries.len()
+        ));
+    }
+
+    /// Entries still waiting or running, for the main screen.
+    pub fn active(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e.status,
+                    QueueStatus::Queued | QueueStatus::Downloading(_) | QueueStatus::Importing
+                )
+            })
+            .count()
+    }
+
+    fn send_selected(&mut self, event: fn(u32) -> AppEvent) {
+        if let Some(entry) = self.entries.get(self.table.selected_row()) {
+            let _ = self.sender.send(event(entry.id));
+        }
+    }
+
+    fn show_report(&mut self) {
+        if let Some(QueueStatus::Done(summary)) = self
+            .entries
+            .get(self.table.selected_row())
+            .map(|e| &e.status)
+        {
+            let _ = self.sender.send(AppEvent::ImportSummary(summary.clone()));
+        }
+    }
+
+    fn render_main(&self, frame: &mut Frame, area: Rect) {
+        self.table.render(frame, area);
+    }
+}
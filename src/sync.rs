@@ -1,63 +1,83 @@
-use crate::config::get_backup_itunesdb;
+use crate::config::{get_backup_itunesdb, CoverArtConfiguration, TranscodeProfile};
+use crate::dlp::{self, Chapter, MediaInfo};
 use crate::screens::search_util::SearchEntry;
-use crate::sync::sync_util::{AppEvent, DBPlaylist, YTPlaylist};
+use crate::sync::ipod_model::ArtworkFormat;
+use crate::sync::ithmb::Artwork;
+use crate::sync::metadata::MetadataField;
+use crate::sync::queue::{DownloadDir, DownloadQueue, QueueJob};
+use crate::sync::sync_util::{AppEvent, DBPlaylist, ImportSummary, YTPlaylist};
 use crate::util::IPodImage;
 use crate::{
-    config::{
-        get_config_path, get_configs_dir, get_temp_dl_dir, get_temp_itunesdb, LyricaConfiguration,
-    },
+    config::{clear_temp_dl_dir, get_configs_dir, get_temp_itunesdb, load_config},
     util, AppState,
 };
 use id3::TagLike;
-use image::imageops::FilterType;
-use image::ImageReader;
-use itunesdb::artworkdb::aobjects::ADatabase;
+use image::{DynamicImage, ImageReader};
 use itunesdb::objects::PlaylistItem;
 use itunesdb::serializer;
 use itunesdb::xobjects::{XDatabase, XPlArgument, XSomeList, XTrackItem};
 use rand::random;
 use soundcloud::sobjects::{CloudPlaylists, CloudTrack};
-use std::io::Read;
-use std::io::{Cursor, Write};
+use std::io::Cursor;
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use tokio::{
     fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
-    sync::mpsc::{Sender, UnboundedReceiver},
+    io::AsyncReadExt,
+    sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender},
+    task,
 };
 use tokio_util::sync::CancellationToken;
 use youtube_api::objects::YoutubeVideo;
 
+pub mod acoustid;
+mod artwork;
+mod artworkdb;
 mod audio_file_info;
+pub mod cancel;
+mod chapters;
+mod cover_fit;
+pub mod device;
 mod downloader;
+mod gapless;
+pub mod ipod_model;
+pub mod ithmb;
+mod lyrics;
 mod manager;
+pub mod metadata;
+pub mod musicbrainz;
+pub mod queue;
+mod soundcheck;
 pub mod sync_util;
+mod transcode;
+mod web;
 
 async fn track_from_video(
     value: &YoutubeVideo,
+    dir: &DownloadDir,
     ipod_path: String,
+    config: &CoverArtConfiguration,
     sender: &Sender<AppEvent>,
-) -> Option<XTrackItem> {
-    let mut track_path = get_temp_dl_dir();
-    track_path.push(&value.videoId);
-    track_path.set_extension("mp3");
-    let mut image_path = get_temp_dl_dir();
+) -> Result<XTrackItem, String> {
+    let track_path = dir.track_path(&value.videoId);
+    let mut image_path = dir.path.clone();
     image_path.push(&value.videoId);
     image_path.set_extension("webp");
 
-    let audio_file = audio_file_info::from_path(track_path.to_str().unwrap())
+    let audio_file = audio_file_info::from_path(track_path.to_str().unwrap_or_default())
         .await
-        .unwrap();
+        .ok_or("the download is missing or not a playable audio file")?;
     let audio_info = &audio_file.get_nice_object();
-    let song_dbid = util::hash_from_path(track_path.clone());
+    let song_dbid = hash_file(track_path.clone())
+        .await
+        .ok_or("the download can't be read")?;
 
     let size_in_bytes = File::open(track_path)
         .await
-        .unwrap()
+        .map_err(|e| e.to_string())?
         .metadata()
         .await
-        .unwrap()
+        .map_err(|e| e.to_string())?
         .size() as u32;
 
     let mut track = XTrackItem::new(
@@ -74,73 +94,224 @@ async fn track_from_video(
     track.data.mhii_link = size_in_bytes;
 
     if image_path.exists() {
-        make_img(sender, ipod_path, song_dbid, image_path, &mut track).await;
+        make_img(sender, ipod_path, song_dbid, image_path, &mut track, config).await;
     }
 
     audio_file.modify_xtrack(&mut track);
 
     track.set_title(value.title.clone());
     track.set_artist(value.publisher.clone());
-    Some(track)
+    apply_info(&mut track, &dir.path, &value.videoId);
+    Ok(track)
 }
 
-async fn make_img(
-    sender: &Sender<AppEvent>,
+// reads the whole file, on the blocking pool like everything else touching the download
+async fn hash_file(path: PathBuf) -> Option<u64> {
+    task::spawn_blocking(move || util::hash_from_path(path))
+        .await
+        .ok()
+        .flatten()
+}
+
+// every site has its own thumbnail format
+fn thumbnail(dir: &Path, id: &str) -> Option<PathBuf> {
+    ["webp", "jpg", "png"]
+        .iter()
+        // with_extension would cut off ids with dots in them
+        .map(|ext| dir.join(format!("{}.{}", id, ext)))
+        .find(|p| p.exists())
+}
+
+async fn track_from_file(
+    track_path: &Path,
+    image_path: Option<PathBuf>,
     ipod_path: String,
-    song_dbid: u64,
-    image_path: PathBuf,
-    track: &mut XTrackItem,
-) {
-    let _ = sender.send(AppEvent::ArtworkProgress((0, 2))).await;
-    let mut adb = get_artwork_db(&ipod_path);
+    config: &CoverArtConfiguration,
+    sender: &Sender<AppEvent>,
+) -> Result<XTrackItem, String> {
+    let audio_file = audio_file_info::from_path(track_path.to_str().unwrap_or_default())
+        .await
+        .ok_or("the download is missing or not a playable audio file")?;
+    let audio_info = &audio_file.get_nice_object();
+    let song_dbid = hash_file(track_path.to_path_buf())
+        .await
+        .ok_or("the download can't be read")?;
 
-    let image_data = std::fs::read(image_path).unwrap();
+    let size_in_bytes = File::open(track_path)
+        .await
+        .map_err(|e| e.to_string())?
+        .metadata()
+        .await
+        .map_err(|e| e.to_string())?
+        .size() as u32;
 
-    let cover_hash = util::hash(&image_data);
+    let mut track = XTrackItem::new(
+        random(),
+        size_in_bytes,
+        (audio_info.duration * 1000.0) as u32,
+        0,
+        (audio_info.bit_rate / 1000) as u32,
+        audio_info.sample_rate as u32 * 0x10000,
+        song_dbid,
+        0,
+    );
 
-    let if_cover_present = adb.if_cover_present(cover_hash);
+    track.data.mhii_link = size_in_bytes;
 
-    let (small_img_name, large_img_name) = adb.add_images(song_dbid, cover_hash);
+    if let Some(image_path) = image_path {
+        make_img(sender, ipod_path, song_dbid, image_path, &mut track, config).await;
+    }
 
-    let size = image_data.len();
+    audio_file.modify_xtrack(&mut track);
+    Ok(track)
+}
 
-    if !if_cover_present {
-        make_cover_image(&image_data, &ipod_path, &small_img_name, (100, 100));
-        let _ = sender.send(AppEvent::ArtworkProgress((1, 2))).await;
-        make_cover_image(&image_data, &ipod_path, &large_img_name, (200, 200));
+async fn track_from_media(
+    value: &MediaInfo,
+    dir: &DownloadDir,
+    ipod_path: String,
+    config: &CoverArtConfiguration,
+    sender: &Sender<AppEvent>,
+) -> Result<XTrackItem, String> {
+    let track_path = dir.track_path(&value.id);
+    let mut track = track_from_file(
+        &track_path,
+        thumbnail(&dir.path, &value.id),
+        ipod_path,
+        config,
+        sender,
+    )
+    .await?;
+
+    track.set_title(value.title.clone().unwrap_or(value.id.clone()));
+    if let Some(artist) = value.artist() {
+        track.set_artist(artist);
     }
+    apply_info(&mut track, &dir.path, &value.id);
+    Ok(track)
+}
 
-    write_artwork_db(adb, &ipod_path);
+/// One chapter of a download cut by `chapters::split`, tagged as a track of the album the upload is.
+/// `number` counts the chapters of `list` from 1.
+async fn track_from_chapter(
+    info: &MediaInfo,
+    list: &[Chapter],
+    number: usize,
+    dir: &DownloadDir,
+    ipod_path: String,
+    config: &CoverArtConfiguration,
+    sender: &Sender<AppEvent>,
+) -> Result<XTrackItem, String> {
+    let track_path = chapters::chapter_path(&dir.track_path(&info.id), number);
+    let mut track = track_from_file(
+        &track_path,
+        thumbnail(&dir.path, &info.id),
+        ipod_path,
+        config,
+        sender,
+    )
+    .await?;
+
+    let chapter = &list[number - 1];
+    let mut changes = info.changes();
+    let title = chapter.title.clone();
+    changes.insert(
+        MetadataField::Title,
+        title.unwrap_or(format!("Chapter {}", number)),
+    );
+    changes.insert(MetadataField::Album, chapters::album(info));
+    changes.insert(MetadataField::TrackNumber, number.to_string());
+    changes.insert(MetadataField::TotalTracks, list.len().to_string());
+    if let Some(artist) = info.artist() {
+        changes.insert(MetadataField::Artist, artist);
+    }
+    metadata::apply_changes(&mut track, &changes);
+    if chapter.end_time > chapter.start_time {
+        track.data.length = ((chapter.end_time - chapter.start_time) * 1000.0) as u32;
+    }
+    Ok(track)
+}
+
+/// Fills in what yt-dlp's info JSON knows better than the file and the site's listing.
+fn apply_info(track: &mut XTrackItem, dir: &Path, id: &str) {
+    let info = match dlp::read_info(dir, id) {
+        Some(info) => info,
+        None => return,
+    };
+    metadata::apply_changes(track, &info.changes());
+    // ffprobe guesses the length of some streams from the bitrate
+    if let Some(duration) = info.duration {
+        track.data.length = (duration * 1000.0) as u32;
+    }
+}
+
+async fn make_img(
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+    song_dbid: u64,
+    image_path: PathBuf,
+    track: &mut XTrackItem,
+    config: &CoverArtConfiguration,
+) {
+    let formats = ipod_model::cover_formats(&ipod_path);
+    let max = formats.len() as u32;
+    let _ = sender.send(AppEvent::ArtworkProgress((0, max))).await;
+
+    // decoding, scaling and writing the thumbnails would hold up the sync service
+    let config = config.clone();
+    let added = task::spawn_blocking(move || {
+        let image_data = std::fs::read(image_path).ok()?;
+        let images = render_covers(&image_data, formats, &config)?;
+        let size = image_data.len();
+
+        let mut artwork = Artwork::open(&ipod_path);
+        artwork.add(
+            song_dbid,
+            util::hash(&image_data),
+            size as u32,
+            &images,
+            &ipod_path,
+        );
+        artwork.save(&ipod_path);
+        Some(size)
+    })
+    .await;
+    let size = match added {
+        Ok(Some(size)) => size,
+        _ => return,
+    };
 
     track.data.artwork_size = size as u32;
     track.data.has_artwork = 1;
     track.data.artwork_count = 1;
-    let _ = sender.send(AppEvent::ArtworkProgress((2, 2))).await;
+    let _ = sender.send(AppEvent::ArtworkProgress((max, max))).await;
 }
 
 async fn track_from_soundcloud(
     value: &CloudTrack,
+    dir: &DownloadDir,
     ipod_path: String,
+    config: &CoverArtConfiguration,
     sender: &Sender<AppEvent>,
-) -> Option<XTrackItem> {
-    let mut track_path = get_temp_dl_dir();
-    track_path.push(value.id.to_string());
-    track_path.set_extension("mp3");
-    let mut image_path = get_temp_dl_dir();
+) -> Result<XTrackItem, String> {
+    let track_path = dir.track_path(&value.id.to_string());
+    let mut image_path = dir.path.clone();
     image_path.push(value.id.to_string());
     image_path.set_extension("jpg");
-    let audio_file = audio_file_info::from_path(track_path.to_str().unwrap())
+    let audio_file = audio_file_info::from_path(track_path.to_str().unwrap_or_default())
         .await
-        .unwrap();
+        .ok_or("the download is missing or not a playable audio file")?;
     let audio_info = &audio_file.get_nice_object();
-    let song_dbid = util::hash_from_path(track_path.clone());
+    let song_dbid = hash_file(track_path.clone())
+        .await
+        .ok_or("the download can't be read")?;
 
     let size_in_bytes = File::open(track_path)
         .await
-        .unwrap()
+        .map_err(|e| e.to_string())?
         .metadata()
         .await
-        .unwrap()
+        .map_err(|e| e.to_string())?
         .size() as u32;
 
     let mut track = XTrackItem::new(
@@ -157,7 +328,7 @@ async fn track_from_soundcloud(
     track.data.mhii_link = size_in_bytes;
 
     if image_path.exists() {
-        make_img(sender, ipod_path, song_dbid, image_path, &mut track).await;
+        make_img(sender, ipod_path, song_dbid, image_path, &mut track, config).await;
     }
 
     audio_file.modify_xtrack(&mut track);
@@ -172,7 +343,8 @@ async fn track_from_soundcloud(
     if value.genre.is_some() {
         track.set_genre(value.genre.clone().unwrap());
     }
-    Some(track)
+    apply_info(&mut track, &dir.path, &value.id.to_string());
+    Ok(track)
 }
 
 fn get_track_location(unique_id: u32, extension: &str) -> String {
@@ -194,7 +366,7 @@ fn get_full_track_location(p: PathBuf, unique_id: u32, extension: &str) -> PathB
     dest.push("iPod_Control");
     dest.push("Music");
     dest.push(["F", &format!("{:02}", &(unique_id % 100))].concat());
-    let _ = std::fs::create_dir_all(dest.to_str().unwrap());
+    device::create_dir_all(&dest);
     dest.push(format!("{:X}", unique_id));
     dest.set_extension(extension);
     dest
@@ -212,21 +384,35 @@ fn overwrite_database(database: &mut XDatabase, ipod_path: &String) {
     let data = serializer::to_bytes(database);
     let p: PathBuf = get_itunesdb_location(ipod_path);
 
-    let cd = get_backup_itunesdb();
-    let _ = std::fs::copy(&p, &cd);
+    if !device::is_dry_run() {
+        let cd = get_backup_itunesdb();
+        let _ = std::fs::copy(&p, &cd);
+    }
 
-    let mut file = std::fs::File::create(p).unwrap();
-    let _ = file.write(&data);
+    let _ = device::write_file(&p, &data);
+}
+
+/// Saves the database in the middle of a long import without another backup.
+fn flush_database(database: &mut XDatabase, ipod_path: &str) {
+    let data = serializer::to_bytes(database);
+    let _ = device::write_file(&get_itunesdb_location(ipod_path), &data);
 }
 
 pub fn initialize_async_service(
     sender: Sender<AppEvent>,
     receiver: UnboundedReceiver<AppEvent>,
+    // the sending half of `receiver`, background downloads report through it
+    requests: UnboundedSender<AppEvent>,
     token: CancellationToken,
 ) {
     tokio::spawn(async move {
         let _ = std::fs::create_dir_all(get_configs_dir());
 
+        let mut queue = DownloadQueue::restore();
+
+        // leftovers of the last run, but not what the restored downloads already have
+        clear_temp_dl_dir(&queue.download_dirs());
+
         let mut ipod_db = None;
 
         let mut database = None;
@@ -238,38 +424,77 @@ pub fn initialize_async_service(
                 _ = token.cancelled() => { return; }
                 r = receiver.recv() => {
                     if let Some(request) = r {
+                        cancel::reset();
+                        // a dry run plays every change against a throwaway copy of the database
+                        let mut scratch = match database.as_mut() {
+                            Some(db) if device::is_dry_run() && request.changes_device() => Some(device::copy_database(db)),
+                            _ => None,
+                        };
+                        let before = scratch.as_mut().map(device::DbSnapshot::new);
+                        let db = match scratch.as_mut() {
+                            Some(scratch) => Some(scratch),
+                            None => database.as_mut(),
+                        };
                         match request {
                             AppEvent::SearchIPod => {
                                 if let Some(p) = util::search_ipod() {
                                     ipod_db = Some(p.clone());
                                     let _ = sender.send(AppEvent::SwitchScreen(AppState::MainScreen)).await;
                                     database = Some(parse_itunes(&sender, p).await);
+                                    // downloads restored from the last run import into this iPod
+                                    queue.refresh(&sender, &requests).await;
                                 } else {
                                     let _ = sender.send(AppEvent::IPodNotFound).await;
                                 }
                             },
-                            AppEvent::DownloadPlaylist(playlist) => { downloader::download_playlist(playlist, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await; },
-                            AppEvent::DownloadTrack(track) => { downloader::download_track(track, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await; },
-                            AppEvent::DownloadYTTrack(video) => { downloader::download_video(video, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await; },
-                            AppEvent::DownloadYTPlaylist(ytplaylist) => { downloader::download_youtube_playlist(ytplaylist, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await; },
+                            AppEvent::DownloadPlaylist((playlist, profile)) => { queue.push(QueueJob::SoundCloudPlaylist(playlist), profile); queue.refresh(&sender, &requests).await; },
+                            AppEvent::DownloadTrack(track) => { queue.push(QueueJob::SoundCloudTrack(track), TranscodeProfile::Original); queue.refresh(&sender, &requests).await; },
+                            AppEvent::DownloadYTTrack(video) => { queue.push(QueueJob::YouTubeVideo(video), TranscodeProfile::Original); queue.refresh(&sender, &requests).await; },
+                            AppEvent::DownloadYTPlaylist((ytplaylist, profile)) => { queue.push(QueueJob::YouTubePlaylist(ytplaylist), profile); queue.refresh(&sender, &requests).await; },
+                            AppEvent::DownloadUrl((url, profile)) => { queue.push(QueueJob::Url(url, None), profile); queue.refresh(&sender, &requests).await; },
+                            AppEvent::QueueResolved((id, info)) => { queue.set_info(id, info); queue.refresh(&sender, &requests).await; },
+                            AppEvent::QueueProgress((id, progress)) => { queue.set_progress(id, progress); queue.refresh(&sender, &requests).await; },
+                            AppEvent::QueueDownloaded((id, result)) => downloader::import_queued(id, result, &mut queue, db.unwrap(), &sender, &requests, ipod_db.clone().unwrap()).await,
+                            AppEvent::QueueRetry(id) => { queue.retry(id); queue.refresh(&sender, &requests).await; },
+                            AppEvent::QueueRemove(id) => { queue.remove(id); queue.refresh(&sender, &requests).await; },
+                            AppEvent::ImportSummary(summary) => { let _ = sender.send(AppEvent::ImportSummary(summary)).await; },
                             AppEvent::SwitchScreen(state) => { let _ = sender.send(AppEvent::SwitchScreen(state)).await;},
-                            AppEvent::LoadFromFS(path) => {
+                            AppEvent::LoadFromFS((path, profile)) => {
                                 let _ = sender
                                         .send(AppEvent::SwitchScreen(AppState::LoadingScreen))
                                         .await;
-                                downloader::load_from_fs(path, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await;
+                                let mut summary = ImportSummary::new(profile);
+                                downloader::load_from_fs(path, profile, &mut summary, db.unwrap(), &sender, ipod_db.clone().unwrap()).await;
                                     let _ = sender
                                         .send(AppEvent::SwitchScreen(AppState::FileSystem))
                                         .await;
+                                let _ = sender.send(AppEvent::ImportSummary(summary)).await;
                             },
-                            AppEvent::LoadFromFSVec(files) => downloader::load_files_from_fs(files, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await,
-                            AppEvent::LoadFromFSPL((files, title)) => downloader::load_files_from_fs_as_playlist(files, title, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await,
-                            AppEvent::RemoveTrack(id) => manager::remove_track(id, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await,
-                            AppEvent::RemovePlaylist((pl_id, is_hard)) => manager::remove_playlist(pl_id, is_hard, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await,
-                            AppEvent::RemoveTrackFromPlaylist((track_id, pl_id)) => manager::remove_track_from_playlist(track_id, pl_id, database.as_mut().unwrap(), &sender, ipod_db.clone().unwrap()).await,
-                            AppEvent::SearchFor(query) => track_search(query, database.as_mut().unwrap(), &sender).await,
+                            AppEvent::LoadFromFSVec((files, profile)) => downloader::load_files_from_fs(files, profile, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::LoadFromFSPL((files, title, profile)) => downloader::load_files_from_fs_as_playlist(files, title, profile, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::RemoveTrack(id) => manager::remove_track(id, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::RemovePlaylist((pl_id, is_hard)) => manager::remove_playlist(pl_id, is_hard, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::RemoveTrackFromPlaylist((track_id, pl_id)) => manager::remove_track_from_playlist(track_id, pl_id, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::SearchFor(query) => track_search(query, db.unwrap(), &sender).await,
+                            AppEvent::EditMetadata(ids) => manager::show_metadata(ids, db.unwrap(), &sender).await,
+                            AppEvent::ShowLyrics(id) => manager::show_lyrics(id, &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::ShowCover(id) => manager::show_cover(id, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::ExportCovers(ids) => manager::export_covers(ids, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::UpdateMetadata((ids, changes, write_tags)) => manager::update_metadata(ids, changes, write_tags, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::UpdateTracksMetadata((edits, write_tags)) => manager::update_tracks_metadata(edits, write_tags, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::IdentifyTracks(ids) => manager::identify_tracks(ids, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::MusicBrainzLookup(ids) => manager::musicbrainz_lookup(ids, db.unwrap(), &sender).await,
+                            AppEvent::MusicBrainzRelease((ids, candidate)) => manager::musicbrainz_release(ids, candidate, db.unwrap(), &sender).await,
+                            AppEvent::PickArtwork(ids) => { let _ = sender.send(AppEvent::PickArtwork(ids)).await; },
+                            AppEvent::SetArtwork((ids, image)) => manager::set_artwork(ids, image, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
+                            AppEvent::MusicBrainzApply((edits, write_tags)) => manager::musicbrainz_apply(edits, write_tags, db.unwrap(), &sender, ipod_db.clone().unwrap()).await,
                             _ => {}
                         }
+                        if let (Some(before), Some(mut scratch)) = (before, scratch) {
+                            let report = before.report(&device::DbSnapshot::new(&mut scratch));
+                            let _ = sender.send(AppEvent::ITunesParsed(get_playlists(database.as_mut().unwrap()))).await;
+                            let _ = sender.send(AppEvent::DryRunReport(report)).await;
+                        }
                     }
                 }
             }
@@ -318,73 +543,37 @@ async fn track_search(query: String, database: &mut XDatabase, sender: &Sender<A
     let _ = sender.send(AppEvent::SearchShow(results)).await;
 }
 
-fn write_artwork_db(adb: ADatabase, ipod_path: &str) {
-    let mut dst = PathBuf::from(ipod_path);
-    dst.push("iPod_Control");
-    dst.push("Artwork");
-    dst.push("ArtworkDB");
-    let bytes = itunesdb::artworkdb::serializer::to_bytes(adb);
-    let mut f = std::fs::File::create(dst).unwrap();
-    let _ = f.write(&bytes);
-}
-
-fn get_artwork_db(ipod_path: &str) -> ADatabase {
-    let mut dst = PathBuf::from(ipod_path);
-    dst.push("iPod_Control");
-    dst.push("Artwork");
-    dst.push("ArtworkDB");
-
-    if dst.exists() {
-        let mut f = std::fs::File::open(dst).unwrap();
-        let mut buf = Vec::new();
-        match f.read_to_end(&mut buf) {
-            Ok(n) => {
-                return itunesdb::artworkdb::deserializer::parse_bytes(&buf[..n]);
-            }
-            Err(_e) => {}
-        }
-    }
-    itunesdb::artworkdb::deserializer::new_db()
+/// Renders the cover once for each format, None if it can't be decoded.
+/// CPU heavy, safe to run away from the iPod.
+fn render_covers(
+    cover: &[u8],
+    formats: &[ArtworkFormat],
+    config: &CoverArtConfiguration,
+) -> Option<Vec<IPodImage>> {
+    let dynamic_im = decode_cover(cover)?;
+    Some(
+        formats
+            .iter()
+            .map(|f| scale_cover(&dynamic_im, f, config))
+            .collect(),
+    )
 }
 
-fn make_cover_image(cover: &[u8], ipod_path: &str, file_name: &str, dim: (u32, u32)) {
-    let mut dynamic_im = ImageReader::new(Cursor::new(cover))
+fn decode_cover(cover: &[u8]) -> Option<DynamicImage> {
+    ImageReader::new(Cursor::new(cover))
         .with_guessed_format()
-        .unwrap()
+        .ok()?
         .decode()
-        .unwrap();
-
-    if dynamic_im.height() != dynamic_im.width() {
-        let side = if dynamic_im.height() < dynamic_im.width() {
-            dynamic_im.height()
-        } else {
-            dynamic_im.width()
-        };
-        let x = if dynamic_im.height() < dynamic_im.width() {
-            (dynamic_im.width() - side) / 2
-        } else {
-            0
-        };
-        let y = if dynamic_im.height() < dynamic_im.width() {
-            0
-        } else {
-            (dynamic_im.height() - side) / 2
-        };
-        dynamic_im = dynamic_im.crop(x, y, side, side);
-    }
-
-    let img: IPodImage = dynamic_im
-        .resize_exact(dim.0, dim.1, FilterType::Lanczos3)
-        .into();
-
-    let mut dst = PathBuf::from(ipod_path);
-    dst.push("iPod_Control");
-    dst.push("Artwork");
-
-    let _ = std::fs::create_dir_all(dst.clone());
+        .ok()
+}
 
-    dst.push(file_name);
-    img.write(dst);
+fn scale_cover(
+    dynamic_im: &DynamicImage,
+    format: &ArtworkFormat,
+    config: &CoverArtConfiguration,
+) -> IPodImage {
+    let scaled = cover_fit::fit(dynamic_im, format.width, format.height, config);
+    IPodImage::encode(&scaled, format, config.get_dither())
 }
 
 fn get_playlists(db: &mut XDatabase) -> Vec<DBPlaylist> {
@@ -422,17 +611,7 @@ async fn parse_itunes(sender: &Sender<AppEvent>, path: String) -> XDatabase {
         .send(AppEvent::ITunesParsed(get_playlists(&mut database)))
         .await;
 
-    let p = get_config_path();
-    if !p.exists() {
-        let config = LyricaConfiguration::default();
-        let cfg_str = toml::to_string_pretty(&config).unwrap();
-        let mut file = File::create(&p).await.unwrap();
-        let _ = file.write(cfg_str.as_bytes()).await;
-    }
-    let mut file = File::open(p).await.unwrap();
-    let mut content = String::new();
-    file.read_to_string(&mut content).await.unwrap();
-    let config: LyricaConfiguration = toml::from_str(&content).unwrap();
+    let config = load_config();
 
     let yt_sender = sender.clone();
     let yt_channel_id = config.get_youtube().user_id.clone();
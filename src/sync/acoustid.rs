@@ -0,0 +1,171 @@
+use crate::config::load_config;
+use crate::sync::metadata::{self, MetadataChanges, MetadataField};
+use crate::sync::web;
+use itunesdb::xobjects::XTrackItem;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// What a lookup proposes for one track. Nothing is written until the proposal is accepted.
+pub struct Identification {
+    pub track: XTrackItem,
+    pub score: f64,
+    pub proposal: Result<MetadataChanges, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Fingerprint {
+    duration: f64,
+    fingerprint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResponse {
+    status: String,
+    error: Option<LookupError>,
+    #[serde(default)]
+    results: Vec<LookupResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResult {
+    score: f64,
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    title: Option<String>,
+    #[serde(default)]
+    artists: Vec<Artist>,
+    #[serde(default)]
+    releasegroups: Vec<ReleaseGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Artist {
+    name: String,
+    joinphrase: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseGroup {
+    title: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    artists: Vec<Artist>,
+}
+
+// "Artist A feat. Artist B", the way MusicBrainz credits them
+fn artist_credit(artists: &[Artist]) -> String {
+    artists
+        .iter()
+        .map(|a| [a.name.as_str(), a.joinphrase.as_deref().unwrap_or_default()].concat())
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+async fn fingerprint(path: &Path) -> Result<Fingerprint, String> {
+    let mut command = Command::new(&load_config().get_acoustid().fpcalc_path);
+    command.arg("-json");
+    command.arg(path);
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::null());
+
+    let output = command
+        .output()
+        .await
+        .map_err(|_e| "fpcalc isn't installed".to_string())?;
+    if !output.status.success() {
+        return Err("fpcalc couldn't decode the file".to_string());
+    }
+    serde_json::from_slice(&output.stdout).map_err(|_e| "fpcalc gave no fingerprint".to_string())
+}
+
+/// The fields of the best match which differ from what the track has now.
+fn propose(track: &XTrackItem, recording: &Recording) -> MetadataChanges {
+    // a recording appears on singles and compilations too, the album is what people expect
+    let release_group = recording
+        .releasegroups
+        .iter()
+        .find(|g| g.kind.as_deref() == Some("Album"))
+        .or(recording.releasegroups.first());
+
+    let mut proposed = vec![
+        (
+            MetadataField::Title,
+            recording.title.clone().unwrap_or_default(),
+        ),
+        (MetadataField::Artist, artist_credit(&recording.artists)),
+    ];
+    if let Some(group) = release_group {
+        proposed.push((
+            MetadataField::Album,
+            group.title.clone().unwrap_or_default(),
+        ));
+        proposed.push((MetadataField::AlbumArtist, artist_credit(&group.artists)));
+    }
+
+    proposed
+        .into_iter()
+        .filter(|(field, value)| !value.is_empty() && *value != metadata::get_field(track, *field))
+        .collect()
+}
+
+/// Fingerprints the file and asks the configured AcoustID service who recorded it.
+pub async fn identify(track: XTrackItem, path: Option<&Path>) -> Identification {
+    let mut identification = Identification {
+        track,
+        score: 0.0,
+        proposal: Err("the file isn't on the iPod".to_string()),
+    };
+    let path = match path {
+        Some(path) => path,
+        None => return identification,
+    };
+
+    let lookup = async {
+        let fp = fingerprint(path).await?;
+        let config = load_config();
+        let acoustid = config.get_acoustid();
+        let url = [acoustid.base_url.trim_end_matches('/'), "/lookup"].concat();
+        let params = [
+            ("format", "json".to_string()),
+            ("client", acoustid.api_key.clone()),
+            ("meta", "recordings releasegroups".to_string()),
+            ("duration", (fp.duration.round() as u32).to_string()),
+            ("fingerprint", fp.fingerprint),
+        ];
+        // fingerprints are too long for a query string
+        let response: LookupResponse = web::request(&url, &params, true).await?;
+        if response.status != "ok" {
+            return Err(response
+                .error
+                .map(|e| e.message)
+                .unwrap_or("the lookup failed".to_string()));
+        }
+        response
+            .results
+            .into_iter()
+            .filter(|r| !r.recordings.is_empty())
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+            .ok_or("no match found".to_string())
+    };
+
+    match lookup.await {
+        Ok(result) => {
+            identification.score = result.score;
+            identification.proposal = Ok(propose(&identification.track, &result.recordings[0]));
+        }
+        Err(reason) => identification.proposal = Err(reason),
+    }
+    identification
+}
@@ -0,0 +1,70 @@
+use crate::config::load_config;
+use crate::sync::{musicbrainz, web};
+use std::path::Path;
+use tokio::sync::Mutex;
+
+// the usual names, in the order they are preferred
+const SIDECAR_NAMES: [&str; 9] = [
+    "cover.jpg",
+    "cover.jpeg",
+    "cover.png",
+    "folder.jpg",
+    "folder.jpeg",
+    "folder.png",
+    "front.jpg",
+    "front.jpeg",
+    "front.png",
+];
+
+// the tracks of an album are imported together, the cover is only fetched once for all of them
+const CACHED_RELEASES: usize = 8;
+static ONLINE_COVERS: Mutex<Vec<(String, Option<Vec<u8>>)>> = Mutex::const_new(Vec::new());
+
+/// A cover image lying next to the track, the name is matched case-insensitively.
+pub fn sidecar(path: &Path) -> Option<Vec<u8>> {
+    let dir = path.parent()?;
+    let files: Vec<_> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+
+    SIDECAR_NAMES.iter().find_map(|name| {
+        let file = files.iter().find(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        })?;
+        std::fs::read(file).ok()
+    })
+}
+
+/// The front cover of the release from the configured Cover Art Archive-compatible service.
+pub async fn cover_art_archive(release_id: &str) -> Option<Vec<u8>> {
+    let config = load_config();
+    let base_url = config.get_coverart().base_url.trim_end_matches('/');
+    if base_url.is_empty() {
+        return None;
+    }
+
+    let mut cache = ONLINE_COVERS.lock().await;
+    if let Some((_, cover)) = cache.iter().find(|(id, _)| id == release_id) {
+        return cover.clone();
+    }
+
+    let url = [base_url, "/release/", release_id, "/front-500"].concat();
+    let cover = web::download(&url).await.ok().filter(|c| !c.is_empty());
+
+    if cache.len() == CACHED_RELEASES {
+        cache.remove(0);
+    }
+    cache.push((release_id.to_string(), cover.clone()));
+    cover
+}
+
+/// Looks the cover up online when the file is tagged with a MusicBrainz release id.
+pub async fn online(path: &Path) -> Option<Vec<u8>> {
+    let release_id = musicbrainz::release_id(path)?;
+    cover_art_archive(&release_id).await
+}
@@ -0,0 +1,397 @@
+// The ArtworkDB as the iPod firmware reads it. The itunesdb crate can only append covers in two
+// fixed sizes, this reads and writes the whole image list so covers can be registered in the
+// formats of the model, taken out again and looked up.
+//
+// Layout (little endian): mhfd > 3 x mhsd, which hold the image list (mhli > mhii > mhod type 2
+// > mhni > mhod type 3 with the ithmb file name), the album list (mhla) and the file list
+// (mhlf > mhif, one per format).
+use std::collections::BTreeMap;
+
+const MHFD_LEN: usize = 0x84;
+const MHSD_LEN: usize = 0x60;
+const LIST_LEN: usize = 0x5c;
+const MHII_LEN: usize = 0x98;
+const MHOD_LEN: usize = 0x18;
+const MHNI_LEN: usize = 0x4c;
+const MHIF_LEN: usize = 0x7c;
+
+// the ids iTunes hands out start here
+const FIRST_IMAGE_ID: u32 = 100;
+
+/// One thumbnail of an image and where it lies in which ithmb file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub format_id: u32,
+    // "F1055_1.ithmb", the database stores it as ":F1055_1.ithmb"
+    pub file: String,
+    pub offset: u32,
+    pub size: u32,
+    pub width: u16,
+    pub height: u16,
+    pub vertical_padding: i16,
+    pub horizontal_padding: i16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageItem {
+    pub id: u32,
+    pub song_dbid: u64,
+    // bytes of the picture the thumbnails were made from
+    pub source_size: u32,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtworkDb {
+    pub images: Vec<ImageItem>,
+    next_id: u32,
+    // the album list of the photo database, kept as it was read
+    albums: Option<Vec<u8>>,
+    // format id -> bytes one image of it takes up
+    formats: BTreeMap<u32, u32>,
+}
+
+impl Default for ArtworkDb {
+    fn default() -> Self {
+        Self {
+            images: Vec::new(),
+            next_id: FIRST_IMAGE_ID,
+            albums: None,
+            formats: BTreeMap::new(),
+        }
+    }
+}
+
+impl ArtworkDb {
+    /// None when the data isn't an ArtworkDB or is cut short.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.get(0..4)? != b"mhfd" || u32_at(data, 8)? as usize > data.len() {
+            return None;
+        }
+        let mut db = Self {
+            next_id: u32_at(data, 28)?.max(FIRST_IMAGE_ID),
+            ..Self::default()
+        };
+
+        let mut pos = u32_at(data, 4)? as usize;
+        for _ in 0..u32_at(data, 20)? {
+            if data.get(pos..pos + 4)? != b"mhsd" {
+                return None;
+            }
+            let end = pos + u32_at(data, pos + 8)? as usize;
+            let body = pos + u32_at(data, pos + 4)? as usize;
+            match u16_at(data, pos + 12)? {
+                1 => db.images = parse_images(data, body)?,
+                // empty for covers, only written back when something is in it
+                2 if u32_at(data, body + 8)? > 0 => db.albums = Some(data.get(pos..end)?.to_vec()),
+                3 => db.formats = parse_formats(data, body)?,
+                _ => {}
+            }
+            pos = end;
+        }
+
+        let highest = db.images.iter().map(|i| i.id + 1).max().unwrap_or(0);
+        db.next_id = db.next_id.max(highest);
+        Some(db)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let images: Vec<u8> = self.images.iter().flat_map(image_bytes).collect();
+        let image_list = [list_header(b"mhli", self.images.len()), images].concat();
+
+        let album_list = match &self.albums {
+            Some(albums) => albums.clone(),
+            None => section(2, &list_header(b"mhla", 0)),
+        };
+
+        let files: Vec<u8> = self
+            .formats
+            .iter()
+            .flat_map(|(id, size)| {
+                let mut mhif = header(b"mhif", MHIF_LEN, MHIF_LEN);
+                put_u32(&mut mhif, 16, *id);
+                put_u32(&mut mhif, 20, *size);
+                mhif
+            })
+            .collect();
+        let file_list = [list_header(b"mhlf", self.formats.len()), files].concat();
+
+        let body = [section(1, &image_list), album_list, section(3, &file_list)].concat();
+        let mut mhfd = header(b"mhfd", MHFD_LEN, MHFD_LEN + body.len());
+        put_u32(&mut mhfd, 16, 2);
+        put_u32(&mut mhfd, 20, 3);
+        put_u32(&mut mhfd, 28, self.next_id);
+        mhfd[48] = 2;
+        [mhfd, body].concat()
+    }
+
+    pub fn image(&self, song_dbid: u64) -> Option<&ImageItem> {
+        self.images.iter().find(|i| i.song_dbid == song_dbid)
+    }
+
+    /// Adds the image of a song, replacing the one it had.
+    pub fn add(&mut self, song_dbid: u64, source_size: u32, thumbnails: Vec<Thumbnail>) {
+        self.remove(song_dbid);
+        for thumbnail in thumbnails.iter() {
+            self.formats.insert(thumbnail.format_id, thumbnail.size);
+        }
+        self.images.push(ImageItem {
+            id: self.next_id,
+            song_dbid,
+            source_size,
+            thumbnails,
+        });
+        self.next_id += 1;
+    }
+
+    /// Takes a song's image out, returns whether it had one.
+    pub fn remove(&mut self, song_dbid: u64) -> bool {
+        let before = self.images.len();
+        self.images.retain(|i| i.song_dbid != song_dbid);
+        self.images.len() != before
+    }
+}
+
+fn parse_images(data: &[u8], pos: usize) -> Option<Vec<ImageItem>> {
+    if data.get(pos..pos + 4)? != b"mhli" {
+        return None;
+    }
+    let mut images = Vec::new();
+    let mut item = pos + u32_at(data, pos + 4)? as usize;
+    for _ in 0..u32_at(data, pos + 8)? {
+        if data.get(item..item + 4)? != b"mhii" {
+            return None;
+        }
+        let mut image = ImageItem {
+            id: u32_at(data, item + 16)?,
+            song_dbid: u64_at(data, item + 20)?,
+            source_size: u32_at(data, item + 48)?,
+            thumbnails: Vec::new(),
+        };
+
+        let mut mhod = item + u32_at(data, item + 4)? as usize;
+        for _ in 0..u32_at(data, item + 12)? {
+            // type 2 holds a thumbnail, the rest are photo details
+            if u16_at(data, mhod + 12)? == 2 {
+                image.thumbnails.push(parse_thumbnail(
+                    data,
+                    mhod + u32_at(data, mhod + 4)? as usize,
+                )?);
+            }
+            mhod += u32_at(data, mhod + 8)? as usize;
+        }
+
+        images.push(image);
+        item += u32_at(data, item + 8)? as usize;
+    }
+    Some(images)
+}
+
+fn parse_thumbnail(data: &[u8], mhni: usize) -> Option<Thumbnail> {
+    if data.get(mhni..mhni + 4)? != b"mhni" {
+        return None;
+    }
+    let name = mhni + u32_at(data, mhni + 4)? as usize;
+    let len = u32_at(data, name + 0x18)? as usize;
+    let raw = data.get(name + 0x24..name + 0x24 + len)?;
+    let file = match data.get(name + 0x1c)? {
+        2 => String::from_utf16_lossy(
+            &raw.chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>(),
+        ),
+        _ => String::from_utf8_lossy(raw).to_string(),
+    };
+
+    Some(Thumbnail {
+        format_id: u32_at(data, mhni + 16)?,
+        // a path relative to the Artwork folder, with colons for slashes
+        file: file.trim_start_matches(':').replace(':', "/"),
+        offset: u32_at(data, mhni + 20)?,
+        size: u32_at(data, mhni + 24)?,
+        vertical_padding: u16_at(data, mhni + 28)? as i16,
+        horizontal_padding: u16_at(data, mhni + 30)? as i16,
+        height: u16_at(data, mhni + 32)?,
+        width: u16_at(data, mhni + 34)?,
+    })
+}
+
+fn parse_formats(data: &[u8], pos: usize) -> Option<BTreeMap<u32, u32>> {
+    if data.get(pos..pos + 4)? != b"mhlf" {
+        return None;
+    }
+    let mut formats = BTreeMap::new();
+    let mut mhif = pos + u32_at(data, pos + 4)? as usize;
+    for _ in 0..u32_at(data, pos + 8)? {
+        formats.insert(u32_at(data, mhif + 16)?, u32_at(data, mhif + 20)?);
+        mhif += u32_at(data, mhif + 8)? as usize;
+    }
+    Some(formats)
+}
+
+fn image_bytes(image: &ImageItem) -> Vec<u8> {
+    let children: Vec<u8> = image.thumbnails.iter().flat_map(thumbnail_bytes).collect();
+    let mut mhii = header(b"mhii", MHII_LEN, MHII_LEN + children.len());
+    put_u32(&mut mhii, 12, image.thumbnails.len() as u32);
+    put_u32(&mut mhii, 16, image.id);
+    mhii[20..28].copy_from_slice(&image.song_dbid.to_le_bytes());
+    put_u32(&mut mhii, 48, image.source_size);
+    [mhii, children].concat()
+}
+
+fn thumbnail_bytes(thumbnail: &Thumbnail) -> Vec<u8> {
+    let name: Vec<u8> = [":", &thumbnail.file.replace('/', ":")]
+        .concat()
+        .encode_utf16()
+        .flat_map(|c| c.to_le_bytes())
+        .collect();
+    let padding = (4 - name.len() % 4) % 4;
+    let mut file = header(b"mhod", MHOD_LEN, 0x24 + name.len() + padding);
+    file[12] = 3;
+    file[15] = padding as u8;
+    file.resize(0x24, 0);
+    put_u32(&mut file, 0x18, name.len() as u32);
+    // UTF-16
+    file[0x1c] = 2;
+    file.extend(name);
+    file.resize(file.len() + padding, 0);
+
+    let mut mhni = header(b"mhni", MHNI_LEN, MHNI_LEN + file.len());
+    put_u32(&mut mhni, 12, 1);
+    put_u32(&mut mhni, 16, thumbnail.format_id);
+    put_u32(&mut mhni, 20, thumbnail.offset);
+    put_u32(&mut mhni, 24, thumbnail.size);
+    mhni[28..30].copy_from_slice(&thumbnail.vertical_padding.to_le_bytes());
+    mhni[30..32].copy_from_slice(&thumbnail.horizontal_padding.to_le_bytes());
+    mhni[32..34].copy_from_slice(&thumbnail.height.to_le_bytes());
+    mhni[34..36].copy_from_slice(&thumbnail.width.to_le_bytes());
+    put_u32(&mut mhni, 40, thumbnail.size);
+
+    let mut container = header(b"mhod", MHOD_LEN, MHOD_LEN + mhni.len() + file.len());
+    container[12] = 2;
+    [container, mhni, file].concat()
+}
+
+fn section(index: u16, list: &[u8]) -> Vec<u8> {
+    let mut mhsd = header(b"mhsd", MHSD_LEN, MHSD_LEN + list.len());
+    mhsd[12..14].copy_from_slice(&index.to_le_bytes());
+    [mhsd, list.to_vec()].concat()
+}
+
+// the lists have a count where the other chunks have their total length
+fn list_header(tag: &[u8; 4], count: usize) -> Vec<u8> {
+    header(tag, LIST_LEN, count)
+}
+
+fn header(tag: &[u8; 4], len: usize, total: usize) -> Vec<u8> {
+    let mut header = vec![0; len];
+    header[0..4].copy_from_slice(tag);
+    put_u32(&mut header, 4, len as u32);
+    put_u32(&mut header, 8, total as u32);
+    header
+}
+
+fn put_u32(data: &mut [u8], at: usize, value: u32) {
+    data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thumbnail(format_id: u32, side: u16, offset: u32) -> Thumbnail {
+        Thumbnail {
+            format_id,
+            file: format!("F{}_1.ithmb", format_id),
+            offset,
+            size: side as u32 * side as u32 * 2,
+            width: side,
+            height: side,
+            vertical_padding: 0,
+            horizontal_padding: 0,
+        }
+    }
+
+    fn sample() -> ArtworkDb {
+        let mut db = ArtworkDb::default();
+        db.add(
+            0x1122334455667788,
+            4321,
+            vec![thumbnail(1055, 128, 0), thumbnail(1060, 320, 0)],
+        );
+        db.add(7, 10, vec![thumbnail(1055, 128, 32768)]);
+        db
+    }
+
+    #[test]
+    fn round_trip() {
+        let db = sample();
+        let bytes = db.to_bytes();
+        assert_eq!(&bytes[0..4], b"mhfd");
+        assert_eq!(u32_at(&bytes, 8), Some(bytes.len() as u32));
+        assert_eq!(ArtworkDb::parse(&bytes), Some(db));
+    }
+
+    #[test]
+    fn formats_are_registered() {
+        let db = sample();
+        assert_eq!(
+            db.formats.into_iter().collect::<Vec<_>>(),
+            vec![(1055, 32768), (1060, 204800)]
+        );
+    }
+
+    #[test]
+    fn remove_and_replace() {
+        let mut db = sample();
+        assert!(db.remove(7));
+        assert!(!db.remove(7));
+        assert!(db.image(7).is_none());
+
+        db.add(0x1122334455667788, 1, vec![thumbnail(1061, 56, 0)]);
+        assert_eq!(db.images.len(), 1);
+        // ids are never handed out twice
+        assert_eq!(db.images[0].id, FIRST_IMAGE_ID + 2);
+        assert_eq!(
+            db.image(0x1122334455667788).unwrap().thumbnails[0].format_id,
+            1061
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = sample().to_bytes();
+        assert!(ArtworkDb::parse(&bytes[..bytes.len() - 10]).is_none());
+        assert!(ArtworkDb::parse(b"mhbd").is_none());
+        assert!(ArtworkDb::parse(&[]).is_none());
+    }
+
+    #[test]
+    fn utf8_file_names() {
+        let mut bytes = sample().to_bytes();
+        // the first image's first file name, rewritten as UTF-8 the way older databases have it
+        let name = bytes
+            .windows(4)
+            .position(|w| w == b"mhni")
+            .map(|p| p + MHNI_LEN)
+            .unwrap();
+        let utf8 = b":F1055_1.ithmb\0\0\0\0\0\0\0\0\0\0\0\0\0\0";
+        bytes[name + 0x1c] = 1;
+        put_u32(&mut bytes, name + 0x18, 14);
+        bytes[name + 0x24..name + 0x24 + utf8.len()].copy_from_slice(utf8);
+        let db = ArtworkDb::parse(&bytes).unwrap();
+        assert_eq!(db.images[0].thumbnails[0].file, "F1055_1.ithmb");
+    }
+}
@@ -1,13 +1,71 @@
 use itunesdb::xobjects::XTrackItem;
 use serde::Deserialize;
+use std::io::Read;
+use std::path::Path;
 use std::process::Stdio;
+use symphonia::core::codecs::{CODEC_TYPE_AAC, CODEC_TYPE_ALAC, CODEC_TYPE_MP3, CODEC_TYPE_NULL};
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
 use tokio::io::{AsyncReadExt, BufReader};
 use tokio::process::Command;
 
-#[derive(Debug, Deserialize, PartialEq)]
+/// Every codec the iPod plays. Anything else is rejected while probing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Mp3 { vbr: bool },
+    AacLc,
+    HeAac,
+    Alac,
+    Aiff,
+    Wav,
+    Audible,
+}
+
+impl Codec {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Codec::Mp3 { .. } => "mp3",
+            Codec::AacLc | Codec::HeAac | Codec::Alac => "m4a",
+            Codec::Aiff => "aif",
+            Codec::Wav => "wav",
+            Codec::Audible => "aa",
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Codec::Mp3 { .. } => "MPEG audio file",
+            Codec::AacLc | Codec::HeAac => "AAC audio file",
+            Codec::Alac => "Apple Lossless audio file",
+            Codec::Aiff => "AIFF audio file",
+            Codec::Wav => "WAV audio file",
+            Codec::Audible => "Audible file",
+        }
+    }
+
+    // the upper-cased extension padded with spaces, same as iTunes does it
+    fn filetype(&self) -> u32 {
+        // except for AIFF, which iTunes spells out
+        if *self == Codec::Aiff {
+            return u32::from_be_bytes(*b"AIFF");
+        }
+        let mut bytes = [b' '; 4];
+        for (i, c) in self.extension().bytes().take(4).enumerate() {
+            bytes[i] = c.to_ascii_uppercase();
+        }
+        u32::from_be_bytes(bytes)
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct AudioInfo {
-    streams: Vec<AudioStream>,
-    format: AudioFormat,
+    codec: Codec,
+    sample_rate: u64,
+    duration: f64,
+    bit_rate: u64,
+    channels: u8,
 }
 
 pub struct FormattedAudio {
@@ -17,89 +75,245 @@ pub struct FormattedAudio {
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
-pub struct AudioStream {
+struct FfprobeInfo {
+    streams: Vec<FfprobeStream>,
+    format: FfprobeFormat,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct FfprobeStream {
     codec_name: String,
+    profile: Option<String>,
     sample_rate: Option<String>,
     channels: Option<u8>,
     sample_fmt: Option<String>,
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
-pub struct AudioFormat {
+struct FfprobeFormat {
+    format_name: String,
     duration: String,
     size: String,
     bit_rate: String,
 }
 
+#[derive(Debug, PartialEq)]
+enum Container {
+    Mpeg,
+    Mp4,
+    Audible,
+    Aiff,
+    Wav,
+    Unknown,
+}
+
 impl AudioInfo {
     pub fn get_nice_object(&self) -> FormattedAudio {
         FormattedAudio {
-            bit_rate: self.format.bit_rate.parse().unwrap(),
-            duration: self.format.duration.parse().unwrap(),
-            sample_rate: self
-                .get_non_image_stream()
-                .sample_rate
-                .as_ref()
-                .unwrap()
-                .parse()
-                .unwrap(),
+            bit_rate: self.bit_rate,
+            duration: self.duration,
+            sample_rate: self.sample_rate,
         }
     }
 
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
     pub fn get_audio_extension(&self) -> &str {
-        match self.get_non_image_stream().codec_name.as_str() {
-            "mp3" => "mp3",
-            "alac" | "aac" => "m4a",
-            _ => "wav",
-        }
+        self.codec.extension()
     }
 
-    fn get_non_image_stream(&self) -> &AudioStream {
-        self.streams
-            .iter()
-            .find(|i| i.codec_name != "mjpeg")
-            .unwrap()
+    pub fn modify_xtrack(&self, track: &mut XTrackItem) {
+        // type1 marks VBR MP3s, type2 marks any MP3
+        let (type1, type2) = match self.codec {
+            Codec::Mp3 { vbr } => (vbr as u8, 1),
+            _ => (0, 0),
+        };
+        track.data.type1 = type1;
+        track.data.type2 = type2;
+        track.data.filetype = self.codec.filetype();
+        // 16.16 fixed point
+        track.data.sample_rate = (self.sample_rate * 0x10000) as u32;
+
+        track.update_arg(6, self.codec.kind().to_string());
     }
+}
 
-    fn get_audio_codec(&self) -> String {
-        match self.get_non_image_stream().codec_name.as_str() {
-            "mp3" => "MPEG audio file",
-            "aac" => "AAC audio file",
-            "alac" => "Apple Lossless audio file",
-            _ => "WAV audio file",
-        }
-        .to_string()
+fn read_head(path: &Path, len: usize) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(len);
+    if let Ok(file) = std::fs::File::open(path) {
+        let _ = file.take(len as u64).read_to_end(&mut buf);
     }
+    buf
+}
 
-    pub fn modify_xtrack(&self, track: &mut XTrackItem) {
-        track.data.type1 = 0;
-        track.data.type2 = if self.get_non_image_stream().codec_name == "mp3" {
-            1
-        } else {
-            0
+fn sniff_container(path: &Path) -> Container {
+    let head = read_head(path, 12);
+    if head.len() < 12 {
+        return Container::Unknown;
+    }
+    match (&head[0..4], &head[4..8], &head[8..12]) {
+        (b"RIFF", _, b"WAVE") => Container::Wav,
+        (b"FORM", _, b"AIFF") | (b"FORM", _, b"AIFC") => Container::Aiff,
+        (_, b"ftyp", b"aax ") => Container::Audible,
+        (_, b"ftyp", _) => Container::Mp4,
+        (_, [0x57, 0x90, 0x75, 0x36], _) => Container::Audible,
+        (b"ID3\x03", _, _) | (b"ID3\x04", _, _) | (b"ID3\x02", _, _) => Container::Mpeg,
+        ([0xFF, b, ..], _, _) if b & 0xE0 == 0xE0 => Container::Mpeg,
+        _ => Container::Unknown,
+    }
+}
+
+/// The start of the first MPEG frame, right after the ID3 tag if there is one.
+pub fn first_mp3_frame(path: &Path) -> Option<Vec<u8>> {
+    let head = read_head(path, 10);
+    let mut skip = 0usize;
+    if head.len() == 10 && &head[0..3] == b"ID3" {
+        skip = 10
+            + head[6..10]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 7) | (*b as usize & 0x7F));
+    }
+    let data = read_head(path, skip + 4096);
+    let frame = data.get(skip..)?;
+    let start = frame
+        .windows(2)
+        .position(|w| w[0] == 0xFF && w[1] & 0xE0 == 0xE0)?;
+    Some(frame[start..].to_vec())
+}
+
+// Xing and VBRI headers sit in the first frame, "Info" is the same header written for CBR
+fn is_vbr_mp3(path: &Path) -> bool {
+    let frame = match first_mp3_frame(path) {
+        Some(f) => f,
+        None => return false,
+    };
+    frame[..frame.len().min(200)]
+        .windows(4)
+        .any(|w| w == b"Xing" || w == b"VBRI")
+}
+
+fn aac_codec(extra_data: Option<&[u8]>) -> Codec {
+    // audioObjectType: 5 is SBR (HE-AAC), 29 is PS (HE-AACv2)
+    match extra_data.and_then(|d| d.first()).map(|b| b >> 3) {
+        Some(5) | Some(29) => Codec::HeAac,
+        _ => Codec::AacLc,
+    }
+}
+
+impl TryFrom<(FfprobeInfo, &Path)> for AudioInfo {
+    type Error = ();
+
+    fn try_from((value, path): (FfprobeInfo, &Path)) -> Result<Self, Self::Error> {
+        let stream = value
+            .streams
+            .iter()
+            .find(|i| i.codec_name != "mjpeg" && i.codec_name != "png")
+            .ok_or(())?;
+        let format = value.format.format_name.as_str();
+        let he_aac = stream
+            .profile
+            .as_deref()
+            .is_some_and(|p| p.starts_with("HE"));
+        let codec = match stream.codec_name.as_str() {
+            _ if format == "aa" => Codec::Audible,
+            "mp3" => Codec::Mp3 {
+                vbr: is_vbr_mp3(path),
+            },
+            "aac" if he_aac => Codec::HeAac,
+            "aac" => Codec::AacLc,
+            "alac" => Codec::Alac,
+            c if c.starts_with("pcm") && format == "aiff" => Codec::Aiff,
+            c if c.starts_with("pcm") && format == "wav" => Codec::Wav,
+            _ => return Err(()),
         };
+        Ok(AudioInfo {
+            codec,
+            sample_rate: stream
+                .sample_rate
+                .as_ref()
+                .and_then(|s| s.parse().ok())
+                .ok_or(())?,
+            duration: value.format.duration.parse().map_err(|_e| ())?,
+            bit_rate: value.format.bit_rate.parse().map_err(|_e| ())?,
+            channels: stream.channels.unwrap_or(2),
+        })
+    }
+}
 
-        let bytes = match self.get_non_image_stream().codec_name.as_str() {
-            "mp3" => "MP3",
-            "aac" => "M4A",
-            "alac" => "M4A ",
-            _ => "WAV",
-        }
-        .as_bytes();
+fn probe_native(p: &str) -> Option<AudioInfo> {
+    let path = Path::new(p);
+    let container = sniff_container(path);
+    let file = std::fs::File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            MediaSourceStream::new(Box::new(file), Default::default()),
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+
+    let track = probed
+        .format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+    let params = &track.codec_params;
+
+    let mut sample_rate = params.sample_rate? as u64;
+    let channels = params.channels.map(|c| c.count() as u8).unwrap_or(2);
+    let time = params.time_base?.calc_time(params.n_frames?);
+    let duration = time.seconds as f64 + time.frac;
+    if duration <= 0.0 {
+        return None;
+    }
 
-        let file_type = u32::from_be_bytes(if bytes.len() == 4 {
-            [bytes[0], bytes[1], bytes[2], bytes[3]]
-        } else {
-            [bytes[0], bytes[1], bytes[2], 0u8]
-        });
+    let is_pcm = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .is_some_and(|d| d.short_name.starts_with("pcm"));
 
-        track.data.filetype = file_type;
+    let codec = match (params.codec, &container) {
+        (_, Container::Audible) => Codec::Audible,
+        (CODEC_TYPE_MP3, _) => Codec::Mp3 {
+            vbr: is_vbr_mp3(path),
+        },
+        (CODEC_TYPE_AAC, _) => aac_codec(params.extra_data.as_deref()),
+        (CODEC_TYPE_ALAC, _) => Codec::Alac,
+        (_, Container::Aiff) if is_pcm => Codec::Aiff,
+        (_, Container::Wav) if is_pcm => Codec::Wav,
+        _ => return None,
+    };
 
-        track.update_arg(6, self.get_audio_codec());
+    // the container often carries the core rate, SBR doubles it on playback
+    if codec == Codec::HeAac && sample_rate <= 24000 {
+        sample_rate *= 2;
     }
+
+    // uncompressed audio has an exact bitrate, everything else is averaged over the file
+    let bit_rate = match params.bits_per_sample {
+        Some(bits) if is_pcm => sample_rate * channels as u64 * bits as u64,
+        _ => (size as f64 * 8.0 / duration) as u64,
+    };
+
+    Some(AudioInfo {
+        codec,
+        sample_rate,
+        duration,
+        bit_rate,
+        channels,
+    })
 }
 
-pub async fn from_path(p: &str) -> Option<AudioInfo> {
+async fn probe_ffprobe(p: &str) -> Option<AudioInfo> {
     let mut command = Command::new("ffprobe");
     command.arg("-i");
     command.arg(p);
@@ -108,21 +322,32 @@ pub async fn from_path(p: &str) -> Option<AudioInfo> {
     command.arg("-v");
     command.arg("quiet");
     command.arg("-show_entries");
-    command.arg("format=duration,size,bit_rate:stream=codec_name,width,height,sample_rate,channels,sample_fmt");
+    command.arg("format=format_name,duration,size,bit_rate:stream=codec_name,profile,width,height,sample_rate,channels,sample_fmt");
     command.stdout(Stdio::piped());
     command.stderr(Stdio::null());
 
-    let mut child = command.spawn().unwrap();
+    let mut child = command.spawn().ok()?;
 
     let mut vec = Vec::new();
-    let stdout = child.stdout.take().unwrap();
+    let stdout = child.stdout.take()?;
     let size = BufReader::new(stdout)
         .read_to_end(&mut vec)
         .await
         .unwrap_or(0);
+    let _ = child.wait().await;
     if size == 0 {
         return None;
     }
 
-    Some(serde_json::from_str(String::from_utf8_lossy(vec.as_slice()).as_ref()).unwrap())
+    let info: FfprobeInfo = serde_json::from_slice(&vec).ok()?;
+    (info, Path::new(p)).try_into().ok()
+}
+
+/// Probes the file natively, ffprobe is only asked when it's installed and the native probe failed.
+/// Returns None for codecs the iPod can't play.
+pub async fn from_path(p: &str) -> Option<AudioInfo> {
+    match probe_native(p) {
+        Some(info) => Some(info),
+        None => probe_ffprobe(p).await,
+    }
 }
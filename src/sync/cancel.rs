@@ -0,0 +1,18 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// The sync service is busy with the operation being cancelled and can't take a request
+// for it, so the loading screen raises this flag and the operation checks it between files.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Called before every operation, so an Esc pressed after the last one finished doesn't carry over.
+pub fn reset() {
+    REQUESTED.store(false, Ordering::Relaxed);
+}
@@ -0,0 +1,174 @@
+use crate::config::load_config;
+use crate::dlp::{Chapter, MediaInfo};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::LazyLock;
+use tokio::process::Command;
+
+/// The chapters of a download, from the list the site has or else from the timestamps in the
+/// description. Empty when there is nothing to split.
+pub fn chapters(info: &MediaInfo) -> Vec<Chapter> {
+    let chapters = match &info.chapters {
+        Some(chapters) if chapters.len() > 1 => chapters.clone(),
+        _ => from_description(
+            info.description.as_deref().unwrap_or_default(),
+            info.duration.unwrap_or(0.0),
+        ),
+    };
+    if chapters.len() > 1 {
+        chapters
+    } else {
+        Vec::new()
+    }
+}
+
+static TIMESTAMP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:(\d{1,2}):)?(\d{1,2}):(\d{2})").unwrap());
+static NUMBERING: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\d+[.)]\s+").unwrap());
+
+// "00:00 Intro", "1. Intro (0:00)", "[1:02:03] - Outro"
+fn from_description(description: &str, duration: f64) -> Vec<Chapter> {
+    let mut starts: Vec<(f64, String)> = Vec::new();
+    for line in description.lines() {
+        let m = match TIMESTAMP.captures(line) {
+            Some(m) => m,
+            None => continue,
+        };
+        let number = |i| m.get(i).map_or(0.0, |n| n.as_str().parse().unwrap_or(0.0));
+        let start = number(1) * 3600.0 + number(2) * 60.0 + number(3);
+        // times going backwards are someone quoting a moment, not the tracklist
+        if starts.last().is_some_and(|(s, _)| *s >= start) {
+            continue;
+        }
+
+        let whole = m.get(0).unwrap();
+        let title = [&line[..whole.start()], &line[whole.end()..]].concat();
+        let title = title.trim_matches(|c: char| c.is_whitespace() || "-–—:|[]()".contains(c));
+        starts.push((start, NUMBERING.replace(title, "").to_string()));
+    }
+
+    // a tracklist starts at the beginning of the upload
+    if starts.first().is_none_or(|(s, _)| *s > 0.0) {
+        return Vec::new();
+    }
+
+    let ends: Vec<f64> = starts
+        .iter()
+        .skip(1)
+        .map(|(s, _)| *s)
+        .chain([duration])
+        .collect();
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(|((start, title), end)| Chapter {
+            start_time: start,
+            end_time: end,
+            title: Some(title).filter(|t| !t.is_empty()),
+        })
+        .collect()
+}
+
+/// The album the chapters are grouped under, and the title of their playlist.
+pub fn album(info: &MediaInfo) -> String {
+    info.album
+        .clone()
+        .or(info.title.clone())
+        .unwrap_or(info.id.clone())
+}
+
+/// Where the chapter with this number (from 1) of `path` goes, next to it.
+pub fn chapter_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-{:02}.{}", stem, number, extension))
+}
+
+/// Cuts `path` into one file per chapter without re-encoding, see `chapter_path`.
+/// Returns whether every chapter was cut.
+pub async fn split(path: &Path, chapters: &[Chapter]) -> bool {
+    let ffmpeg = load_config().get_transcode().ffmpeg_path.clone();
+
+    for (i, chapter) in chapters.iter().enumerate() {
+        let dest = chapter_path(path, i + 1);
+        let mut command = Command::new(&ffmpeg);
+        command.args(["-nostdin", "-y", "-loglevel", "error"]);
+        // seeking the input jumps there instead of decoding everything before the chapter
+        command.args(["-ss", &chapter.start_time.to_string()]);
+        // the last chapter of a description without a known duration runs to the end
+        if chapter.end_time > chapter.start_time {
+            command.args(["-t", &(chapter.end_time - chapter.start_time).to_string()]);
+        }
+        command.arg("-i");
+        command.arg(path);
+        command.args(["-map", "0:a:0", "-c", "copy", "-map_metadata", "-1"]);
+        command.arg(&dest);
+        command.stdout(Stdio::null());
+        command.stderr(Stdio::null());
+        command.kill_on_drop(true);
+
+        let cut = command.status().await.is_ok_and(|s| s.success());
+        if !cut || !dest.exists() {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn starts(chapters: &[Chapter]) -> Vec<(f64, f64, Option<&str>)> {
+        chapters
+            .iter()
+            .map(|c| (c.start_time, c.end_time, c.title.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn description_tracklist() {
+        let description = "Tracklist:\n00:00 Intro\n2. Second (3:15)\n[1:02:03] - Outro\n";
+        assert_eq!(
+            starts(&from_description(description, 4000.0)),
+            vec![
+                (0.0, 195.0, Some("Intro")),
+                (195.0, 3723.0, Some("Second")),
+                (3723.0, 4000.0, Some("Outro")),
+            ]
+        );
+    }
+
+    #[test]
+    fn description_times_going_backwards() {
+        let description = "0:00 One\n4:00 Two\nthe solo at 2:30 is the best part\n6:00 Three";
+        assert_eq!(
+            starts(&from_description(description, 500.0)),
+            vec![
+                (0.0, 240.0, Some("One")),
+                (240.0, 360.0, Some("Two")),
+                (360.0, 500.0, Some("Three")),
+            ]
+        );
+        // the same time twice isn't a new chapter either
+        let chapters = from_description("0:00 One\n0:00 Again\n1:00 Two", 120.0);
+        assert_eq!(chapters.len(), 2);
+    }
+
+    #[test]
+    fn description_without_tracklist() {
+        // a moment worth seeing isn't a tracklist, it doesn't start at 0:00
+        assert!(from_description("skip to 1:30 for the drop", 300.0).is_empty());
+        assert!(from_description("no times here", 300.0).is_empty());
+    }
+
+    #[test]
+    fn description_untitled_chapter() {
+        let chapters = from_description("0:00\n1:00 - B", 0.0);
+        assert_eq!(
+            starts(&chapters),
+            vec![(0.0, 60.0, None), (60.0, 0.0, Some("B"))]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use crate::config::{CoverArtConfiguration, CoverFit};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, GrayImage, Rgba, RgbaImage};
+
+// darker than this counts as a black bar
+const BAR_LUMA: u8 = 24;
+// the busiest window is searched on a copy this high
+const SEARCH_HEIGHT: u32 = 64;
+
+/// Scales the cover to exactly `width` x `height` the way the configuration asks for.
+pub fn fit(
+    cover: &DynamicImage,
+    width: u32,
+    height: u32,
+    config: &CoverArtConfiguration,
+) -> DynamicImage {
+    match config.get_fit() {
+        CoverFit::Crop => {
+            let (x, y, w, h) = centre_window(cover.width(), cover.height(), width, height);
+            cover
+                .crop_imm(x, y, w, h)
+                .resize_exact(width, height, FilterType::Lanczos3)
+        }
+        CoverFit::Letterbox => letterbox(cover, width, height, config.get_background()),
+        CoverFit::Smart => {
+            let (bx, by, bw, bh) = trim_bars(cover);
+            let trimmed = cover.crop_imm(bx, by, bw, bh);
+            let (x, y, w, h) = busiest_window(&trimmed, width, height);
+            trimmed
+                .crop_imm(x, y, w, h)
+                .resize_exact(width, height, FilterType::Lanczos3)
+        }
+    }
+}
+
+// the largest window of the target's aspect ratio, centred
+fn centre_window(src_w: u32, src_h: u32, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let (w, h) = window_size(src_w, src_h, width, height);
+    ((src_w - w) / 2, (src_h - h) / 2, w, h)
+}
+
+fn window_size(src_w: u32, src_h: u32, width: u32, height: u32) -> (u32, u32) {
+    if src_w as u64 * height as u64 > src_h as u64 * width as u64 {
+        (
+            (src_h as u64 * width as u64 / height as u64).max(1) as u32,
+            src_h,
+        )
+    } else {
+        (
+            src_w,
+            (src_w as u64 * height as u64 / width as u64).max(1) as u32,
+        )
+    }
+}
+
+fn letterbox(cover: &DynamicImage, width: u32, height: u32, background: [u8; 3]) -> DynamicImage {
+    let scaled = cover.resize(width, height, FilterType::Lanczos3);
+    let [r, g, b] = background;
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba([r, g, b, 255]));
+    let x = (width - scaled.width()) / 2;
+    let y = (height - scaled.height()) / 2;
+    image::imageops::overlay(&mut canvas, &scaled.to_rgba8(), x as i64, y as i64);
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// The part of the picture inside black bars, such as a 4:3 video in a 16:9 thumbnail.
+fn trim_bars(cover: &DynamicImage) -> (u32, u32, u32, u32) {
+    let luma = cover.to_luma8();
+    let (w, h) = luma.dimensions();
+    let dark_col = |x: u32| (0..h).all(|y| luma.get_pixel(x, y).0[0] < BAR_LUMA);
+    let dark_row = |y: u32| (0..w).all(|x| luma.get_pixel(x, y).0[0] < BAR_LUMA);
+
+    let left = (0..w).find(|x| !dark_col(*x)).unwrap_or(0);
+    let right = (left..w).rev().find(|x| !dark_col(*x)).unwrap_or(w - 1) + 1;
+    let top = (0..h).find(|y| !dark_row(*y)).unwrap_or(0);
+    let bottom = (top..h).rev().find(|y| !dark_row(*y)).unwrap_or(h - 1) + 1;
+
+    // an almost black picture is left as it is
+    if right - left < w / 4 || bottom - top < h / 4 {
+        return (0, 0, w, h);
+    }
+    (left, top, right - left, bottom - top)
+}
+
+/// Slides a window of the target's aspect ratio along the long side and keeps the one with the
+/// most detail, which on a video thumbnail is usually the face or the title rather than the background.
+fn busiest_window(cover: &DynamicImage, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let (src_w, src_h) = cover.dimensions();
+    let (w, h) = window_size(src_w, src_h, width, height);
+    if w == src_w && h == src_h {
+        return (0, 0, w, h);
+    }
+
+    let scale = SEARCH_HEIGHT as f32 / src_h.min(src_w) as f32;
+    let small = cover
+        .resize_exact(
+            ((src_w as f32 * scale) as u32).max(1),
+            ((src_h as f32 * scale) as u32).max(1),
+            FilterType::Triangle,
+        )
+        .to_luma8();
+    let detail = detail_profile(&small, src_w >= src_h);
+
+    // window length and free play along the long side, on the small copy
+    let long = detail.len();
+    let window = ((if src_w >= src_h { w } else { h }) as f32 * scale) as usize;
+    let window = window.clamp(1, long);
+    let mut best = (0, 0.0);
+    let mut sum: f32 = detail[..window].iter().sum();
+    for start in 0..=long - window {
+        if start > 0 {
+            sum += detail[start + window - 1] - detail[start - 1];
+        }
+        if sum > best.1 {
+            best = (start, sum);
+        }
+    }
+
+    let offset = (best.0 as f32 / scale) as u32;
+    if src_w >= src_h {
+        (offset.min(src_w - w), 0, w, h)
+    } else {
+        (0, offset.min(src_h - h), w, h)
+    }
+}
+
+// summed gradient strength of every column (or row, when the picture is tall)
+fn detail_profile(luma: &GrayImage, by_column: bool) -> Vec<f32> {
+    let (w, h) = luma.dimensions();
+    let at = |x: u32, y: u32| luma.get_pixel(x, y).0[0] as f32;
+    let gradient = |x: u32, y: u32| {
+        let dx = at((x + 1).min(w - 1), y) - at(x.saturating_sub(1), y);
+        let dy = at(x, (y + 1).min(h - 1)) - at(x, y.saturating_sub(1));
+        dx.abs() + dy.abs()
+    };
+
+    if by_column {
+        (0..w)
+            .map(|x| (0..h).map(|y| gradient(x, y)).sum())
+            .collect()
+    } else {
+        (0..h)
+            .map(|y| (0..w).map(|x| gradient(x, y)).sum())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_of_odd_sizes() {
+        assert_eq!(window_size(101, 100, 1, 1), (100, 100));
+        assert_eq!(window_size(99, 100, 1, 1), (99, 99));
+        assert_eq!(window_size(3, 7, 1, 1), (3, 3));
+        assert_eq!(window_size(7, 3, 2, 1), (6, 3));
+        assert_eq!(window_size(5, 5, 2, 1), (5, 2));
+        assert_eq!(centre_window(101, 100, 1, 1), (0, 0, 100, 100));
+        assert_eq!(centre_window(3, 8, 1, 1), (0, 2, 3, 3));
+    }
+
+    #[test]
+    fn window_never_leaves_the_picture() {
+        for (width, height) in [(1, 1), (2, 1), (1, 2), (100, 200)] {
+            for src_w in 1..20 {
+                for src_h in 1..20 {
+                    let (x, y, w, h) = centre_window(src_w, src_h, width, height);
+                    assert!(w >= 1 && h >= 1);
+                    assert!(x + w <= src_w && y + h <= src_h);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn window_of_a_sliver() {
+        assert_eq!(window_size(1, 1000, 1000, 1), (1, 1));
+        assert_eq!(window_size(1000, 1, 1, 1000), (1, 1));
+    }
+}
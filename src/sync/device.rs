@@ -0,0 +1,216 @@
+use crate::sync::metadata;
+use itunesdb::serializer;
+use itunesdb::xobjects::{XDatabase, XSomeList};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// Every write to the iPod goes through this module, so a dry run can record it instead.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+static FILE_CHANGES: Mutex<Vec<PlannedChange>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    CopyFile,
+    WriteFile,
+    DeleteFile,
+    TrackAdded,
+    TrackModified,
+    TrackRemoved,
+    PlaylistAdded,
+    PlaylistModified,
+    PlaylistRemoved,
+}
+
+impl From<ChangeKind> for String {
+    fn from(value: ChangeKind) -> Self {
+        match value {
+            ChangeKind::CopyFile => "COPY",
+            ChangeKind::WriteFile => "WRITE",
+            ChangeKind::DeleteFile => "DELETE",
+            ChangeKind::TrackAdded => "+TRACK",
+            ChangeKind::TrackModified => "~TRACK",
+            ChangeKind::TrackRemoved => "-TRACK",
+            ChangeKind::PlaylistAdded => "+PLAYLIST",
+            ChangeKind::PlaylistModified => "~PLAYLIST",
+            ChangeKind::PlaylistRemoved => "-PLAYLIST",
+        }
+        .to_string()
+    }
+}
+
+pub struct PlannedChange {
+    pub kind: ChangeKind,
+    pub item: String,
+    pub bytes: u64,
+}
+
+pub struct DryRunReport {
+    pub changes: Vec<PlannedChange>,
+    pub bytes_copied: u64,
+    pub bytes_deleted: u64,
+}
+
+pub fn enable_dry_run() {
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+fn record(kind: ChangeKind, path: &Path, bytes: u64) {
+    let item = path.to_str().unwrap_or_default().to_string();
+    let mut changes = FILE_CHANGES.lock().unwrap();
+    // the database gets rewritten after every file, only the last write matters
+    changes.retain(|c| !(c.kind == kind && c.item == item));
+    changes.push(PlannedChange { kind, item, bytes });
+}
+
+pub fn create_dir_all(path: &Path) {
+    if !is_dry_run() {
+        let _ = std::fs::create_dir_all(path);
+    }
+}
+
+pub fn copy_file(from: &Path, to: &Path) -> std::io::Result<u64> {
+    if is_dry_run() {
+        let size = std::fs::metadata(from)?.len();
+        record(ChangeKind::CopyFile, to, size);
+        return Ok(size);
+    }
+    std::fs::copy(from, to)
+}
+
+pub fn write_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if is_dry_run() {
+        record(ChangeKind::WriteFile, path, data.len() as u64);
+        return Ok(());
+    }
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(data)
+}
+
+pub fn remove_file(path: &Path) -> std::io::Result<()> {
+    if is_dry_run() {
+        let size = std::fs::metadata(path)?.len();
+        record(ChangeKind::DeleteFile, path, size);
+        return Ok(());
+    }
+    std::fs::remove_file(path)
+}
+
+/// Adds to the end of a file, creating it if there is none.
+pub fn append_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if is_dry_run() {
+        record(ChangeKind::WriteFile, path, data.len() as u64);
+        return Ok(());
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(data)
+}
+
+/// Files which would have been modified in place, such as tags written into a track.
+pub fn touch_file(path: &Path) -> bool {
+    if is_dry_run() {
+        let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        record(ChangeKind::WriteFile, path, size);
+        return false;
+    }
+    true
+}
+
+pub fn copy_database(database: &mut XDatabase) -> XDatabase {
+    itunesdb::deserializer::parse_bytes(&serializer::to_bytes(database))
+}
+
+pub struct DbSnapshot {
+    tracks: HashMap<u32, (String, String)>,
+    playlists: HashMap<u64, (String, usize)>,
+}
+
+impl DbSnapshot {
+    pub fn new(database: &mut XDatabase) -> Self {
+        let mut tracks = HashMap::new();
+        if let XSomeList::TrackList(list) = &database.find_dataset(1).child {
+            for t in list.iter() {
+                let name = [t.get_artist(), " - ".to_string(), t.get_title()].concat();
+                let fingerprint = metadata::fingerprint(t);
+                tracks.insert(t.data.unique_id, (name, fingerprint));
+            }
+        }
+        let playlists = database
+            .get_playlists()
+            .iter()
+            .map(|p| {
+                (
+                    p.data.persistent_playlist_id,
+                    (p.get_title(), p.elems.len()),
+                )
+            })
+            .collect();
+        Self { tracks, playlists }
+    }
+
+    fn diff(&self, after: &DbSnapshot) -> Vec<PlannedChange> {
+        let mut changes = Vec::new();
+        let change = |kind, item: &String| PlannedChange {
+            kind,
+            item: item.clone(),
+            bytes: 0,
+        };
+        for (id, (name, fingerprint)) in after.tracks.iter() {
+            match self.tracks.get(id) {
+                None => changes.push(change(ChangeKind::TrackAdded, name)),
+                Some((_, old)) if old != fingerprint => {
+                    changes.push(change(ChangeKind::TrackModified, name))
+                }
+                _ => {}
+            }
+        }
+        for (id, (name, _)) in self.tracks.iter() {
+            if !after.tracks.contains_key(id) {
+                changes.push(change(ChangeKind::TrackRemoved, name));
+            }
+        }
+        for (id, (title, count)) in after.playlists.iter() {
+            match self.playlists.get(id) {
+                None => changes.push(change(ChangeKind::PlaylistAdded, title)),
+                Some(old) if old != &(title.clone(), *count) => {
+                    changes.push(change(ChangeKind::PlaylistModified, title))
+                }
+                _ => {}
+            }
+        }
+        for (id, (title, _)) in self.playlists.iter() {
+            if !after.playlists.contains_key(id) {
+                changes.push(change(ChangeKind::PlaylistRemoved, title));
+            }
+        }
+        changes
+    }
+
+    pub fn report(&self, after: &DbSnapshot) -> DryRunReport {
+        let mut changes = std::mem::take(&mut *FILE_CHANGES.lock().unwrap());
+        changes.append(&mut self.diff(after));
+
+        let total = |kind| {
+            changes
+                .iter()
+                .filter(|c| c.kind == kind)
+                .map(|c| c.bytes)
+                .sum()
+        };
+
+        DryRunReport {
+            bytes_copied: total(ChangeKind::CopyFile),
+            bytes_deleted: total(ChangeKind::DeleteFile),
+            changes,
+        }
+    }
+}
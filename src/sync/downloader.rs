@@ -1,209 +1,536 @@
-use crate::config::get_temp_dl_dir;
-use crate::sync::sync_util::{AppEvent, YTPlaylist};
+use crate::config::{
+    clear_temp_files, get_temp_dl_dir, load_config, CoverArtConfiguration, LyricaConfiguration,
+    TranscodeConfiguration, TranscodeProfile,
+};
+use crate::dlp::{self, MediaInfo};
+use crate::sync::audio_file_info::AudioInfo;
+use crate::sync::gapless::GaplessInfo;
+use crate::sync::ipod_model::{self, ArtworkFormat};
+use crate::sync::ithmb::Artwork;
+use crate::sync::metadata::ImportedTags;
+use crate::sync::queue::{self, DownloadDir, DownloadQueue, QueueJob, QueueStatus};
+use crate::sync::sync_util::{AppEvent, ImportOutcome, ImportResult, ImportSummary, YTPlaylist};
 use crate::sync::{
-    audio_file_info, get_artwork_db, get_full_track_location, get_playlists, get_track_location,
-    make_cover_image, overwrite_database, track_from_soundcloud, track_from_video,
-    write_artwork_db,
+    artwork, audio_file_info, cancel, chapters, device, flush_database, gapless,
+    get_full_track_location, get_playlists, get_track_location, lyrics, metadata,
+    overwrite_database, render_covers, soundcheck, track_from_chapter, track_from_media,
+    track_from_soundcloud, track_from_video, transcode,
 };
-use crate::{dlp, util, AppState};
-use audiotags::Tag;
+use crate::util::IPodImage;
+use crate::{util, AppState};
+use futures::StreamExt;
 use itunesdb::objects::ListSortOrder;
-use itunesdb::xobjects::{XDatabase, XPlaylist, XTrackItem};
+use itunesdb::xobjects::{XDatabase, XPlaylist, XSomeList, XTrackItem};
 use ratatui::prelude::Color;
 use soundcloud::sobjects::{CloudPlaylist, CloudTrack};
-use std::os::unix::fs::MetadataExt;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
-use tokio::fs::File;
-use tokio::sync::mpsc::Sender;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{Sender, UnboundedSender};
+use tokio::task;
 use youtube_api::objects::YoutubeVideo;
 
-pub async fn download_playlist(
-    playlist: CloudPlaylist,
-    database: &mut XDatabase,
-    sender: &Sender<AppEvent>,
-    ipod_path: String,
-) {
-    if let Ok(()) =
-        dlp::download_from_soundcloud(&playlist.permalink_url, &get_temp_dl_dir(), sender.clone())
-            .await
+/// Swaps `source` for a smaller re-encode when the space saving profile is on and the track is above the threshold.
+/// Returns the bytes saved.
+async fn shrink_if_needed(
+    source: &mut PathBuf,
+    original: &Path,
+    audio_file: &mut AudioInfo,
+    profile: TranscodeProfile,
+    config: &TranscodeConfiguration,
+) -> Option<u64> {
+    if profile != TranscodeProfile::SpaceSaving || !transcode::exceeds_threshold(audio_file, config)
     {
-        let tracks = playlist.tracks;
+        return None;
+    }
+    let smaller = transcode::shrink(original, config).await?;
+    match audio_file_info::from_path(smaller.to_str().unwrap_or_default()).await {
+        Some(info) => {
+            let size = |p: &Path| std::fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+            let saved = size(source).saturating_sub(size(&smaller));
+            if source != original {
+                let _ = std::fs::remove_file(&source);
+            }
+            *source = smaller;
+            *audio_file = info;
+            Some(saved)
+        }
+        None => {
+            let _ = std::fs::remove_file(smaller);
+            None
+        }
+    }
+}
+
+// a whole track takes a while to copy, it goes to the blocking pool rather than holding up the sync service
+async fn copy_to_ipod(from: PathBuf, to: PathBuf) -> std::io::Result<u64> {
+    task::spawn_blocking(move || device::copy_file(&from, &to))
+        .await
+        .map_err(std::io::Error::other)?
+}
 
-        let p: PathBuf = Path::new(&ipod_path).into();
+/// Copies a track downloaded by yt-dlp onto the iPod, re-encoding it first if the profile asks for it.
+async fn copy_download(
+    track: &mut XTrackItem,
+    track_path: PathBuf,
+    ipod_path: &Path,
+    config: &TranscodeConfiguration,
+    summary: &mut ImportSummary,
+) -> Result<(), String> {
+    let mut source = track_path.clone();
+    let mut extension = track_path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    if let Some(mut audio_file) =
+        audio_file_info::from_path(track_path.to_str().unwrap_or_default()).await
+    {
+        if let Some(saved) = shrink_if_needed(
+            &mut source,
+            &track_path,
+            &mut audio_file,
+            summary.profile,
+            config,
+        )
+        .await
+        {
+            summary.shrunk += 1;
+            summary.bytes_saved += saved;
+            let size = std::fs::metadata(&source).map(|m| m.len()).unwrap_or(0) as u32;
+            track.data.size = size;
+            track.data.mhii_link = size;
+            track.data.bitrate = (audio_file.get_nice_object().bit_rate / 1000) as u32;
+            audio_file.modify_xtrack(track);
+            extension = audio_file.get_audio_extension().to_string();
+        }
+    }
 
-        let mut new_playlist = XPlaylist::new(rand::random(), ListSortOrder::SongTitle);
+    track.set_location(get_track_location(track.data.unique_id, &extension));
+    let dest = get_full_track_location(ipod_path.into(), track.data.unique_id, &extension);
+    let copied = copy_to_ipod(source.clone(), dest).await;
 
-        new_playlist.set_title(playlist.title);
+    if source != track_path {
+        let _ = std::fs::remove_file(source);
+    }
+    copied
+        .map(|_| ())
+        .map_err(|e| ["copying to the iPod failed: ", &e.to_string()].concat())
+}
 
-        for track in tracks {
-            if track.title.is_none() {
-                continue;
-            }
-            if let Some(mut t) = track_from_soundcloud(&track, ipod_path.clone(), sender).await {
-                if !database.if_track_in_library(t.data.dbid) {
-                    t.data.unique_id = database.get_unique_id();
-                    new_playlist.add_elem(t.data.unique_id);
-                    t.set_location(get_track_location(t.data.unique_id, "mp3"));
-                    let dest = get_full_track_location(p.clone(), t.data.unique_id, "mp3");
-                    let mut track_path = get_temp_dl_dir();
-                    track_path.push(track.id.to_string());
-                    track_path.set_extension("mp3");
-
-                    let _ = std::fs::copy(track_path.to_str().unwrap(), dest.to_str().unwrap());
-                    database.add_track(t);
-                } else if let Some(unique_id) = database.get_unique_id_by_dbid(t.data.dbid) {
-                    new_playlist.add_elem(unique_id);
-                }
-            }
+/// Adds one downloaded track, returns its id when it's on the iPod afterwards.
+async fn add_download(
+    track: Result<XTrackItem, String>,
+    item: String,
+    track_path: PathBuf,
+    config: &LyricaConfiguration,
+    summary: &mut ImportSummary,
+    database: &mut XDatabase,
+    ipod_path: &str,
+) -> Option<u32> {
+    let mut t = match track {
+        Ok(t) => t,
+        Err(reason) => {
+            summary.add(item, None, ImportOutcome::Failed(reason));
+            return None;
         }
+    };
+    if database.if_track_in_library(t.data.dbid) {
+        summary.add(item, None, ImportOutcome::Duplicate);
+        return database.get_unique_id_by_dbid(t.data.dbid);
+    }
 
-        database.add_playlist(new_playlist);
+    t.data.unique_id = database.get_unique_id();
+    match copy_download(
+        &mut t,
+        track_path,
+        Path::new(ipod_path),
+        config.get_transcode(),
+        summary,
+    )
+    .await
+    {
+        Ok(()) => {
+            let id = t.data.unique_id;
+            database.add_track(t);
+            summary.add(item, None, ImportOutcome::Imported);
+            Some(id)
+        }
+        Err(reason) => {
+            summary.add(item, None, ImportOutcome::Failed(reason));
+            None
+        }
     }
+}
 
-    let _ = sender
-        .send(AppEvent::SwitchScreen(AppState::MainScreen))
-        .await;
+async fn import_playlist(
+    playlist: CloudPlaylist,
+    dir: &DownloadDir,
+    config: &LyricaConfiguration,
+    summary: &mut ImportSummary,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: &str,
+) {
+    let mut new_playlist = XPlaylist::new(rand::random(), ListSortOrder::SongTitle);
 
-    let _ = sender
-        .send(AppEvent::ITunesParsed(get_playlists(database)))
+    new_playlist.set_title(playlist.title);
+
+    for track in playlist.tracks {
+        let item = match &track.title {
+            Some(title) => title.clone(),
+            None => continue,
+        };
+        let track_path = dir.track_path(&track.id.to_string());
+
+        let t = track_from_soundcloud(
+            &track,
+            dir,
+            ipod_path.to_string(),
+            config.get_coverart(),
+            sender,
+        )
         .await;
+        if let Some(id) =
+            add_download(t, item, track_path, config, summary, database, ipod_path).await
+        {
+            new_playlist.add_elem(id);
+        }
+    }
 
-    overwrite_database(database, &ipod_path);
-
-    crate::config::clear_temp_dl_dir();
+    database.add_playlist(new_playlist);
 }
 
-pub async fn download_track(
+async fn import_track(
     track: CloudTrack,
+    dir: &DownloadDir,
+    config: &LyricaConfiguration,
+    summary: &mut ImportSummary,
     database: &mut XDatabase,
     sender: &Sender<AppEvent>,
-    ipod_path: String,
+    ipod_path: &str,
 ) {
-    if let Ok(()) = dlp::download_track_from_soundcloud(
-        &track.permalink_url.clone().unwrap(),
-        &get_temp_dl_dir(),
-        sender.clone(),
+    let item = track.title.clone().unwrap_or_default();
+    let track_path = dir.track_path(&track.id.to_string());
+
+    let t = track_from_soundcloud(
+        &track,
+        dir,
+        ipod_path.to_string(),
+        config.get_coverart(),
+        sender,
     )
-    .await
-    {
-        let p: PathBuf = Path::new(&ipod_path).into();
-
-        if let Some(mut t) = track_from_soundcloud(&track, ipod_path.clone(), sender).await {
-            if !database.if_track_in_library(t.data.dbid) {
-                t.data.unique_id = database.get_unique_id();
-                t.set_location(get_track_location(t.data.unique_id, "mp3"));
-                let dest = get_full_track_location(p.clone(), t.data.unique_id, "mp3");
-
-                let mut track_path = get_temp_dl_dir();
-                track_path.push(track.id.to_string());
-                track_path.set_extension("mp3");
-
-                let _ = std::fs::copy(track_path.to_str().unwrap(), dest.to_str().unwrap());
+    .await;
+    add_download(t, item, track_path, config, summary, database, ipod_path).await;
+}
 
-                database.add_track(t);
-            }
-        }
+/// Cuts a single download into its chapters and imports them as an album with a playlist of its own.
+/// Returns false, having added nothing, when splitting is off or the download has no chapters.
+async fn import_chapters(
+    id: &str,
+    dir: &DownloadDir,
+    config: &LyricaConfiguration,
+    summary: &mut ImportSummary,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: &str,
+) -> bool {
+    if !config.get_downloader().split_chapters {
+        return false;
+    }
+    let info = match dlp::read_info(&dir.path, id) {
+        Some(info) => info,
+        None => return false,
+    };
+    let list = chapters::chapters(&info);
+    let track_path = dir.track_path(id);
+    // a download ffmpeg can't cut is still imported as one track
+    if list.is_empty() || !chapters::split(&track_path, &list).await {
+        return false;
     }
 
-    let _ = sender
-        .send(AppEvent::SwitchScreen(AppState::MainScreen))
-        .await;
+    let mut new_playlist = XPlaylist::new(rand::random(), ListSortOrder::SongTitle);
 
-    let _ = sender
-        .send(AppEvent::ITunesParsed(get_playlists(database)))
+    new_playlist.set_title(chapters::album(&info));
+
+    for (i, chapter) in list.iter().enumerate() {
+        let number = i + 1;
+        let item = chapter
+            .title
+            .clone()
+            .unwrap_or(format!("Chapter {}", number));
+        let chapter_path = chapters::chapter_path(&track_path, number);
+
+        let t = track_from_chapter(
+            &info,
+            &list,
+            number,
+            dir,
+            ipod_path.to_string(),
+            config.get_coverart(),
+            sender,
+        )
         .await;
+        if let Some(id) =
+            add_download(t, item, chapter_path, config, summary, database, ipod_path).await
+        {
+            new_playlist.add_elem(id);
+        }
+    }
 
-    overwrite_database(database, &ipod_path);
-
-    crate::config::clear_temp_dl_dir();
+    database.add_playlist(new_playlist);
+    true
 }
 
-pub async fn download_video(
+async fn import_video(
     video: YoutubeVideo,
+    dir: &DownloadDir,
+    config: &LyricaConfiguration,
+    summary: &mut ImportSummary,
     database: &mut XDatabase,
     sender: &Sender<AppEvent>,
-    ipod_path: String,
+    ipod_path: &str,
 ) {
-    if let Ok(()) =
-        dlp::download_track_from_youtube(&video.videoId.clone(), &get_temp_dl_dir(), sender.clone())
-            .await
+    if import_chapters(
+        &video.videoId,
+        dir,
+        config,
+        summary,
+        database,
+        sender,
+        ipod_path,
+    )
+    .await
     {
-        let p: PathBuf = Path::new(&ipod_path).into();
+        return;
+    }
 
-        if let Some(mut t) = track_from_video(&video, ipod_path.clone(), sender).await {
-            if !database.if_track_in_library(t.data.dbid) {
-                t.data.unique_id = database.get_unique_id();
-                t.set_location(get_track_location(t.data.unique_id, "mp3"));
-                let dest = get_full_track_location(p.clone(), t.data.unique_id, "mp3");
+    let track_path = dir.track_path(&video.videoId);
 
-                let mut track_path = get_temp_dl_dir();
-                track_path.push(&video.videoId);
-                track_path.set_extension("mp3");
+    let t = track_from_video(
+        &video,
+        dir,
+        ipod_path.to_string(),
+        config.get_coverart(),
+        sender,
+    )
+    .await;
+    add_download(
+        t,
+        video.title.clone(),
+        track_path,
+        config,
+        summary,
+        database,
+        ipod_path,
+    )
+    .await;
+}
 
-                let _ = std::fs::copy(track_path.to_str().unwrap(), dest.to_str().unwrap());
+async fn import_youtube_playlist(
+    playlist: YTPlaylist,
+    dir: &DownloadDir,
+    config: &LyricaConfiguration,
+    summary: &mut ImportSummary,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: &str,
+) {
+    let mut new_playlist = XPlaylist::new(rand::random(), ListSortOrder::SongTitle);
 
-                database.add_track(t);
-            }
-        }
-    }
+    new_playlist.set_title(playlist.title);
 
-    let _ = sender
-        .send(AppEvent::SwitchScreen(AppState::MainScreen))
-        .await;
+    for video in playlist.videos {
+        let track_path = dir.track_path(&video.videoId);
 
-    let _ = sender
-        .send(AppEvent::ITunesParsed(get_playlists(database)))
+        let t = track_from_video(
+            &video,
+            dir,
+            ipod_path.to_string(),
+            config.get_coverart(),
+            sender,
+        )
         .await;
+        if let Some(id) = add_download(
+            t,
+            video.title.clone(),
+            track_path,
+            config,
+            summary,
+            database,
+            ipod_path,
+        )
+        .await
+        {
+            new_playlist.add_elem(id);
+        }
+    }
 
-    overwrite_database(database, &ipod_path);
-
-    crate::config::clear_temp_dl_dir();
+    database.add_playlist(new_playlist);
 }
 
-pub async fn download_youtube_playlist(
-    playlist: YTPlaylist,
+/// Imports a URL pasted into the app, a playlist of any site becomes a playlist on the iPod.
+async fn import_media(
+    info: MediaInfo,
+    dir: &DownloadDir,
+    config: &LyricaConfiguration,
+    summary: &mut ImportSummary,
     database: &mut XDatabase,
     sender: &Sender<AppEvent>,
-    ipod_path: String,
+    ipod_path: &str,
 ) {
-    if let Ok(()) =
-        dlp::download_from_youtube(&playlist.url, &get_temp_dl_dir(), sender.clone()).await
-    {
-        let videos = playlist.videos;
-
-        let p: PathBuf = Path::new(&ipod_path).into();
+    let entries = match info.entries {
+        Some(entries) => entries,
+        None => {
+            if import_chapters(&info.id, dir, config, summary, database, sender, ipod_path).await {
+                return;
+            }
+            let item = info.title.clone().unwrap_or(info.id.clone());
+            let track_path = dir.track_path(&info.id);
+            let t = track_from_media(
+                &info,
+                dir,
+                ipod_path.to_string(),
+                config.get_coverart(),
+                sender,
+            )
+            .await;
+            add_download(t, item, track_path, config, summary, database, ipod_path).await;
+            return;
+        }
+    };
 
-        let mut new_playlist = XPlaylist::new(rand::random(), ListSortOrder::SongTitle);
+    let mut new_playlist = XPlaylist::new(rand::random(), ListSortOrder::SongTitle);
 
-        new_playlist.set_title(playlist.title);
+    new_playlist.set_title(info.title.unwrap_or(info.id));
 
-        for video in videos {
-            if let Some(mut t) = track_from_video(&video, ipod_path.clone(), sender).await {
-                if !database.if_track_in_library(t.data.dbid) {
-                    t.data.unique_id = database.get_unique_id();
-                    new_playlist.add_elem(t.data.unique_id);
-                    t.set_location(get_track_location(t.data.unique_id, "mp3"));
-                    let dest = get_full_track_location(p.clone(), t.data.unique_id, "mp3");
+    for entry in entries {
+        let item = entry.title.clone().unwrap_or(entry.id.clone());
+        let track_path = dir.track_path(&entry.id);
 
-                    let mut track_path = get_temp_dl_dir();
-                    track_path.push(&video.videoId);
-                    track_path.set_extension("mp3");
+        let t = track_from_media(
+            &entry,
+            dir,
+            ipod_path.to_string(),
+            config.get_coverart(),
+            sender,
+        )
+        .await;
+        if let Some(id) =
+            add_download(t, item, track_path, config, summary, database, ipod_path).await
+        {
+            new_playlist.add_elem(id);
+        }
+    }
 
-                    let _ = std::fs::copy(track_path.to_str().unwrap(), dest.to_str().unwrap());
+    database.add_playlist(new_playlist);
+}
 
-                    database.add_track(t);
-                } else if let Some(unique_id) = database.get_unique_id_by_dbid(t.data.dbid) {
-                    new_playlist.add_elem(unique_id);
-                }
-            }
+/// Imports what yt-dlp left in the directory of a queue entry, the queue shows how it went.
+pub async fn import_queued(
+    id: u32,
+    result: Result<(), String>,
+    queue: &mut DownloadQueue,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    requests: &UnboundedSender<AppEvent>,
+    ipod_path: String,
+) {
+    let entry = match queue.get(id) {
+        Some(entry) => entry.clone(),
+        None => return,
+    };
+    let dir = DownloadDir {
+        path: queue::download_dir(id),
+        format: entry.format,
+    };
+
+    // with --ignore-errors one bad item of a playlist fails the run, the rest is imported all the same
+    // and the items that didn't come down are reported as missing
+    if let Err(reason) = result {
+        if reason == "cancelled" || !dir.has_downloads() {
+            let _ = tokio::fs::remove_dir_all(&dir.path).await;
+            queue.set_status(id, QueueStatus::Failed(reason));
+            queue.refresh(sender, requests).await;
+            return;
         }
-
-        database.add_playlist(new_playlist);
     }
 
-    let _ = sender
-        .send(AppEvent::SwitchScreen(AppState::MainScreen))
-        .await;
+    // the next download can start while this one is imported
+    queue.set_status(id, QueueStatus::Importing);
+    queue.refresh(sender, requests).await;
+
+    let config = load_config();
+    let mut summary = ImportSummary::new(entry.profile);
+    match entry.job {
+        QueueJob::SoundCloudPlaylist(playlist) => {
+            import_playlist(
+                playlist,
+                &dir,
+                &config,
+                &mut summary,
+                database,
+                sender,
+                &ipod_path,
+            )
+            .await
+        }
+        QueueJob::SoundCloudTrack(track) => {
+            import_track(
+                track,
+                &dir,
+                &config,
+                &mut summary,
+                database,
+                sender,
+                &ipod_path,
+            )
+            .await
+        }
+        QueueJob::YouTubePlaylist(playlist) => {
+            import_youtube_playlist(
+                playlist,
+                &dir,
+                &config,
+                &mut summary,
+                database,
+                sender,
+                &ipod_path,
+            )
+            .await
+        }
+        QueueJob::YouTubeVideo(video) => {
+            import_video(
+                video,
+                &dir,
+                &config,
+                &mut summary,
+                database,
+                sender,
+                &ipod_path,
+            )
+            .await
+        }
+        QueueJob::Url(_, Some(info)) => {
+            import_media(
+                *info,
+                &dir,
+                &config,
+                &mut summary,
+                database,
+                sender,
+                &ipod_path,
+            )
+            .await
+        }
+        // downloads only start once the URL is resolved
+        QueueJob::Url(url, None) => summary.add(
+            url,
+            None,
+            ImportOutcome::Failed("the URL couldn't be resolved".to_string()),
+        ),
+    }
 
     let _ = sender
         .send(AppEvent::ITunesParsed(get_playlists(database)))
@@ -211,178 +538,441 @@ pub async fn download_youtube_playlist(
 
     overwrite_database(database, &ipod_path);
 
-    crate::config::clear_temp_dl_dir();
+    let _ = tokio::fs::remove_dir_all(&dir.path).await;
+
+    // a single track has nothing more to report than why it failed
+    let status = match summary.results.as_slice() {
+        [ImportResult {
+            outcome: ImportOutcome::Failed(reason),
+            ..
+        }] => QueueStatus::Failed(reason.clone()),
+        _ => QueueStatus::Done(summary),
+    };
+    queue.set_status(id, status);
+    queue.refresh(sender, requests).await;
 }
 
-pub async fn load_from_fs(
-    path: PathBuf,
-    database: &mut XDatabase,
-    sender: &Sender<AppEvent>,
-    ipod_path: String,
-) -> u32 {
-    let tag = Tag::new().read_from_path(&path);
+// an import stopped halfway, by a crash or a yanked cable, keeps at most this many tracks off the database
+const FLUSH_EVERY: usize = 25;
 
-    let mut id = database.get_unique_id();
+struct PreparedCover {
+    hash: u64,
+    size: usize,
+    // one thumbnail for each of the model's cover formats
+    images: Vec<IPodImage>,
+}
 
-    let audio_file = audio_file_info::from_path(path.to_str().unwrap())
-        .await
-        .unwrap();
-    let audio_info = &audio_file.get_nice_object();
+/// Everything about a file that doesn't touch the iPod, so several files can be prepared at once.
+struct PreparedTrack {
+    path: PathBuf,
+    // the file which ends up on the iPod, a temp copy if it was transcoded or got lyrics
+    source: PathBuf,
+    song_dbid: u64,
+    audio_file: AudioInfo,
+    tags: ImportedTags,
+    has_lyrics: bool,
+    // sidecar lyrics which couldn't go into the file
+    lyrics_note: Option<String>,
+    gapless: Option<GaplessInfo>,
+    soundcheck: Option<u32>,
+    size: u32,
+    cover: Option<PreparedCover>,
+    bytes_saved: Option<u64>,
+}
 
-    let song_dbid = util::hash_from_path(path.clone());
+enum Prepared {
+    // already on the iPod
+    Known(u64),
+    New(Box<PreparedTrack>),
+}
 
-    if !database.if_track_in_library(song_dbid) {
-        let mut lyrics = 0;
-        if let Ok(idmpeg) = id3::Tag::read_from_path(&path) {
-            lyrics = idmpeg.lyrics().count();
+/// Prepares a file on the blocking pool, hashing, probing, ffmpeg and the tags all block.
+async fn prepare(
+    path: PathBuf,
+    known: Arc<HashSet<u64>>,
+    profile: TranscodeProfile,
+    formats: &'static [ArtworkFormat],
+    config: Arc<LyricaConfiguration>,
+) -> Result<Prepared, String> {
+    let handle = Handle::current();
+    task::spawn_blocking(move || {
+        // a dropped import doesn't stop the task, it shouldn't start on a file after a cancel
+        if cancel::is_requested() {
+            return Err("cancelled".to_string());
         }
+        handle.block_on(prepare_file(path, known, profile, formats, config))
+    })
+    .await
+    .map_err(|_e| "crashed while reading the file".to_string())?
+}
 
-        let mut year = None;
-        let mut title = None;
-        let mut genre = None;
-        let mut artist = None;
-        let mut cover = None;
-        let mut album = None;
-
-        let mut track_number = None;
-        let mut total_tracks = None;
-        let mut disc_number = None;
-        let mut total_discs = None;
-
-        if let Ok(tag) = tag {
-            year = tag.year();
-            title = tag.title().map(|s| s.to_string());
-            genre = tag.genre().map(|s| s.to_string());
-            artist = tag.artist().map(|s| s.to_string());
-            cover = tag.album_cover().map(|a| a.data.to_vec());
-            album = tag.album_title().map(|a| a.to_string());
-
-            track_number = tag.track_number();
-            total_tracks = tag.total_tracks();
-            disc_number = tag.disc_number();
-            total_discs = tag.total_discs();
-        }
+async fn prepare_file(
+    path: PathBuf,
+    known: Arc<HashSet<u64>>,
+    profile: TranscodeProfile,
+    formats: &'static [ArtworkFormat],
+    config: Arc<LyricaConfiguration>,
+) -> Result<Prepared, String> {
+    let song_dbid = util::hash_from_path(path.clone()).ok_or("the file can't be read")?;
+    if known.contains(&song_dbid) {
+        return Ok(Prepared::Known(song_dbid));
+    }
 
-        let size_in_bytes = File::open(path.clone())
-            .await
-            .unwrap()
-            .metadata()
+    let mut source = path.clone();
+    if transcode::needs_transcoding(&path) {
+        source = transcode::transcode(&path, config.get_transcode())
             .await
-            .unwrap()
-            .size() as u32;
-
-        let mut track = XTrackItem::new(
-            id,
-            size_in_bytes,
-            (audio_info.duration * 1000.0) as u32,
-            year.unwrap_or(0) as u32,
-            (audio_info.bit_rate / 1000) as u32,
-            (audio_info.sample_rate * 65536) as u32,
-            song_dbid,
-            0,
-        );
-
-        audio_file.modify_xtrack(&mut track);
+            .ok_or("transcoding failed, is ffmpeg installed?")?;
+    }
 
-        track.data.gapless_album_flag = 1;
-        track.data.gapless_track_flag = 0;
+    let probed = match source.to_str() {
+        Some(s) => audio_file_info::from_path(s).await,
+        None => None,
+    };
+    let mut audio_file = match probed {
+        Some(audio_file) => audio_file,
+        None => {
+            if source != path {
+                let _ = std::fs::remove_file(&source);
+            }
+            return Err("not a playable audio file or the codec isn't supported".to_string());
+        }
+    };
+    let bytes_saved = shrink_if_needed(
+        &mut source,
+        &path,
+        &mut audio_file,
+        profile,
+        config.get_transcode(),
+    )
+    .await;
+
+    let mut prepared = prepare_blocking(
+        path,
+        source,
+        song_dbid,
+        audio_file,
+        bytes_saved,
+        formats,
+        &config,
+    );
+
+    if prepared.cover.is_none() {
+        if let Some(cover) = artwork::online(&prepared.path).await {
+            prepared.cover = prepare_cover(cover, formats, config.get_coverart());
+        }
+    }
 
-        track.data.mhii_link = size_in_bytes;
+    Ok(Prepared::New(Box::new(prepared)))
+}
 
-        track.data.lyrics_flag = (lyrics > 0) as u8;
+fn prepare_cover(
+    cover: Vec<u8>,
+    formats: &[ArtworkFormat],
+    config: &CoverArtConfiguration,
+) -> Option<PreparedCover> {
+    Some(PreparedCover {
+        hash: util::hash(&cover),
+        size: cover.len(),
+        images: render_covers(&cover, formats, config)?,
+    })
+}
 
-        if let Some(track_number) = track_number {
-            track.data.track_number = track_number as u32;
+fn prepare_blocking(
+    path: PathBuf,
+    mut source: PathBuf,
+    song_dbid: u64,
+    audio_file: AudioInfo,
+    bytes_saved: Option<u64>,
+    formats: &[ArtworkFormat],
+    config: &LyricaConfiguration,
+) -> PreparedTrack {
+    let mut tags = metadata::read_tags(&source);
+
+    let mut has_lyrics = lyrics::embedded_lyrics(&source).is_some();
+    let mut lyrics_note = None;
+    if !has_lyrics {
+        if let Some(text) = lyrics::external_lyrics(&path) {
+            if source == path {
+                // the user's file stays untouched, lyrics go into a copy
+                let mut tmp = get_temp_dl_dir();
+                tmp.push("lyrics");
+                let _ = std::fs::create_dir_all(&tmp);
+                tmp.push(format!("{:016x}", song_dbid));
+                tmp.set_extension(path.extension().unwrap_or_default());
+                if std::fs::copy(&path, &tmp).is_ok() {
+                    source = tmp;
+                }
+            }
+            has_lyrics = source != path && lyrics::embed_lyrics(&source, &text);
+            if !has_lyrics {
+                lyrics_note =
+                    Some("the .lrc/.txt lyrics couldn't be embedded into this format".to_string());
+            }
         }
+    }
 
-        if let Some(total_tracks) = total_tracks {
-            track.data.total_tracks = total_tracks as u32;
-        }
+    // embedded pictures first, then a cover file in the same directory
+    let cover = tags
+        .cover
+        .take()
+        .or_else(|| artwork::sidecar(&path))
+        .and_then(|cover| prepare_cover(cover, formats, config.get_coverart()));
+
+    PreparedTrack {
+        gapless: gapless::read(&source, &audio_file),
+        soundcheck: soundcheck::read(&source, &path, config.get_soundcheck()),
+        size: std::fs::metadata(&source).map(|m| m.len()).unwrap_or(0) as u32,
+        path,
+        source,
+        song_dbid,
+        audio_file,
+        tags,
+        has_lyrics,
+        lyrics_note,
+        cover,
+        bytes_saved,
+    }
+}
 
-        if let Some(disc_number) = disc_number {
-            track.data.disc_number = disc_number as u32;
+/// The serialized half of an import: the copy to the iPod and the database changes.
+async fn commit(
+    prepared: Prepared,
+    item: String,
+    database: &mut XDatabase,
+    artwork: &mut Artwork,
+    summary: &mut ImportSummary,
+    ipod_path: &str,
+) -> Option<u32> {
+    let prepared = match prepared {
+        Prepared::Known(song_dbid) => {
+            summary.add(item, None, ImportOutcome::Duplicate);
+            return database.get_unique_id_by_dbid(song_dbid);
         }
+        Prepared::New(prepared) => prepared,
+    };
 
-        if let Some(total_discs) = total_discs {
-            track.data.total_discs = total_discs as u32;
+    // the same file twice in one import
+    if database.if_track_in_library(prepared.song_dbid) {
+        if prepared.source != prepared.path {
+            let _ = std::fs::remove_file(&prepared.source);
         }
+        summary.add(item, None, ImportOutcome::Duplicate);
+        return database.get_unique_id_by_dbid(prepared.song_dbid);
+    }
 
-        if let Some(title) = title {
-            track.set_title(title.to_string());
-        } else {
-            track.set_title(path.file_name().unwrap().to_str().unwrap().to_string());
-        }
+    let id = database.get_unique_id();
+    let audio_info = prepared.audio_file.get_nice_object();
+    let tags = &prepared.tags;
+
+    let mut track = XTrackItem::new(
+        id,
+        prepared.size,
+        (audio_info.duration * 1000.0) as u32,
+        tags.year.unwrap_or(0) as u32,
+        (audio_info.bit_rate / 1000) as u32,
+        (audio_info.sample_rate * 65536) as u32,
+        prepared.song_dbid,
+        0,
+    );
+
+    prepared.audio_file.modify_xtrack(&mut track);
+
+    if let Some(gapless) = prepared.gapless {
+        gapless.apply(&mut track);
+    }
 
-        if let Some(genre) = genre {
-            track.set_genre(genre.to_string());
-        }
+    if let Some(soundcheck) = prepared.soundcheck {
+        track.data.soundcheck = soundcheck;
+    }
 
-        if let Some(artist) = artist {
-            track.set_artist(artist.to_string());
-        }
+    track.data.mhii_link = prepared.size;
 
-        if let Some(cover) = cover {
-            let _ = sender.send(AppEvent::ArtworkProgress((0, 2))).await;
+    track.data.lyrics_flag = prepared.has_lyrics as u8;
 
-            let mut adb = get_artwork_db(&ipod_path);
+    tags.apply(&mut track);
 
-            let cover_hash = util::hash(&cover);
+    if let Some(title) = &tags.title {
+        track.set_title(title.to_string());
+    } else {
+        track.set_title(item.clone());
+    }
 
-            let if_cover_present = adb.if_cover_present(cover_hash);
+    let extension = prepared.audio_file.get_audio_extension();
+    track.set_location(get_track_location(track.data.unique_id, extension));
 
-            let (small_img_name, large_img_name) = adb.add_images(song_dbid, cover_hash);
+    let dest = get_full_track_location(PathBuf::from(ipod_path), track.data.unique_id, extension);
 
-            let size = cover.len();
+    let copied = copy_to_ipod(prepared.source.clone(), dest).await;
 
-            if !if_cover_present {
-                make_cover_image(&cover, &ipod_path, &small_img_name, (100, 100));
-                let _ = sender.send(AppEvent::ArtworkProgress((1, 2))).await;
-                make_cover_image(&cover, &ipod_path, &large_img_name, (200, 200));
-            }
+    if prepared.source != prepared.path {
+        let _ = std::fs::remove_file(&prepared.source);
+    }
 
-            write_artwork_db(adb, &ipod_path);
+    if let Err(e) = copied {
+        let reason = ["copying to the iPod failed: ", &e.to_string()].concat();
+        summary.add(item, Some(prepared.path), ImportOutcome::Failed(reason));
+        return None;
+    }
 
-            track.data.artwork_size = size as u32;
-            track.data.has_artwork = 1;
-            track.data.artwork_count = 1;
+    if let Some(cover) = &prepared.cover {
+        artwork.add(
+            prepared.song_dbid,
+            cover.hash,
+            cover.size as u32,
+            &cover.images,
+            ipod_path,
+        );
 
-            let _ = sender.send(AppEvent::ArtworkProgress((2, 2))).await;
-        }
+        track.data.artwork_size = cover.size as u32;
+        track.data.has_artwork = 1;
+        track.data.artwork_count = 1;
+    }
 
-        if let Some(album) = album {
-            track.set_album(album);
-            // TODO: Add new album into iTunesDB
-        }
+    let album = gapless::album_key(&track);
+    database.add_track(track);
 
-        track.set_location(get_track_location(
-            track.data.unique_id,
-            audio_file.get_audio_extension(),
-        ));
+    summary.add(item, Some(prepared.path.clone()), ImportOutcome::Imported);
+    if let Some(note) = prepared.lyrics_note {
+        summary.note(note);
+    }
+    if let Some(saved) = prepared.bytes_saved {
+        summary.shrunk += 1;
+        summary.bytes_saved += saved;
+    }
 
-        let dest = get_full_track_location(
-            PathBuf::from(ipod_path.clone()),
-            track.data.unique_id,
-            audio_file.get_audio_extension(),
-        );
+    if let Some(album) = album {
+        gapless::update_album_flags(database, &album);
+    }
 
-        let _ = std::fs::copy(path.to_str().unwrap(), dest.to_str().unwrap());
+    Some(id)
+}
 
-        database.add_track(track);
+/// Probes, hashes, tags and renders artwork for several files at once on the blocking pool,
+/// while copying and adding them to the database one by one in the original order.
+async fn import_files(
+    files: Vec<PathBuf>,
+    profile: TranscodeProfile,
+    summary: &mut ImportSummary,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: &str,
+) -> Vec<Option<u32>> {
+    let mut known = HashSet::new();
+    if let XSomeList::TrackList(tracks) = &database.find_dataset(1).child {
+        known.extend(tracks.iter().map(|t| t.data.dbid));
+    }
+    let known = Arc::new(known);
+    // read once for the whole batch rather than by every file
+    let config = Arc::new(load_config());
+    let formats = ipod_model::cover_formats(ipod_path);
+
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4);
+
+    let total = files.len() as u32;
+    let mut prepared = futures::stream::iter(files.clone())
+        .map(|file| {
+            let known = known.clone();
+            let config = config.clone();
+            async move {
+                (
+                    file.clone(),
+                    prepare(file, known, profile, formats, config).await,
+                )
+            }
+        })
+        .buffered(workers);
+
+    let mut artwork = Artwork::open(ipod_path);
+    let mut ids = Vec::new();
+    while let Some((path, track)) = prepared.next().await {
+        if cancel::is_requested() {
+            break;
+        }
+        let _ = sender
+            .send(AppEvent::OverallProgress((
+                ids.len() as u32,
+                total,
+                Color::Green,
+            )))
+            .await;
+        let item = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        ids.push(match track {
+            Ok(t) => commit(t, item, database, &mut artwork, summary, ipod_path).await,
+            Err(reason) => {
+                summary.add(item, Some(path), ImportOutcome::Failed(reason));
+                None
+            }
+        });
+
+        // the copied files are useless to the iPod until the databases list them
+        if ids.len() % FLUSH_EVERY == 0 {
+            artwork.save(ipod_path);
+            artwork = Artwork::open(ipod_path);
+            // only the first save backs up the database as it was before the import
+            if ids.len() == FLUSH_EVERY {
+                overwrite_database(database, &ipod_path.to_string());
+            } else {
+                flush_database(database, ipod_path);
+            }
+        }
+    }
 
-        overwrite_database(database, &ipod_path);
-    } else if let Some(unique_id) = database.get_unique_id_by_dbid(song_dbid) {
-        id = unique_id;
+    // what finished stays on the iPod, the rest can be retried from the report
+    if ids.len() < files.len() {
+        // files already on the blocking pool carry on regardless, their temp copies are only
+        // safe to delete once they are done, the ones not started yet see the cancel and return
+        while prepared.next().await.is_some() {}
+        for path in files.into_iter().skip(ids.len()) {
+            let item = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            summary.add(
+                item,
+                Some(path),
+                ImportOutcome::Failed("cancelled".to_string()),
+            );
+            ids.push(None);
+        }
+        clear_temp_files();
     }
 
+    artwork.save(ipod_path);
+
+    overwrite_database(database, &ipod_path.to_string());
+
     let _ = sender
         .send(AppEvent::ITunesParsed(get_playlists(database)))
         .await;
 
-    id
+    ids
+}
+
+pub async fn load_from_fs(
+    path: PathBuf,
+    profile: TranscodeProfile,
+    summary: &mut ImportSummary,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+) -> Option<u32> {
+    import_files(vec![path], profile, summary, database, sender, &ipod_path)
+        .await
+        .pop()
+        .flatten()
 }
 
 pub async fn load_files_from_fs(
     files: Vec<PathBuf>,
+    profile: TranscodeProfile,
     database: &mut XDatabase,
     sender: &Sender<AppEvent>,
     ipod_path: String,
@@ -390,43 +980,33 @@ pub async fn load_files_from_fs(
     let _ = sender
         .send(AppEvent::SwitchScreen(AppState::LoadingScreen))
         .await;
-    for (i, file) in files.iter().enumerate() {
-        let _ = sender
-            .send(AppEvent::OverallProgress((
-                i as u32,
-                files.len() as u32,
-                Color::Green,
-            )))
-            .await;
-        load_from_fs(file.clone(), database, sender, ipod_path.clone()).await;
-    }
+    let mut summary = ImportSummary::new(profile);
+    import_files(files, profile, &mut summary, database, sender, &ipod_path).await;
 
     let _ = sender
         .send(AppEvent::SwitchScreen(AppState::FileSystem))
         .await;
+
+    let _ = sender.send(AppEvent::ImportSummary(summary)).await;
 }
 
 pub async fn load_files_from_fs_as_playlist(
     files: Vec<PathBuf>,
     title: String,
+    profile: TranscodeProfile,
     database: &mut XDatabase,
     sender: &Sender<AppEvent>,
     ipod_path: String,
 ) {
     let mut new_playlist = XPlaylist::new(rand::random(), ListSortOrder::SongTitle);
 
-    new_playlist.set_title(title);
+    new_playlist.set_title(title.clone());
 
-    for (i, file) in files.iter().enumerate() {
-        let _ = sender
-            .send(AppEvent::OverallProgress((
-                i as u32,
-                files.len() as u32,
-                Color::Green,
-            )))
-            .await;
-        let id = load_from_fs(file.clone(), database, sender, ipod_path.clone()).await;
+    let mut summary = ImportSummary::new(profile);
+    summary.playlist = Some(title);
 
+    let ids = import_files(files, profile, &mut summary, database, sender, &ipod_path).await;
+    for id in ids.into_iter().flatten() {
         new_playlist.add_elem(id);
     }
 
@@ -441,4 +1021,6 @@ pub async fn load_files_from_fs_as_playlist(
         .await;
 
     overwrite_database(database, &ipod_path);
+
+    let _ = sender.send(AppEvent::ImportSummary(summary)).await;
 }
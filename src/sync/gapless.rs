@@ -0,0 +1,253 @@
+use crate::sync::audio_file_info::{self, AudioInfo, Codec};
+use crate::sync::metadata::{self, MetadataField};
+use itunesdb::xobjects::{XDatabase, XSomeList, XTrackItem};
+use mp4ameta::FreeformIdent;
+use std::path::Path;
+
+// the mp3 decoder adds this many samples of silence on top of the encoder delay
+const DECODER_DELAY: u32 = 529;
+
+const ITUNSMPB: FreeformIdent = FreeformIdent::new("com.apple.iTunes", "iTunSMPB");
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GaplessInfo {
+    pub pregap: u32,
+    pub postgap: u32,
+    pub sample_count: u64,
+    // bytes from the first frame up to the 8th from the end, only used for mp3
+    pub gapless_data: u32,
+}
+
+impl GaplessInfo {
+    pub fn apply(&self, track: &mut XTrackItem) {
+        track.data.pregap = self.pregap;
+        track.data.postgap = self.postgap;
+        track.data.sample_count = self.sample_count;
+        track.data.gapless_data = self.gapless_data;
+        track.data.gapless_track_flag = 1;
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?))
+}
+
+struct XingHeader {
+    frames: u32,
+    bytes: u32,
+    // encoder delay and padding from the LAME extension
+    lame: Option<(u32, u32)>,
+}
+
+fn read_xing(frame: &[u8]) -> Option<XingHeader> {
+    let start = frame[..frame.len().min(200)]
+        .windows(4)
+        .position(|w| w == b"Xing" || w == b"Info")?;
+    let flags = be_u32(frame.get(start + 4..)?)?;
+    let mut pos = start + 8;
+
+    let mut frames = 0;
+    if flags & 0x1 != 0 {
+        frames = be_u32(frame.get(pos..)?)?;
+        pos += 4;
+    }
+    let mut bytes = 0;
+    if flags & 0x2 != 0 {
+        bytes = be_u32(frame.get(pos..)?)?;
+        pos += 4;
+    }
+    if flags & 0x4 != 0 {
+        pos += 100; // seek table
+    }
+    if flags & 0x8 != 0 {
+        pos += 4; // quality
+    }
+
+    let lame = match frame.get(pos..pos + 24) {
+        Some(ext) if &ext[0..4] == b"LAME" || &ext[0..4] == b"Lavf" || &ext[0..4] == b"Lavc" => {
+            let delay = ((ext[21] as u32) << 4) | ((ext[22] as u32) >> 4);
+            let padding = (((ext[22] & 0x0F) as u32) << 8) | ext[23] as u32;
+            Some((delay, padding))
+        }
+        _ => None,
+    };
+
+    Some(XingHeader {
+        frames,
+        bytes,
+        lame,
+    })
+}
+
+/// " 00000000 00000840 000001CA 00000000003F31F6 ..." - delay, padding and the real sample count
+fn parse_itunsmpb(value: &str) -> Option<(u32, u32, u64)> {
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    let delay = u32::from_str_radix(fields.get(1)?, 16).ok()?;
+    let padding = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+    let samples = u64::from_str_radix(fields.get(3)?, 16).ok()?;
+    Some((delay, padding, samples))
+}
+
+fn read_mp3(path: &Path) -> Option<GaplessInfo> {
+    let frame = audio_file_info::first_mp3_frame(path)?;
+    let xing = read_xing(&frame)?;
+    if xing.frames == 0 || xing.bytes == 0 {
+        return None;
+    }
+
+    // MPEG-1 layer III frames carry 1152 samples, MPEG-2 and 2.5 carry half of that
+    let samples_per_frame: u64 = if (frame.get(1)? >> 3) & 0x3 == 0x3 {
+        1152
+    } else {
+        576
+    };
+    let total = xing.frames as u64 * samples_per_frame;
+
+    let (pregap, postgap) = match xing.lame {
+        Some((delay, padding)) => (delay + DECODER_DELAY, padding.saturating_sub(DECODER_DELAY)),
+        None => {
+            // iTunes writes its own encodes with an ID3 copy of iTunSMPB instead
+            let tag = id3::Tag::read_from_path(path).ok()?;
+            let smpb = tag.extended_texts().find(|t| t.description == "iTunSMPB")?;
+            let (delay, padding, _) = parse_itunsmpb(&smpb.value)?;
+            (delay + DECODER_DELAY, padding.saturating_sub(DECODER_DELAY))
+        }
+    };
+
+    let frame_size = xing.bytes / xing.frames;
+    Some(GaplessInfo {
+        pregap,
+        postgap,
+        sample_count: total.saturating_sub((pregap + postgap) as u64),
+        gapless_data: xing.bytes.saturating_sub(8 * frame_size),
+    })
+}
+
+fn read_mp4(path: &Path) -> Option<GaplessInfo> {
+    let tag = mp4ameta::Tag::read_from_path(path).ok()?;
+    let (pregap, postgap, sample_count) = tag.strings_of(&ITUNSMPB).find_map(parse_itunsmpb)?;
+    if sample_count == 0 {
+        return None;
+    }
+    Some(GaplessInfo {
+        pregap,
+        postgap,
+        sample_count,
+        gapless_data: 0,
+    })
+}
+
+/// Encoder delay and padding of lossy files. Lossless files have neither, only the sample count matters.
+pub fn read(path: &Path, audio_file: &AudioInfo) -> Option<GaplessInfo> {
+    match audio_file.codec() {
+        Codec::Mp3 { .. } => read_mp3(path),
+        Codec::AacLc | Codec::HeAac => read_mp4(path),
+        Codec::Alac | Codec::Aiff | Codec::Wav => {
+            let info = audio_file.get_nice_object();
+            Some(GaplessInfo {
+                sample_count: (info.duration * info.sample_rate as f64) as u64,
+                ..Default::default()
+            })
+        }
+        Codec::Audible => None,
+    }
+}
+
+/// Album artist and album a track belongs to, the artist stands in for a missing album artist.
+/// None for tracks without an album.
+pub fn album_key(track: &XTrackItem) -> Option<(String, String)> {
+    let album = track.get_album();
+    if album.is_empty() {
+        return None;
+    }
+    let mut artist = metadata::get_field(track, MetadataField::AlbumArtist);
+    if artist.is_empty() {
+        artist = track.get_artist();
+    }
+    Some((artist, album))
+}
+
+/// An album only plays gapless when every one of its tracks does.
+/// Called after adding or removing tracks of the album.
+pub fn update_album_flags(database: &mut XDatabase, key: &(String, String)) {
+    if let XSomeList::TrackList(tracks) = &mut database.find_dataset(1).child {
+        let in_album = |t: &XTrackItem| album_key(t).as_ref() == Some(key);
+        let gapless = tracks
+            .iter()
+            .filter(|t| in_album(t))
+            .all(|t| t.data.gapless_track_flag == 1);
+        for track in tracks.iter_mut().filter(|t| in_album(t)) {
+            track.data.gapless_album_flag = gapless as u16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xing_frame(flags: u32) -> Vec<u8> {
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x64];
+        frame.extend([0; 32]);
+        frame.extend(b"Info");
+        frame.extend(flags.to_be_bytes());
+        frame
+    }
+
+    #[test]
+    fn itunsmpb_fields() {
+        let value = " 00000000 00000840 000001CA 00000000003F31F6 00000000 00000000";
+        assert_eq!(parse_itunsmpb(value), Some((0x840, 0x1CA, 0x3F31F6)));
+    }
+
+    #[test]
+    fn itunsmpb_truncated() {
+        assert_eq!(parse_itunsmpb(" 00000000 00000840 000001CA"), None);
+        assert_eq!(parse_itunsmpb(""), None);
+        assert_eq!(parse_itunsmpb(" 00000000 0000084G 000001CA 0"), None);
+    }
+
+    #[test]
+    fn xing_frames_and_bytes() {
+        let mut frame = xing_frame(0x3);
+        frame.extend(1000u32.to_be_bytes());
+        frame.extend(417_000u32.to_be_bytes());
+        let xing = read_xing(&frame).unwrap();
+        assert_eq!((xing.frames, xing.bytes), (1000, 417_000));
+        assert!(xing.lame.is_none());
+    }
+
+    #[test]
+    fn xing_lame_delay() {
+        let mut frame = xing_frame(0x0);
+        let mut ext = b"LAME3.100".to_vec();
+        ext.resize(21, 0);
+        // 576 samples of delay, 1234 of padding
+        ext.extend([0x24, 0x04, 0xD2]);
+        frame.extend(ext);
+        assert_eq!(read_xing(&frame).unwrap().lame, Some((576, 1234)));
+    }
+
+    #[test]
+    fn xing_truncated() {
+        // cut off inside the flags
+        let frame = xing_frame(0x3);
+        assert!(read_xing(&frame[..frame.len() - 2]).is_none());
+        // the flags announce a frame count that isn't there
+        let mut frame = xing_frame(0x3);
+        frame.extend(1000u32.to_be_bytes());
+        assert!(read_xing(&frame).is_none());
+        frame.extend([0, 1]);
+        assert!(read_xing(&frame).is_none());
+        // a seek table running past the end only loses the LAME extension
+        let mut frame = xing_frame(0x4);
+        frame.extend([0; 10]);
+        assert!(read_xing(&frame).is_some_and(|x| x.lame.is_none()));
+    }
+
+    #[test]
+    fn xing_missing() {
+        assert!(read_xing(&[]).is_none());
+        assert!(read_xing(&[0xFF, 0xFB, 0x90, 0x64]).is_none());
+    }
+}
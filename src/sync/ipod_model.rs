@@ -0,0 +1,193 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb565Le,
+    // 4:2:2 interleaved, only photos for the TV out are stored this way
+    Uyvy,
+}
+
+/// One thumbnail the firmware expects for every cover, identified by its format (correlation) id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArtworkFormat {
+    pub id: u32,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: PixelFormat,
+    // extra pixels at the end of every row, some formats are stored wider than they are shown
+    pub row_padding: u32,
+}
+
+impl ArtworkFormat {
+    const fn rgb565(id: u32, side: u32) -> Self {
+        Self {
+            id,
+            width: side,
+            height: side,
+            pixel_format: PixelFormat::Rgb565Le,
+            row_padding: 0,
+        }
+    }
+
+    pub fn stride(&self) -> u32 {
+        self.width + self.row_padding
+    }
+
+    /// Bytes one image of this format takes up in the ithmb file.
+    pub fn image_size(&self) -> u32 {
+        // both pixel formats are two bytes per pixel
+        self.stride() * self.height * 2
+    }
+}
+
+const PHOTO_COVERS: [ArtworkFormat; 2] = [
+    ArtworkFormat::rgb565(1016, 140),
+    ArtworkFormat::rgb565(1017, 56),
+];
+
+const VIDEO_COVERS: [ArtworkFormat; 2] = [
+    ArtworkFormat::rgb565(1028, 100),
+    ArtworkFormat::rgb565(1029, 200),
+];
+
+const NANO_COVERS: [ArtworkFormat; 2] = [
+    ArtworkFormat::rgb565(1027, 100),
+    ArtworkFormat::rgb565(1031, 42),
+];
+
+// the classic and the 3rd gen nano share cover flow
+const CLASSIC_COVERS: [ArtworkFormat; 4] = [
+    ArtworkFormat::rgb565(1055, 128),
+    ArtworkFormat::rgb565(1068, 128),
+    ArtworkFormat::rgb565(1060, 320),
+    ArtworkFormat {
+        row_padding: 8,
+        ..ArtworkFormat::rgb565(1061, 56)
+    },
+];
+
+const NANO_4G_COVERS: [ArtworkFormat; 4] = [
+    ArtworkFormat::rgb565(1055, 128),
+    ArtworkFormat::rgb565(1078, 80),
+    ArtworkFormat::rgb565(1071, 240),
+    ArtworkFormat::rgb565(1074, 50),
+];
+
+const NANO_5G_COVERS: [ArtworkFormat; 4] = [
+    ArtworkFormat::rgb565(1056, 128),
+    ArtworkFormat::rgb565(1078, 80),
+    ArtworkFormat::rgb565(1073, 240),
+    ArtworkFormat::rgb565(1074, 50),
+];
+
+// the photo database shares the ithmb layout, a full screen photo for the TV out
+const TV_OUT_PHOTOS: [ArtworkFormat; 1] = [ArtworkFormat {
+    id: 1019,
+    width: 720,
+    height: 480,
+    pixel_format: PixelFormat::Uyvy,
+    row_padding: 0,
+}];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IPodModel {
+    Photo,
+    Video,
+    Classic,
+    Nano,
+    Nano3G,
+    Nano4G,
+    Nano5G,
+    Unknown,
+}
+
+// model numbers as they appear in SysInfo, without the region letter in front
+const MODEL_NUMBERS: [(&str, IPodModel); 7] = [
+    ("9585 9586 9829 9830 A079 A127", IPodModel::Photo),
+    (
+        "A002 A003 A146 A147 A444 A446 A448 A450 A664",
+        IPodModel::Video,
+    ),
+    (
+        "B029 B147 B145 B150 B562 B565 C293 C297",
+        IPodModel::Classic,
+    ),
+    (
+        "A004 A005 A099 A107 A350 A352 A477 A426 A428 A487 A489 A497 A725 A726 A899",
+        IPodModel::Nano,
+    ),
+    ("A978 A980 B249 B253 B257 B261", IPodModel::Nano3G),
+    (
+        "B598 B654 B732 B735 B739 B742 B745 B748 B751 B754",
+        IPodModel::Nano4G,
+    ),
+    (
+        "C027 C031 C034 C037 C040 C043 C046 C049 C050 C060 C062 C064 C066 C068 C070 C072 C074",
+        IPodModel::Nano5G,
+    ),
+];
+
+impl IPodModel {
+    pub fn cover_formats(&self) -> &'static [ArtworkFormat] {
+        match self {
+            IPodModel::Photo => &PHOTO_COVERS,
+            IPodModel::Nano => &NANO_COVERS,
+            IPodModel::Classic | IPodModel::Nano3G => &CLASSIC_COVERS,
+            IPodModel::Nano4G => &NANO_4G_COVERS,
+            IPodModel::Nano5G => &NANO_5G_COVERS,
+            // what lyrica always wrote before models were told apart
+            IPodModel::Video | IPodModel::Unknown => &VIDEO_COVERS,
+        }
+    }
+}
+
+fn from_model_number(model: &str) -> IPodModel {
+    // "xB029" or "MB029LL", the letters around the number vary by region
+    let model = model.trim().trim_start_matches(['x', 'M', 'P']);
+    let number = model.get(0..4).unwrap_or_default().to_uppercase();
+    MODEL_NUMBERS
+        .iter()
+        .find(|(numbers, _)| numbers.split(' ').any(|n| n == number))
+        .map(|(_, model)| *model)
+        .unwrap_or(IPodModel::Unknown)
+}
+
+/// Reads the model number iTunes left in iPod_Control/Device/SysInfo.
+pub fn detect(ipod_path: &str) -> IPodModel {
+    let mut sysinfo = PathBuf::from(ipod_path);
+    sysinfo.push("iPod_Control");
+    sysinfo.push("Device");
+    sysinfo.push("SysInfo");
+
+    std::fs::read_to_string(sysinfo)
+        .ok()
+        .and_then(|content| {
+            content
+                .lines()
+                .find_map(|l| l.strip_prefix("ModelNumStr:"))
+                .map(from_model_number)
+        })
+        .unwrap_or(IPodModel::Unknown)
+}
+
+/// The thumbnails to generate for every cover put on this iPod.
+pub fn cover_formats(ipod_path: &str) -> &'static [ArtworkFormat] {
+    detect(ipod_path).cover_formats()
+}
+
+/// Looks a format up by its id, whichever model it belongs to.
+pub fn format(id: u32) -> Option<ArtworkFormat> {
+    [
+        &PHOTO_COVERS[..],
+        &VIDEO_COVERS,
+        &NANO_COVERS,
+        &CLASSIC_COVERS,
+        &NANO_4G_COVERS,
+        &NANO_5G_COVERS,
+        &TV_OUT_PHOTOS,
+    ]
+    .iter()
+    .flat_map(|formats| formats.iter())
+    .find(|f| f.id == id)
+    .copied()
+}
@@ -0,0 +1,301 @@
+use crate::sync::artworkdb::{ArtworkDb, Thumbnail};
+use crate::sync::device;
+use crate::sync::ipod_model::{self, ArtworkFormat, PixelFormat};
+use crate::util::IPodImage;
+use image::RgbaImage;
+use itunesdb::xobjects::XTrackItem;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// lyrica's record of the picture every cover was made from, so tracks with the same one share thumbnails
+const HASHES_FILE: &str = "LyricaCovers.json";
+
+// the firmware reads ithmb files up to about this size, the next thumbnails go into a new one
+const MAX_ITHMB_SIZE: u64 = 256_000_000;
+
+// the ArtworkDB as last read, for browsing covers without parsing it every time
+static CACHE: Mutex<Option<(String, ArtworkDb)>> = Mutex::new(None);
+
+pub fn artwork_dir(ipod_path: &str) -> PathBuf {
+    let mut dir = PathBuf::from(ipod_path);
+    dir.push("iPod_Control");
+    dir.push("Artwork");
+    dir
+}
+
+// None when there is an ArtworkDB but it can't be read, a missing one is empty
+fn read_db(ipod_path: &str) -> Option<ArtworkDb> {
+    match std::fs::read(artwork_dir(ipod_path).join("ArtworkDB")) {
+        Ok(data) => ArtworkDb::parse(&data),
+        Err(_e) => Some(ArtworkDb::default()),
+    }
+}
+
+/// The ArtworkDB of the iPod together with the thumbnails waiting to be written next to it.
+pub struct Artwork {
+    db: ArtworkDb,
+    formats: &'static [ArtworkFormat],
+    // song dbid -> hash of the picture its cover was made from
+    hashes: HashMap<u64, u64>,
+    // ithmb file -> thumbnails to append to it, written before the ArtworkDB pointing at them
+    pending: BTreeMap<String, Vec<u8>>,
+    // an ArtworkDB lyrica can't read is left as it is rather than replaced by an empty one
+    unreadable: bool,
+    // covers went, their thumbnails are still in the ithmb files
+    removed: bool,
+}
+
+impl Artwork {
+    pub fn open(ipod_path: &str) -> Self {
+        let db = read_db(ipod_path);
+        let hashes = std::fs::read(artwork_dir(ipod_path).join(HASHES_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self {
+            unreadable: db.is_none(),
+            db: db.unwrap_or_default(),
+            formats: ipod_model::cover_formats(ipod_path),
+            hashes,
+            pending: BTreeMap::new(),
+            removed: false,
+        }
+    }
+
+    /// The thumbnails every cover on this iPod is made of.
+    pub fn formats(&self) -> &'static [ArtworkFormat] {
+        self.formats
+    }
+
+    /// Adds a track's cover, replacing the one it had. `images` are in the order of the
+    /// model's cover formats, as render_covers made them. A cover another track already
+    /// has shares its thumbnails.
+    pub fn add(
+        &mut self,
+        song_dbid: u64,
+        hash: u64,
+        source_size: u32,
+        images: &[IPodImage],
+        ipod_path: &str,
+    ) {
+        let shared = self
+            .hashes
+            .iter()
+            .filter(|(_, h)| **h == hash)
+            .filter_map(|(dbid, _)| self.db.image(*dbid))
+            .find(|image| {
+                self.formats
+                    .iter()
+                    .all(|f| image.thumbnails.iter().any(|t| t.format_id == f.id))
+            })
+            .map(|image| image.thumbnails.clone());
+
+        let thumbnails = match shared {
+            Some(thumbnails) => thumbnails,
+            None => self
+                .formats
+                .iter()
+                .zip(images)
+                .map(|(format, image)| self.append(format, image, ipod_path))
+                .collect(),
+        };
+
+        self.removed |= self.db.image(song_dbid).is_some();
+        self.db.add(song_dbid, source_size, thumbnails);
+        self.hashes.insert(song_dbid, hash);
+    }
+
+    // queues a thumbnail for the end of its format's ithmb file
+    fn append(&mut self, format: &ArtworkFormat, image: &IPodImage, ipod_path: &str) -> Thumbnail {
+        let dir = artwork_dir(ipod_path);
+        let size = image.data().len() as u64;
+        let mut number = 1;
+        loop {
+            let file = format!("F{}_{}.ithmb", format.id, number);
+            let written = std::fs::metadata(dir.join(&file))
+                .map(|m| m.len())
+                .unwrap_or(0);
+            let offset = written + self.pending.get(&file).map_or(0, |p| p.len() as u64);
+            if offset + size > MAX_ITHMB_SIZE && offset > 0 {
+                number += 1;
+                continue;
+            }
+
+            self.pending
+                .entry(file.clone())
+                .or_default()
+                .extend_from_slice(image.data());
+            return Thumbnail {
+                format_id: format.id,
+                file,
+                offset: offset as u32,
+                size: size as u32,
+                width: format.width as u16,
+                height: format.height as u16,
+                vertical_padding: 0,
+                horizontal_padding: 0,
+            };
+        }
+    }
+
+    /// Drops the cover of a track which is removed or gets another one.
+    pub fn remove(&mut self, track: &XTrackItem) {
+        self.removed |= self.db.remove(track.data.dbid);
+        self.hashes.remove(&track.data.dbid);
+    }
+
+    /// Copies the thumbnails still in use out of ithmb files which have unused ones into new
+    /// files and points the ArtworkDB at them. Returns the ithmb files nothing points at anymore.
+    fn compact(&mut self, dir: &Path) -> Vec<String> {
+        // ithmb file -> format id, and offset and size of every thumbnail in it
+        let mut used: BTreeMap<String, (u32, BTreeSet<(u32, u32)>)> = BTreeMap::new();
+        for thumbnail in self.db.images.iter().flat_map(|i| i.thumbnails.iter()) {
+            used.entry(thumbnail.file.clone())
+                .or_insert((thumbnail.format_id, BTreeSet::new()))
+                .1
+                .insert((thumbnail.offset, thumbnail.size));
+        }
+
+        for (file, (format_id, slots)) in used.iter() {
+            let written = std::fs::metadata(dir.join(file))
+                .map(|m| m.len())
+                .unwrap_or(0);
+            let total = written + self.pending.get(file).map_or(0, |p| p.len() as u64);
+            let kept: u64 = slots.iter().map(|(_, size)| *size as u64).sum();
+            if kept >= total {
+                continue;
+            }
+
+            // the file as it is once the pending thumbnails are in it
+            let mut data = std::fs::read(dir.join(file)).unwrap_or_default();
+            data.extend(self.pending.remove(file).unwrap_or_default());
+
+            let mut compacted = Vec::new();
+            let mut moved = HashMap::new();
+            for (offset, size) in slots.iter() {
+                if moved.contains_key(offset) {
+                    continue;
+                }
+                let start = *offset as usize;
+                if let Some(bytes) = data.get(start..start + *size as usize) {
+                    moved.insert(*offset, compacted.len() as u32);
+                    compacted.extend_from_slice(bytes);
+                }
+            }
+
+            let new_file = (1..)
+                .map(|n| format!("F{}_{}.ithmb", format_id, n))
+                .find(|f| !dir.join(f).exists() && !self.pending.contains_key(f))
+                .unwrap_or_default();
+            for thumbnail in self
+                .db
+                .images
+                .iter_mut()
+                .flat_map(|i| i.thumbnails.iter_mut())
+                .filter(|t| &t.file == file)
+            {
+                if let Some(offset) = moved.get(&thumbnail.offset) {
+                    thumbnail.file = new_file.clone();
+                    thumbnail.offset = *offset;
+                }
+            }
+            self.pending.insert(new_file, compacted);
+        }
+
+        let referenced: HashSet<&str> = self
+            .db
+            .images
+            .iter()
+            .flat_map(|i| i.thumbnails.iter())
+            .map(|t| t.file.as_str())
+            .collect();
+        std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|f| f.ends_with(".ithmb") && !referenced.contains(f.as_str()))
+            .collect()
+    }
+
+    /// Appends the new thumbnails to their ithmb files, then writes the ArtworkDB and the record.
+    /// After covers were removed, ithmb files with thumbnails no cover uses are replaced.
+    pub fn save(mut self, ipod_path: &str) {
+        if self.unreadable {
+            return;
+        }
+        let dir = artwork_dir(ipod_path);
+        device::create_dir_all(&dir);
+
+        let obsolete = match self.removed {
+            true => self.compact(&dir),
+            false => Vec::new(),
+        };
+
+        for (file, data) in self.pending.iter() {
+            if device::append_file(&dir.join(file), data).is_err() {
+                // the ArtworkDB on the iPod still matches the files, better than pointing past them
+                return;
+            }
+        }
+        let _ = device::write_file(&dir.join("ArtworkDB"), &self.db.to_bytes());
+
+        // only once the ArtworkDB on the iPod no longer points into them
+        for file in obsolete {
+            let _ = device::remove_file(&dir.join(file));
+        }
+
+        if let Ok(data) = serde_json::to_vec(&self.hashes) {
+            let _ = device::write_file(&dir.join(HASHES_FILE), &data);
+        }
+        if let Ok(mut cache) = CACHE.lock() {
+            *cache = None;
+        }
+    }
+}
+
+// the format of a thumbnail, one the table doesn't know is taken to be RGB565 as its entry describes it
+fn thumbnail_format(thumbnail: &Thumbnail) -> Option<ArtworkFormat> {
+    if let Some(format) = ipod_model::format(thumbnail.format_id) {
+        return Some(format);
+    }
+    let (width, height) = (thumbnail.width as u32, thumbnail.height as u32);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let stride = thumbnail.size / (height * 2);
+    Some(ArtworkFormat {
+        id: thumbnail.format_id,
+        width,
+        height,
+        pixel_format: PixelFormat::Rgb565Le,
+        row_padding: stride.checked_sub(width)?,
+    })
+}
+
+/// The largest thumbnail of a track's cover, None if it has none lyrica can read.
+pub fn read_cover(song_dbid: u64, ipod_path: &str) -> Option<RgbaImage> {
+    let (format, thumbnail) = {
+        let mut cache = CACHE.lock().ok()?;
+        if cache.as_ref().is_none_or(|(path, _)| path != ipod_path) {
+            *cache = Some((ipod_path.to_string(), read_db(ipod_path)?));
+        }
+        cache
+            .as_ref()?
+            .1
+            .image(song_dbid)?
+            .thumbnails
+            .iter()
+            .filter_map(|t| Some((thumbnail_format(t)?, t.clone())))
+            .max_by_key(|(f, _)| f.width * f.height)?
+    };
+
+    let mut ithmb = std::fs::File::open(artwork_dir(ipod_path).join(&thumbnail.file)).ok()?;
+    ithmb.seek(SeekFrom::Start(thumbnail.offset as u64)).ok()?;
+    let mut data = vec![0; format.image_size() as usize];
+    ithmb.read_exact(&mut data).ok()?;
+
+    IPodImage::decode(&data, &format)
+}
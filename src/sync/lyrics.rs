@@ -0,0 +1,107 @@
+use id3::frame::Lyrics;
+use id3::TagLike;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn is_mp4(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "m4a" | "m4b" | "mp4")
+}
+
+fn non_empty(text: String) -> Option<String> {
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Lyrics stored in a way the iPod can display: ID3 USLT or the MP4 ©lyr atom.
+pub fn embedded_lyrics(path: &Path) -> Option<String> {
+    if is_mp4(path) {
+        let tag = mp4ameta::Tag::read_from_path(path).ok()?;
+        return tag.lyrics().and_then(|l| non_empty(l.to_string()));
+    }
+    let tag = id3::Tag::read_from_path(path).ok()?;
+    let lyrics = tag.lyrics().find_map(|l| non_empty(l.text.clone()));
+    lyrics
+}
+
+/// Lyrics the iPod can't show as they are: synchronised ID3 lyrics and .lrc/.txt sidecars.
+pub fn external_lyrics(path: &Path) -> Option<String> {
+    if let Ok(tag) = id3::Tag::read_from_path(path) {
+        let synced = tag.synchronised_lyrics().find_map(|l| {
+            non_empty(
+                l.content
+                    .iter()
+                    .map(|(_, line)| line.trim())
+                    .collect::<Vec<&str>>()
+                    .join("\n"),
+            )
+        });
+        if synced.is_some() {
+            return synced;
+        }
+    }
+
+    let mut lrc: PathBuf = path.into();
+    lrc.set_extension("lrc");
+    if let Ok(content) = std::fs::read_to_string(&lrc) {
+        if let Some(lyrics) = non_empty(strip_lrc(&content)) {
+            return Some(lyrics);
+        }
+    }
+
+    let mut txt: PathBuf = path.into();
+    txt.set_extension("txt");
+    std::fs::read_to_string(&txt).ok().and_then(non_empty)
+}
+
+static LRC_TIMESTAMP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\d+:\d+(?:[.:]\d+)?\]|<\d+:\d+(?:[.:]\d+)?>").unwrap());
+static LRC_ID_TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[[a-zA-Z#]+:.*\]$").unwrap());
+
+fn strip_lrc(content: &str) -> String {
+    content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !LRC_ID_TAG.is_match(l))
+        .map(|l| LRC_TIMESTAMP.replace_all(l, "").trim().to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Writes unsynchronised lyrics into the file, replacing whatever lyrics it had.
+pub fn embed_lyrics(path: &Path, lyrics: &str) -> bool {
+    if is_mp4(path) {
+        return match mp4ameta::Tag::read_from_path(path) {
+            Ok(mut tag) => {
+                tag.set_lyrics(lyrics);
+                tag.write_to_path(path).is_ok()
+            }
+            Err(_e) => false,
+        };
+    }
+
+    if extension(path) != "mp3" {
+        return false;
+    }
+
+    let mut tag = id3::Tag::read_from_path(path).unwrap_or_default();
+    tag.remove_all_lyrics();
+    tag.add_frame(Lyrics {
+        lang: "eng".to_string(),
+        description: String::new(),
+        text: lyrics.to_string(),
+    });
+    // older iPod firmwares only read ID3v2.3 properly
+    tag.write_to_path(path, id3::Version::Id3v23).is_ok()
+}
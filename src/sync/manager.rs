@@ -1,11 +1,44 @@
+use crate::config::{get_configs_dir, load_config};
+use crate::sync::ithmb::Artwork;
+use crate::sync::metadata::{self, MetadataChanges};
+use crate::sync::musicbrainz::{self, MusicBrainzIds, ReleaseCandidate};
 use crate::sync::sync_util::AppEvent;
-use crate::sync::{get_full_track_location, get_playlists, overwrite_database};
-use crate::AppState;
-use itunesdb::xobjects::XDatabase;
+use crate::sync::{acoustid, artwork, cancel, device, gapless, ithmb, lyrics};
+use crate::sync::{get_full_track_location, get_playlists, overwrite_database, render_covers};
+use crate::{util, AppState};
+use itunesdb::xobjects::{XDatabase, XSomeList};
 use ratatui::prelude::Color;
 use std::path::PathBuf;
 use tokio::sync::mpsc::Sender;
 
+const TRACK_EXTENSIONS: [&str; 6] = ["mp3", "m4a", "wav", "aif", "aa", "aax"];
+
+fn find_track_file(ipod_path: &str, id: u32) -> Option<PathBuf> {
+    TRACK_EXTENSIONS
+        .iter()
+        .map(|ext| get_full_track_location(PathBuf::from(ipod_path), id, ext))
+        .find(|p| p.exists())
+}
+
+/// Takes a track off the iPod, its cover goes from `artwork`.
+fn delete_track(id: u32, database: &mut XDatabase, artwork: &mut Artwork, ipod_path: &str) {
+    if let Some(track) = database.get_track(id) {
+        artwork.remove(track);
+    }
+    let album = database.get_track(id).and_then(gapless::album_key);
+    database.remove_track_completely(id);
+    // the remaining tracks may all be gapless now
+    if let Some(album) = album {
+        gapless::update_album_flags(database, &album);
+    }
+    for ext in TRACK_EXTENSIONS.iter() {
+        let dest = get_full_track_location(PathBuf::from(ipod_path), id, ext);
+        if dest.exists() {
+            let _ = device::remove_file(&dest);
+        }
+    }
+}
+
 pub async fn remove_track(
     id: u32,
     database: &mut XDatabase,
@@ -15,11 +48,9 @@ pub async fn remove_track(
     let _ = sender
         .send(AppEvent::OverallProgress((0, 1, Color::Red)))
         .await;
-    database.remove_track_completely(id);
-    for ext in ["mp3", "m4a", "wav", "aif"].iter() {
-        let dest = get_full_track_location(PathBuf::from(ipod_path.clone()), id, ext);
-        let _ = std::fs::remove_file(dest);
-    }
+    let mut artwork = Artwork::open(&ipod_path);
+    delete_track(id, database, &mut artwork, &ipod_path);
+    artwork.save(&ipod_path);
 
     let _ = sender
         .send(AppEvent::OverallProgress((1, 1, Color::Red)))
@@ -49,23 +80,38 @@ pub async fn remove_playlist(
         if pl.is_none() {
             return;
         }
-        let pl = pl.unwrap();
-        let max = pl.elems.len();
-        let mut i = 1;
-        for (item, _args) in pl.elems.iter() {
+        let ids: Vec<u32> = pl
+            .unwrap()
+            .elems
+            .iter()
+            .map(|(item, _)| item.track_id)
+            .collect();
+        let max = ids.len();
+        let mut artwork = Artwork::open(&ipod_path);
+        for (i, id) in ids.into_iter().enumerate() {
+            if cancel::is_requested() {
+                break;
+            }
             let _ = sender
-                .send(AppEvent::OverallProgress((i, max as u32, Color::Red)))
+                .send(AppEvent::OverallProgress((
+                    i as u32 + 1,
+                    max as u32,
+                    Color::Red,
+                )))
                 .await;
-            remove_track(item.track_id, database, sender, ipod_path.clone()).await;
-            i += 1;
+            delete_track(id, database, &mut artwork, &ipod_path);
         }
+        artwork.save(&ipod_path);
     }
 
     let _ = sender
         .send(AppEvent::OverallProgress((0, 1, Color::Red)))
         .await;
 
-    database.remove_playlist(pl_id);
+    // a playlist removed halfway keeps the tracks which are left
+    if !cancel::is_requested() {
+        database.remove_playlist(pl_id);
+    }
 
     let _ = sender
         .send(AppEvent::OverallProgress((1, 1, Color::Red)))
@@ -109,3 +155,315 @@ pub async fn remove_track_from_playlist(
 
     overwrite_database(database, &ipod_path);
 }
+
+pub async fn show_metadata(ids: Vec<u32>, database: &mut XDatabase, sender: &Sender<AppEvent>) {
+    let tracks = ids
+        .iter()
+        .filter_map(|id| database.get_track(*id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if tracks.is_empty() {
+        return;
+    }
+
+    let _ = sender.send(AppEvent::MetadataShow(tracks)).await;
+}
+
+pub async fn show_lyrics(id: u32, sender: &Sender<AppEvent>, ipod_path: String) {
+    let lyrics = find_track_file(&ipod_path, id).and_then(|p| lyrics::embedded_lyrics(&p));
+
+    let _ = sender.send(AppEvent::LyricsGot((id, lyrics))).await;
+}
+
+pub async fn show_cover(
+    id: u32,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+) {
+    let cover = database
+        .get_track(id)
+        .and_then(|t| ithmb::read_cover(t.data.dbid, &ipod_path));
+
+    let _ = sender.send(AppEvent::CoverGot((id, cover))).await;
+}
+
+/// Saves the covers of the tracks as PNG files in ~/.lyrica/covers, named after the track and its id.
+pub async fn export_covers(
+    ids: Vec<u32>,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+) {
+    let mut dir = get_configs_dir();
+    dir.push("covers");
+    let _ = std::fs::create_dir_all(&dir);
+
+    let mut exported = 0;
+    for track in ids.iter().filter_map(|id| database.get_track(*id)) {
+        let cover = match ithmb::read_cover(track.data.dbid, &ipod_path) {
+            Some(cover) => cover,
+            None => continue,
+        };
+        // the id keeps tracks of the same name apart
+        let name = [
+            track.get_artist(),
+            " - ".to_string(),
+            track.get_title(),
+            format!(" ({}).png", track.data.unique_id),
+        ]
+        .concat()
+        .replace(['/', '\\', ':'], "_");
+        if cover.save(dir.join(name)).is_ok() {
+            exported += 1;
+        }
+    }
+
+    let _ = sender.send(AppEvent::CoversExported((exported, dir))).await;
+}
+
+pub async fn identify_tracks(
+    ids: Vec<u32>,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+) {
+    let tracks = ids
+        .iter()
+        .filter_map(|id| database.get_track(*id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let max = tracks.len() as u32;
+    let _ = sender
+        .send(AppEvent::OverallProgress((0, max, Color::LightBlue)))
+        .await;
+
+    let mut identified = Vec::new();
+    for (i, track) in tracks.into_iter().enumerate() {
+        // the tracks identified so far are still shown
+        if cancel::is_requested() {
+            break;
+        }
+        let path = find_track_file(&ipod_path, track.data.unique_id);
+        identified.push(acoustid::identify(track, path.as_deref()).await);
+        let _ = sender
+            .send(AppEvent::OverallProgress((
+                i as u32 + 1,
+                max,
+                Color::LightBlue,
+            )))
+            .await;
+    }
+
+    let _ = sender.send(AppEvent::IdentifyShow(identified)).await;
+}
+
+pub async fn musicbrainz_lookup(
+    ids: Vec<u32>,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+) {
+    let mut tracks = ids
+        .iter()
+        .filter_map(|id| database.get_track(*id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // an album lookup only makes sense for the tracks of that album
+    if let Some(album) = tracks.first().map(|t| t.get_album()) {
+        tracks.retain(|t| t.get_album() == album);
+    }
+
+    let _ = sender
+        .send(AppEvent::OverallProgress((0, 1, Color::LightBlue)))
+        .await;
+
+    let candidates = musicbrainz::search(&tracks).await;
+    let ids = tracks.iter().map(|t| t.data.unique_id).collect();
+
+    let _ = sender
+        .send(AppEvent::MusicBrainzCandidates((ids, candidates)))
+        .await;
+}
+
+pub async fn musicbrainz_release(
+    ids: Vec<u32>,
+    candidate: ReleaseCandidate,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+) {
+    let tracks = ids
+        .iter()
+        .filter_map(|id| database.get_track(*id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let _ = sender
+        .send(AppEvent::OverallProgress((0, 1, Color::LightBlue)))
+        .await;
+
+    let proposal = musicbrainz::propose(candidate, tracks).await;
+
+    let _ = sender.send(AppEvent::MusicBrainzProposal(proposal)).await;
+}
+
+pub async fn musicbrainz_apply(
+    edits: Vec<(u32, MetadataChanges, MusicBrainzIds)>,
+    write_tags: bool,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+) {
+    if write_tags {
+        for (id, _, mbids) in edits.iter() {
+            if let Some(path) = find_track_file(&ipod_path, *id) {
+                if device::touch_file(&path) {
+                    mbids.write_tags(&path);
+                }
+            }
+        }
+    }
+
+    let edits = edits
+        .into_iter()
+        .map(|(id, changes, _)| (id, changes))
+        .collect();
+    update_tracks_metadata(edits, write_tags, database, sender, ipod_path).await;
+}
+
+/// Adds or replaces the artwork of tracks already on the iPod.
+pub async fn set_artwork(
+    ids: Vec<u32>,
+    image: Option<PathBuf>,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+) {
+    let max = ids.len() as u32;
+    let _ = sender
+        .send(AppEvent::OverallProgress((0, max, Color::LightBlue)))
+        .await;
+
+    let picked = image.and_then(|p| std::fs::read(p).ok());
+    let config = load_config();
+    let mut artwork = Artwork::open(&ipod_path);
+
+    for (i, id) in ids.iter().enumerate() {
+        if cancel::is_requested() {
+            break;
+        }
+        let cover = match &picked {
+            Some(cover) => Some(cover.clone()),
+            None => match find_track_file(&ipod_path, *id) {
+                Some(path) => artwork::online(&path).await,
+                None => None,
+            },
+        };
+
+        if let (Some(cover), XSomeList::TrackList(tracks)) =
+            (cover, &mut database.find_dataset(1).child)
+        {
+            if let Some(track) = tracks.iter_mut().find(|t| t.data.unique_id == *id) {
+                if let Some(images) =
+                    render_covers(&cover, artwork.formats(), config.get_coverart())
+                {
+                    artwork.add(
+                        track.data.dbid,
+                        util::hash(&cover),
+                        cover.len() as u32,
+                        &images,
+                        &ipod_path,
+                    );
+
+                    track.data.artwork_size = cover.len() as u32;
+                    track.data.has_artwork = 1;
+                    track.data.artwork_count = 1;
+                }
+            }
+        }
+
+        let _ = sender
+            .send(AppEvent::OverallProgress((
+                i as u32 + 1,
+                max,
+                Color::LightBlue,
+            )))
+            .await;
+    }
+
+    artwork.save(&ipod_path);
+
+    let _ = sender
+        .send(AppEvent::SwitchScreen(AppState::MainScreen))
+        .await;
+
+    let _ = sender
+        .send(AppEvent::ITunesParsed(get_playlists(database)))
+        .await;
+
+    overwrite_database(database, &ipod_path);
+}
+
+pub async fn update_metadata(
+    ids: Vec<u32>,
+    changes: MetadataChanges,
+    write_tags: bool,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+) {
+    let edits = ids.into_iter().map(|id| (id, changes.clone())).collect();
+    update_tracks_metadata(edits, write_tags, database, sender, ipod_path).await;
+}
+
+/// Applies a separate set of changes to every track, e.g. accepted lookup results.
+pub async fn update_tracks_metadata(
+    edits: Vec<(u32, MetadataChanges)>,
+    write_tags: bool,
+    database: &mut XDatabase,
+    sender: &Sender<AppEvent>,
+    ipod_path: String,
+) {
+    let max = edits.len() as u32;
+    let _ = sender
+        .send(AppEvent::OverallProgress((0, max, Color::LightBlue)))
+        .await;
+
+    for (i, (id, changes)) in edits.iter().enumerate() {
+        // the tracks done so far are saved
+        if cancel::is_requested() {
+            break;
+        }
+        if let XSomeList::TrackList(tracks) = &mut database.find_dataset(1).child {
+            if let Some(track) = tracks.iter_mut().find(|t| t.data.unique_id == *id) {
+                metadata::apply_changes(track, changes);
+            }
+        }
+        if write_tags {
+            if let Some(path) = find_track_file(&ipod_path, *id) {
+                if device::touch_file(&path) {
+                    metadata::write_file_tags(&path, changes);
+                }
+            }
+        }
+        let _ = sender
+            .send(AppEvent::OverallProgress((
+                i as u32 + 1,
+                max,
+                Color::LightBlue,
+            )))
+            .await;
+    }
+
+    let _ = sender
+        .send(AppEvent::SwitchScreen(AppState::MainScreen))
+        .await;
+
+    let _ = sender
+        .send(AppEvent::ITunesParsed(get_playlists(database)))
+        .await;
+
+    overwrite_database(database, &ipod_path);
+}
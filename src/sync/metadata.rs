@@ -0,0 +1,616 @@
+use audiotags::Tag;
+use id3::frame::{Comment, Popularimeter};
+use id3::TagLike;
+use itunesdb::xobjects::XTrackItem;
+use mp4ameta::{AdvisoryRating, Data, Fourcc};
+use std::collections::HashMap;
+use std::path::Path;
+use strum::IntoEnumIterator;
+use strum_macros::{EnumCount as EnumCountMacro, EnumIter};
+
+// string argument (mhod) types of a track item
+pub const ARG_COMMENT: u32 = 8;
+pub const ARG_COMPOSER: u32 = 12;
+pub const ARG_GROUPING: u32 = 13;
+pub const ARG_ALBUM_ARTIST: u32 = 22;
+pub const ARG_SORT_ARTIST: u32 = 23;
+pub const ARG_SORT_TITLE: u32 = 27;
+pub const ARG_SORT_ALBUM: u32 = 28;
+pub const ARG_SORT_ALBUM_ARTIST: u32 = 29;
+pub const ARG_SORT_COMPOSER: u32 = 30;
+
+#[derive(Debug, EnumCountMacro, EnumIter, Eq, Hash, PartialEq, Clone, Copy)]
+pub enum MetadataField {
+    Title,
+    Artist,
+    AlbumArtist,
+    Album,
+    Genre,
+    Composer,
+    Year,
+    TrackNumber,
+    TotalTracks,
+    DiscNumber,
+    TotalDiscs,
+    Comment,
+    Rating,
+    Bpm,
+    Compilation,
+}
+
+impl From<MetadataField> for String {
+    fn from(value: MetadataField) -> Self {
+        match value {
+            MetadataField::Title => "Title",
+            MetadataField::Artist => "Artist",
+            MetadataField::AlbumArtist => "Album Artist",
+            MetadataField::Album => "Album",
+            MetadataField::Genre => "Genre",
+            MetadataField::Composer => "Composer",
+            MetadataField::Year => "Year",
+            MetadataField::TrackNumber => "Track Number",
+            MetadataField::TotalTracks => "Total Tracks",
+            MetadataField::DiscNumber => "Disc Number",
+            MetadataField::TotalDiscs => "Total Discs",
+            MetadataField::Comment => "Comment",
+            MetadataField::Rating => "Rating (0-5)",
+            MetadataField::Bpm => "BPM",
+            MetadataField::Compilation => "Compilation (0/1)",
+        }
+        .to_string()
+    }
+}
+
+impl MetadataField {
+    pub fn is_numeric(&self) -> bool {
+        !matches!(
+            self,
+            MetadataField::Title
+                | MetadataField::Artist
+                | MetadataField::AlbumArtist
+                | MetadataField::Album
+                | MetadataField::Genre
+                | MetadataField::Composer
+                | MetadataField::Comment
+        )
+    }
+}
+
+/// Only the fields which were changed by the user, so batch edits leave the rest untouched.
+pub type MetadataChanges = HashMap<MetadataField, String>;
+
+pub fn get_arg(track: &XTrackItem, arg_type: u32) -> String {
+    track
+        .args
+        .iter()
+        .find(|a| a.arg_type == arg_type)
+        .map(|a| a.val.clone())
+        .unwrap_or_default()
+}
+
+fn number_or_empty(n: u32) -> String {
+    if n == 0 {
+        String::new()
+    } else {
+        n.to_string()
+    }
+}
+
+pub fn get_field(track: &XTrackItem, field: MetadataField) -> String {
+    match field {
+        MetadataField::Title => track.get_title(),
+        MetadataField::Artist => track.get_artist(),
+        MetadataField::AlbumArtist => get_arg(track, ARG_ALBUM_ARTIST),
+        MetadataField::Album => track.get_album(),
+        MetadataField::Genre => track.get_genre(),
+        MetadataField::Composer => get_arg(track, ARG_COMPOSER),
+        MetadataField::Year => number_or_empty(track.data.year),
+        MetadataField::TrackNumber => number_or_empty(track.data.track_number),
+        MetadataField::TotalTracks => number_or_empty(track.data.total_tracks),
+        MetadataField::DiscNumber => number_or_empty(track.data.disc_number),
+        MetadataField::TotalDiscs => number_or_empty(track.data.total_discs),
+        MetadataField::Comment => get_arg(track, ARG_COMMENT),
+        MetadataField::Rating => (track.data.rating / 20).to_string(),
+        MetadataField::Bpm => number_or_empty(track.data.bpm as u32),
+        MetadataField::Compilation => track.data.compilation_flag.to_string(),
+    }
+}
+
+/// A string covering every editable field, used to tell whether a track was modified.
+pub fn fingerprint(track: &XTrackItem) -> String {
+    MetadataField::iter()
+        .map(|field| get_field(track, field))
+        .collect::<Vec<String>>()
+        .join("\u{1f}")
+}
+
+pub fn apply_changes(track: &mut XTrackItem, changes: &MetadataChanges) {
+    for (field, value) in changes.iter() {
+        let number: u32 = value.trim().parse().unwrap_or(0);
+        match field {
+            MetadataField::Title => track.set_title(value.clone()),
+            MetadataField::Artist => track.set_artist(value.clone()),
+            MetadataField::AlbumArtist => track.update_arg(ARG_ALBUM_ARTIST, value.clone()),
+            MetadataField::Album => track.set_album(value.clone()),
+            MetadataField::Genre => track.set_genre(value.clone()),
+            MetadataField::Composer => track.update_arg(ARG_COMPOSER, value.clone()),
+            MetadataField::Year => track.data.year = number,
+            MetadataField::TrackNumber => track.data.track_number = number,
+            MetadataField::TotalTracks => track.data.total_tracks = number,
+            MetadataField::DiscNumber => track.data.disc_number = number,
+            MetadataField::TotalDiscs => track.data.total_discs = number,
+            MetadataField::Comment => track.update_arg(ARG_COMMENT, value.clone()),
+            MetadataField::Rating => track.data.rating = (number.min(5) * 20) as u8,
+            MetadataField::Bpm => track.data.bpm = number.min(u16::MAX as u32) as u16,
+            MetadataField::Compilation => track.data.compilation_flag = (number > 0) as u8,
+        }
+    }
+}
+
+// the iPod keeps star ratings in its database, other players read them from these
+const POPM_USER: &str = "Windows Media Player 9 Series";
+const MP4_RATING: Fourcc = Fourcc(*b"rate");
+
+fn is_mp4(path: &Path) -> bool {
+    path.extension().is_some_and(|e| {
+        matches!(
+            e.to_str().unwrap_or_default().to_lowercase().as_str(),
+            "m4a" | "m4b" | "mp4"
+        )
+    })
+}
+
+/// Writes the changed fields into the tags of the file itself, MP4 atoms or ID3.
+pub fn write_file_tags(path: &Path, changes: &MetadataChanges) {
+    if is_mp4(path) {
+        write_mp4_tags(path, changes);
+    } else {
+        write_id3_tags(path, changes);
+    }
+}
+
+fn write_mp4_tags(path: &Path, changes: &MetadataChanges) {
+    let mut tag = match mp4ameta::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(_e) => return,
+    };
+    for (field, value) in changes.iter() {
+        let number: u16 = value.trim().parse().unwrap_or(0);
+        match field {
+            MetadataField::Title => tag.set_title(value.clone()),
+            MetadataField::Artist => tag.set_artist(value.clone()),
+            MetadataField::AlbumArtist => tag.set_album_artist(value.clone()),
+            MetadataField::Album => tag.set_album(value.clone()),
+            MetadataField::Genre => tag.set_genre(value.clone()),
+            MetadataField::Composer => tag.set_composer(value.clone()),
+            MetadataField::Comment => tag.set_comment(value.clone()),
+            MetadataField::Year if number == 0 => tag.remove_year(),
+            MetadataField::Year => tag.set_year(number.to_string()),
+            MetadataField::TrackNumber if number == 0 => tag.remove_track_number(),
+            MetadataField::TrackNumber => tag.set_track_number(number),
+            MetadataField::TotalTracks if number == 0 => tag.remove_total_tracks(),
+            MetadataField::TotalTracks => tag.set_total_tracks(number),
+            MetadataField::DiscNumber if number == 0 => tag.remove_disc_number(),
+            MetadataField::DiscNumber => tag.set_disc_number(number),
+            MetadataField::TotalDiscs if number == 0 => tag.remove_total_discs(),
+            MetadataField::TotalDiscs => tag.set_total_discs(number),
+            MetadataField::Bpm if number == 0 => tag.remove_bpm(),
+            MetadataField::Bpm => tag.set_bpm(number),
+            MetadataField::Compilation if number == 0 => tag.remove_compilation(),
+            MetadataField::Compilation => tag.set_compilation(),
+            MetadataField::Rating if number == 0 => tag.remove_data_of(&MP4_RATING),
+            MetadataField::Rating => {
+                tag.set_data(MP4_RATING, Data::Utf8((number.min(5) * 20).to_string()))
+            }
+        }
+    }
+    let _ = tag.write_to_path(path);
+}
+
+// covers MP3 as well as the ID3 chunks of AIFF and WAV files
+fn write_id3_tags(path: &Path, changes: &MetadataChanges) {
+    let mut tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+        Err(_e) => return,
+    };
+    for (field, value) in changes.iter() {
+        let number: u32 = value.trim().parse().unwrap_or(0);
+        match field {
+            MetadataField::Title => tag.set_title(value.clone()),
+            MetadataField::Artist => tag.set_artist(value.clone()),
+            MetadataField::AlbumArtist => tag.set_album_artist(value.clone()),
+            MetadataField::Album => tag.set_album(value.clone()),
+            MetadataField::Genre => tag.set_genre(value.clone()),
+            MetadataField::Composer => tag.set_text("TCOM", value.clone()),
+            MetadataField::Comment => {
+                tag.remove_comment(Some(""), None);
+                tag.add_frame(Comment {
+                    lang: "eng".to_string(),
+                    description: String::new(),
+                    text: value.clone(),
+                });
+            }
+            MetadataField::Year if number == 0 => tag.remove_year(),
+            MetadataField::Year => tag.set_year(number as i32),
+            MetadataField::TrackNumber if number == 0 => tag.remove_track(),
+            MetadataField::TrackNumber => tag.set_track(number),
+            MetadataField::TotalTracks if number == 0 => tag.remove_total_tracks(),
+            MetadataField::TotalTracks => tag.set_total_tracks(number),
+            MetadataField::DiscNumber if number == 0 => tag.remove_disc(),
+            MetadataField::DiscNumber => tag.set_disc(number),
+            MetadataField::TotalDiscs if number == 0 => tag.remove_total_discs(),
+            MetadataField::TotalDiscs => tag.set_total_discs(number),
+            MetadataField::Bpm => tag.set_text("TBPM", value.trim()),
+            MetadataField::Compilation => {
+                tag.set_text("TCMP", if value.trim() == "1" { "1" } else { "0" })
+            }
+            MetadataField::Rating => {
+                tag.remove("POPM");
+                if number > 0 {
+                    tag.add_frame(Popularimeter {
+                        user: POPM_USER.to_string(),
+                        rating: stars_to_popm(number.min(5) as u8),
+                        counter: 0,
+                    });
+                }
+            }
+        }
+    }
+    let version = tag.version();
+    let _ = tag.write_to_path(path, version);
+}
+
+#[derive(Default)]
+pub struct ImportedTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub composer: Option<String>,
+    pub comment: Option<String>,
+    pub grouping: Option<String>,
+    pub year: Option<i32>,
+    pub track_number: Option<u16>,
+    pub total_tracks: Option<u16>,
+    pub disc_number: Option<u16>,
+    pub total_discs: Option<u16>,
+    pub bpm: Option<u16>,
+    pub compilation: bool,
+    pub explicit: bool,
+    // 0-5 stars
+    pub rating: Option<u8>,
+    pub sort_title: Option<String>,
+    pub sort_artist: Option<String>,
+    pub sort_album: Option<String>,
+    pub sort_album_artist: Option<String>,
+    pub sort_composer: Option<String>,
+    pub cover: Option<Vec<u8>>,
+}
+
+fn owned(s: Option<&str>) -> Option<String> {
+    s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// Drops a leading article the same way iTunes does when it generates sort fields.
+pub fn sort_name(name: &str) -> Option<String> {
+    let lower = name.to_lowercase();
+    ["the ", "a ", "an "]
+        .iter()
+        .find(|article| lower.starts_with(*article) && name.len() > article.len())
+        .map(|article| name[article.len()..].trim_start().to_string())
+}
+
+// the values Windows Media Player writes, which most players go by
+fn stars_to_popm(stars: u8) -> u8 {
+    match stars {
+        0 => 0,
+        1 => 1,
+        2 => 64,
+        3 => 128,
+        4 => 196,
+        _ => 255,
+    }
+}
+
+fn popm_to_stars(rating: u8) -> u8 {
+    match rating {
+        0 => 0,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        _ => 5,
+    }
+}
+
+fn read_mp4_tags(path: &Path) -> Option<ImportedTags> {
+    let tag = mp4ameta::Tag::read_from_path(path).ok()?;
+    // 0-100 like the database, written by lyrica and a few other players
+    let rating = tag
+        .strings_of(&MP4_RATING)
+        .next()
+        .and_then(|r| r.trim().parse::<u8>().ok())
+        .map(|r| r.min(100) / 20);
+    Some(ImportedTags {
+        title: owned(tag.title()),
+        artist: owned(tag.artist()),
+        album: owned(tag.album()),
+        album_artist: owned(tag.album_artist()),
+        genre: owned(tag.genre()),
+        composer: owned(tag.composer()),
+        comment: owned(tag.comment()),
+        grouping: owned(tag.grouping()),
+        year: tag
+            .year()
+            .and_then(|y| y.get(..4))
+            .and_then(|y| y.parse().ok()),
+        track_number: tag.track_number(),
+        total_tracks: tag.total_tracks(),
+        disc_number: tag.disc_number(),
+        total_discs: tag.total_discs(),
+        bpm: tag.bpm(),
+        compilation: tag.compilation(),
+        explicit: matches!(tag.advisory_rating(), Some(AdvisoryRating::Explicit)),
+        rating,
+        sort_title: owned(tag.title_sort_order()),
+        sort_artist: owned(tag.artist_sort_order()),
+        sort_album: owned(tag.album_sort_order()),
+        sort_album_artist: owned(tag.album_artist_sort_order()),
+        sort_composer: owned(tag.composer_sort_order()),
+        cover: tag.artwork().map(|a| a.data.to_vec()),
+    })
+}
+
+// covers MP3 as well as the ID3 chunks of AIFF and WAV files
+fn read_id3_tags(path: &Path) -> Option<ImportedTags> {
+    let tag = id3::Tag::read_from_path(path).ok()?;
+    let text = |id: &str| owned(tag.get(id).and_then(|f| f.content().text()));
+    let extended = |description: &str| {
+        tag.extended_texts()
+            .find(|t| t.description.eq_ignore_ascii_case(description))
+            .map(|t| t.value.trim().to_string())
+    };
+    let cover = tag
+        .pictures()
+        .find(|p| p.picture_type == id3::frame::PictureType::CoverFront)
+        .or_else(|| tag.pictures().next())
+        .map(|p| p.data.clone());
+    let comment = tag
+        .comments()
+        .find(|c| c.description.is_empty())
+        .or_else(|| tag.comments().next())
+        .and_then(|c| owned(Some(&c.text)));
+
+    Some(ImportedTags {
+        title: owned(tag.title()),
+        artist: owned(tag.artist()),
+        album: owned(tag.album()),
+        album_artist: owned(tag.album_artist()),
+        genre: tag.genre_parsed().map(|g| g.to_string()),
+        composer: text("TCOM"),
+        comment,
+        grouping: text("GRP1").or_else(|| text("TIT1")),
+        year: tag.year(),
+        track_number: tag.track().map(|n| n as u16),
+        total_tracks: tag.total_tracks().map(|n| n as u16),
+        disc_number: tag.disc().map(|n| n as u16),
+        total_discs: tag.total_discs().map(|n| n as u16),
+        bpm: text("TBPM")
+            .and_then(|b| b.parse::<f32>().ok())
+            .map(|b| b.round() as u16),
+        compilation: text("TCMP").is_some_and(|c| c == "1"),
+        explicit: extended("ITUNESADVISORY").is_some_and(|e| e == "1"),
+        rating: tag
+            .get("POPM")
+            .and_then(|f| f.content().popularimeter())
+            .map(|p| popm_to_stars(p.rating)),
+        sort_title: text("TSOT"),
+        sort_artist: text("TSOP"),
+        sort_album: text("TSOA"),
+        sort_album_artist: text("TSO2"),
+        sort_composer: text("TSOC"),
+        cover,
+    })
+}
+
+fn read_generic_tags(path: &Path) -> Option<ImportedTags> {
+    let tag = Tag::new().read_from_path(path).ok()?;
+    Some(ImportedTags {
+        title: owned(tag.title()),
+        artist: owned(tag.artist()),
+        album: owned(tag.album_title()),
+        album_artist: owned(tag.album_artist()),
+        genre: owned(tag.genre()),
+        composer: owned(tag.composer()),
+        comment: owned(tag.comment()),
+        year: tag.year(),
+        track_number: tag.track_number(),
+        total_tracks: tag.total_tracks(),
+        disc_number: tag.disc_number(),
+        total_discs: tag.total_discs(),
+        cover: tag.album_cover().map(|a| a.data.to_vec()),
+        ..Default::default()
+    })
+}
+
+pub fn read_tags(path: &Path) -> ImportedTags {
+    let tags = if is_mp4(path) {
+        read_mp4_tags(path)
+    } else {
+        read_id3_tags(path)
+    };
+
+    let mut tags = tags.or_else(|| read_generic_tags(path)).unwrap_or_default();
+
+    if tags.sort_artist.is_none() {
+        tags.sort_artist = tags.artist.as_deref().and_then(sort_name);
+    }
+    if tags.sort_album_artist.is_none() {
+        tags.sort_album_artist = tags.album_artist.as_deref().and_then(sort_name);
+    }
+    if tags.sort_album.is_none() {
+        tags.sort_album = tags.album.as_deref().and_then(sort_name);
+    }
+    if tags.sort_title.is_none() {
+        tags.sort_title = tags.title.as_deref().and_then(sort_name);
+    }
+
+    tags
+}
+
+impl ImportedTags {
+    /// Everything but title and artwork, which the importer handles on its own.
+    pub fn apply(&self, track: &mut XTrackItem) {
+        let args = [
+            (ARG_ALBUM_ARTIST, &self.album_artist),
+            (ARG_COMPOSER, &self.composer),
+            (ARG_COMMENT, &self.comment),
+            (ARG_GROUPING, &self.grouping),
+            (ARG_SORT_TITLE, &self.sort_title),
+            (ARG_SORT_ARTIST, &self.sort_artist),
+            (ARG_SORT_ALBUM, &self.sort_album),
+            (ARG_SORT_ALBUM_ARTIST, &self.sort_album_artist),
+            (ARG_SORT_COMPOSER, &self.sort_composer),
+        ];
+        for (arg_type, value) in args {
+            if let Some(value) = value {
+                track.update_arg(arg_type, value.clone());
+            }
+        }
+
+        if let Some(artist) = &self.artist {
+            track.set_artist(artist.clone());
+        }
+        if let Some(album) = &self.album {
+            track.set_album(album.clone());
+        }
+        if let Some(genre) = &self.genre {
+            track.set_genre(genre.clone());
+        }
+        if let Some(track_number) = self.track_number {
+            track.data.track_number = track_number as u32;
+        }
+        if let Some(total_tracks) = self.total_tracks {
+            track.data.total_tracks = total_tracks as u32;
+        }
+        if let Some(disc_number) = self.disc_number {
+            track.data.disc_number = disc_number as u32;
+        }
+        if let Some(total_discs) = self.total_discs {
+            track.data.total_discs = total_discs as u32;
+        }
+        if let Some(bpm) = self.bpm {
+            track.data.bpm = bpm;
+        }
+        if let Some(rating) = self.rating {
+            track.data.rating = rating.min(5) * 20;
+        }
+        track.data.compilation_flag = self.compilation as u8;
+        track.data.explicit_flag = self.explicit as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    // an empty file, id3 writes a tag into it as it would in front of the audio
+    fn empty_file(name: &std::ffi::OsStr) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lyrica-metadata-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, []).unwrap();
+        path
+    }
+
+    #[test]
+    fn sort_name_drops_articles() {
+        assert_eq!(sort_name("The Beatles").as_deref(), Some("Beatles"));
+        assert_eq!(
+            sort_name("a Tribe Called Quest").as_deref(),
+            Some("Tribe Called Quest")
+        );
+        assert_eq!(sort_name("An  Horse").as_deref(), Some("Horse"));
+        assert_eq!(sort_name("Theatre of Tragedy"), None);
+        assert_eq!(sort_name("Anathema"), None);
+        // nothing would be left
+        assert_eq!(sort_name("The "), None);
+        assert_eq!(sort_name(""), None);
+    }
+
+    #[test]
+    fn stars_survive_popm() {
+        for stars in 0..=5 {
+            assert_eq!(popm_to_stars(stars_to_popm(stars)), stars);
+        }
+        assert_eq!(popm_to_stars(255), 5);
+        assert_eq!(popm_to_stars(100), 3);
+    }
+
+    #[test]
+    fn mp4_by_extension() {
+        assert!(is_mp4(Path::new("a/track.M4A")));
+        assert!(is_mp4(Path::new("book.m4b")));
+        assert!(!is_mp4(Path::new("track.mp3")));
+        assert!(!is_mp4(Path::new("m4a")));
+    }
+
+    #[test]
+    fn id3_tags_round_trip() {
+        let path = empty_file("round_trip.mp3".as_ref());
+        let changes: MetadataChanges = [
+            (MetadataField::Title, "The Title"),
+            (MetadataField::Composer, "Someone"),
+            (MetadataField::Comment, "a comment"),
+            (MetadataField::Year, "1999"),
+            (MetadataField::TrackNumber, "3"),
+            (MetadataField::TotalTracks, "12"),
+            (MetadataField::Rating, "4"),
+            (MetadataField::Bpm, "120"),
+            (MetadataField::Compilation, "1"),
+        ]
+        .into_iter()
+        .map(|(f, v)| (f, v.to_string()))
+        .collect();
+        write_file_tags(&path, &changes);
+
+        let tags = read_tags(&path);
+        assert_eq!(tags.title.as_deref(), Some("The Title"));
+        assert_eq!(tags.sort_title.as_deref(), Some("Title"));
+        assert_eq!(tags.composer.as_deref(), Some("Someone"));
+        assert_eq!(tags.comment.as_deref(), Some("a comment"));
+        assert_eq!(tags.year, Some(1999));
+        assert_eq!(tags.track_number, Some(3));
+        assert_eq!(tags.total_tracks, Some(12));
+        assert_eq!(tags.rating, Some(4));
+        assert_eq!(tags.bpm, Some(120));
+        assert!(tags.compilation);
+
+        // clearing the rating and the track number takes them out of the file
+        let changes: MetadataChanges = [
+            (MetadataField::Rating, "0".to_string()),
+            (MetadataField::TrackNumber, String::new()),
+        ]
+        .into_iter()
+        .collect();
+        write_file_tags(&path, &changes);
+        let tags = read_tags(&path);
+        assert_eq!(tags.rating, None);
+        assert_eq!(tags.track_number, None);
+        assert_eq!(tags.title.as_deref(), Some("The Title"));
+    }
+
+    #[test]
+    fn non_utf8_paths() {
+        let path = empty_file(std::ffi::OsStr::from_bytes(b"caf\xe9.mp3"));
+        let changes: MetadataChanges = [(MetadataField::Title, "Caf\u{e9}".to_string())]
+            .into_iter()
+            .collect();
+        write_file_tags(&path, &changes);
+        assert_eq!(read_tags(&path).title.as_deref(), Some("Caf\u{e9}"));
+    }
+}
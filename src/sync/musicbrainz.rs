@@ -0,0 +1,494 @@
+use crate::config::load_config;
+use crate::sync::metadata::{self, MetadataChanges, MetadataField};
+use crate::sync::web;
+use id3::TagLike;
+use itunesdb::xobjects::XTrackItem;
+use mp4ameta::FreeformIdent;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+// every request waits here until the configured interval since the last one has passed
+static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::const_new(None);
+
+// tag names used by MusicBrainz Picard, so other tools find the ids
+const TAG_RECORDING_ID: &str = "MusicBrainz Track Id";
+const TAG_RELEASE_TRACK_ID: &str = "MusicBrainz Release Track Id";
+const TAG_RELEASE_ID: &str = "MusicBrainz Album Id";
+const TAG_RELEASE_GROUP_ID: &str = "MusicBrainz Release Group Id";
+const TAG_ARTIST_ID: &str = "MusicBrainz Artist Id";
+const TAG_ALBUM_ARTIST_ID: &str = "MusicBrainz Album Artist Id";
+
+#[derive(Debug, Clone)]
+pub struct ReleaseCandidate {
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub date: String,
+    pub country: String,
+    pub track_count: u32,
+    pub score: u32,
+    // set when the release was found through one of its recordings
+    pub recording_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MusicBrainzIds {
+    pub recording: String,
+    pub release_track: String,
+    pub release: String,
+    pub release_group: String,
+    pub artist: String,
+    pub album_artist: String,
+}
+
+pub struct TrackProposal {
+    pub track: XTrackItem,
+    // None when the release has no track matching this one
+    pub changes: Option<MetadataChanges>,
+    pub ids: MusicBrainzIds,
+}
+
+pub struct ReleaseProposal {
+    pub release: ReleaseCandidate,
+    pub tracks: Vec<TrackProposal>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistCredit {
+    name: String,
+    joinphrase: Option<String>,
+    artist: Option<ArtistRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArtistRef {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseGroupRef {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    id: String,
+    #[serde(default)]
+    score: u32,
+    title: String,
+    date: Option<String>,
+    country: Option<String>,
+    #[serde(rename = "track-count", default)]
+    track_count: u32,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    #[serde(rename = "release-group")]
+    release_group: Option<ReleaseGroupRef>,
+    #[serde(default)]
+    media: Vec<Medium>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Medium {
+    #[serde(default)]
+    position: u32,
+    #[serde(default)]
+    tracks: Vec<Track>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Track {
+    id: String,
+    #[serde(default)]
+    position: u32,
+    title: String,
+    length: Option<u64>,
+    #[serde(rename = "artist-credit", default)]
+    artist_credit: Vec<ArtistCredit>,
+    recording: Option<RecordingRef>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordingRef {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recording {
+    id: String,
+    #[serde(default)]
+    score: u32,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseSearch {
+    releases: Vec<Release>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordingSearch {
+    recordings: Vec<Recording>,
+}
+
+fn artist_credit(credits: &[ArtistCredit]) -> String {
+    credits
+        .iter()
+        .map(|c| [c.name.as_str(), c.joinphrase.as_deref().unwrap_or_default()].concat())
+        .collect::<Vec<String>>()
+        .concat()
+}
+
+fn first_artist_id(credits: &[ArtistCredit]) -> String {
+    credits
+        .iter()
+        .find_map(|c| c.artist.as_ref().map(|a| a.id.clone()))
+        .unwrap_or_default()
+}
+
+// Lucene syntax characters have to be escaped inside a query term
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if "+-&|!(){}[]^\"~*?:\\/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+async fn get<T: DeserializeOwned>(path: &str, params: Vec<(&str, String)>) -> Result<T, String> {
+    let config = load_config();
+    let musicbrainz = config.get_musicbrainz();
+    let url = [musicbrainz.base_url.trim_end_matches('/'), "/", path].concat();
+    let interval = Duration::from_millis(musicbrainz.min_interval_ms);
+
+    let mut last = LAST_REQUEST.lock().await;
+    if let Some(elapsed) = last.map(|l| l.elapsed()) {
+        if elapsed < interval {
+            tokio::time::sleep(interval - elapsed).await;
+        }
+    }
+    let mut params = params;
+    params.push(("fmt", "json".to_string()));
+    let response = web::request(&url, &params, false).await;
+    *last = Some(Instant::now());
+    response
+}
+
+fn candidate(release: &Release, score: u32, recording_id: Option<String>) -> ReleaseCandidate {
+    ReleaseCandidate {
+        id: release.id.clone(),
+        title: release.title.clone(),
+        artist: artist_credit(&release.artist_credit),
+        date: release.date.clone().unwrap_or_default(),
+        country: release.country.clone().unwrap_or_default(),
+        track_count: release.track_count,
+        score,
+        recording_id,
+    }
+}
+
+fn non_empty(value: String) -> Option<String> {
+    Some(value).filter(|v| !v.trim().is_empty())
+}
+
+/// Releases matching the album of the tracks, or the releases of the recording for a single track.
+pub async fn search(tracks: &[XTrackItem]) -> Result<Vec<ReleaseCandidate>, String> {
+    let first = tracks.first().ok_or("no tracks selected")?;
+    let album_artist = non_empty(metadata::get_field(first, MetadataField::AlbumArtist))
+        .or(non_empty(first.get_artist()));
+
+    if tracks.len() > 1 {
+        let album = non_empty(first.get_album()).ok_or("the tracks have no album to look up")?;
+        let mut query = ["release:\"", &escape(&album), "\""].concat();
+        if let Some(artist) = &album_artist {
+            query = [query.as_str(), " AND artist:\"", &escape(artist), "\""].concat();
+        }
+        let search: ReleaseSearch = get(
+            "release",
+            vec![("query", query), ("limit", "15".to_string())],
+        )
+        .await?;
+        return Ok(search
+            .releases
+            .iter()
+            .map(|r| candidate(r, r.score, None))
+            .collect());
+    }
+
+    let title = non_empty(first.get_title()).ok_or("the track has no title to look up")?;
+    let mut query = ["recording:\"", &escape(&title), "\""].concat();
+    if let Some(artist) = non_empty(first.get_artist()) {
+        query = [query.as_str(), " AND artist:\"", &escape(&artist), "\""].concat();
+    }
+    if first.data.length > 0 {
+        // durations are in ms, a few seconds either way still counts
+        let length = first.data.length as u64;
+        query = format!(
+            "{} AND dur:[{} TO {}]",
+            query,
+            length.saturating_sub(5000),
+            length + 5000
+        );
+    }
+    let search: RecordingSearch = get(
+        "recording",
+        vec![("query", query), ("limit", "10".to_string())],
+    )
+    .await?;
+
+    let mut candidates: Vec<ReleaseCandidate> = Vec::new();
+    for recording in search.recordings.iter() {
+        for release in recording.releases.iter() {
+            if !candidates.iter().any(|c| c.id == release.id) {
+                candidates.push(candidate(
+                    release,
+                    recording.score,
+                    Some(recording.id.clone()),
+                ));
+            }
+        }
+    }
+    Ok(candidates)
+}
+
+struct ReleaseTrack<'a> {
+    disc: u32,
+    total_discs: u32,
+    track: &'a Track,
+    total_tracks: u32,
+}
+
+fn match_track<'a>(
+    track: &XTrackItem,
+    tracks: &'a [ReleaseTrack<'a>],
+    recording_id: Option<&str>,
+) -> Option<&'a ReleaseTrack<'a>> {
+    let by_recording = recording_id.and_then(|id| {
+        tracks
+            .iter()
+            .find(|t| t.track.recording.as_ref().is_some_and(|r| r.id == id))
+    });
+    let by_position = || {
+        let disc = track.data.disc_number.max(1);
+        tracks.iter().find(|t| {
+            track.data.track_number != 0
+                && t.disc == disc
+                && t.track.position == track.data.track_number
+        })
+    };
+    let by_title = || {
+        let title = track.get_title().to_lowercase();
+        tracks
+            .iter()
+            .find(|t| t.track.title.to_lowercase() == title)
+    };
+    let by_length = || {
+        tracks
+            .iter()
+            .filter_map(|t| {
+                let distance = t.track.length?.abs_diff(track.data.length as u64);
+                Some((distance, t))
+            })
+            .filter(|(distance, _)| *distance < 3000)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, t)| t)
+    };
+    by_recording
+        .or_else(by_position)
+        .or_else(by_title)
+        .or_else(by_length)
+}
+
+/// Fetches the release and lines its tracks up with ours.
+pub async fn propose(
+    candidate: ReleaseCandidate,
+    tracks: Vec<XTrackItem>,
+) -> Result<ReleaseProposal, String> {
+    let release: Release = get(
+        &["release/", &candidate.id].concat(),
+        vec![(
+            "inc",
+            "recordings artist-credits release-groups".to_string(),
+        )],
+    )
+    .await?;
+
+    let total_discs = release.media.len() as u32;
+    let release_tracks: Vec<ReleaseTrack> = release
+        .media
+        .iter()
+        .flat_map(|medium| {
+            medium.tracks.iter().map(move |t| ReleaseTrack {
+                disc: medium.position.max(1),
+                total_discs,
+                track: t,
+                total_tracks: medium.tracks.len() as u32,
+            })
+        })
+        .collect();
+
+    let album_artist = artist_credit(&release.artist_credit);
+    let year = release
+        .date
+        .as_deref()
+        .and_then(|d| d.get(0..4))
+        .unwrap_or_default()
+        .to_string();
+
+    let proposals = tracks
+        .into_iter()
+        .map(|track| {
+            let matched =
+                match match_track(&track, &release_tracks, candidate.recording_id.as_deref()) {
+                    Some(matched) => matched,
+                    None => {
+                        return TrackProposal {
+                            track,
+                            changes: None,
+                            ids: MusicBrainzIds::default(),
+                        }
+                    }
+                };
+            let artist = if matched.track.artist_credit.is_empty() {
+                album_artist.clone()
+            } else {
+                artist_credit(&matched.track.artist_credit)
+            };
+            let proposed = [
+                (MetadataField::Title, matched.track.title.clone()),
+                (MetadataField::Artist, artist),
+                (MetadataField::Album, release.title.clone()),
+                (MetadataField::AlbumArtist, album_artist.clone()),
+                (MetadataField::Year, year.clone()),
+                (
+                    MetadataField::TrackNumber,
+                    matched.track.position.to_string(),
+                ),
+                (MetadataField::TotalTracks, matched.total_tracks.to_string()),
+                (MetadataField::DiscNumber, matched.disc.to_string()),
+                (MetadataField::TotalDiscs, matched.total_discs.to_string()),
+            ];
+            let changes = proposed
+                .into_iter()
+                .filter(|(field, value)| {
+                    !value.is_empty() && *value != metadata::get_field(&track, *field)
+                })
+                .collect();
+            let ids = MusicBrainzIds {
+                recording: matched
+                    .track
+                    .recording
+                    .as_ref()
+                    .map(|r| r.id.clone())
+                    .unwrap_or_default(),
+                release_track: matched.track.id.clone(),
+                release: release.id.clone(),
+                release_group: release
+                    .release_group
+                    .as_ref()
+                    .map(|g| g.id.clone())
+                    .unwrap_or_default(),
+                artist: if matched.track.artist_credit.is_empty() {
+                    first_artist_id(&release.artist_credit)
+                } else {
+                    first_artist_id(&matched.track.artist_credit)
+                },
+                album_artist: first_artist_id(&release.artist_credit),
+            };
+            TrackProposal {
+                track,
+                changes: Some(changes),
+                ids,
+            }
+        })
+        .collect();
+
+    Ok(ReleaseProposal {
+        release: candidate,
+        tracks: proposals,
+    })
+}
+
+fn is_mp4(path: &Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str(),
+        "m4a" | "m4b" | "mp4"
+    )
+}
+
+/// The release id Picard or an earlier lookup stored in the file.
+pub fn release_id(path: &Path) -> Option<String> {
+    let id = if is_mp4(path) {
+        mp4ameta::Tag::read_from_path(path)
+            .ok()?
+            .strings_of(&FreeformIdent::new("com.apple.iTunes", TAG_RELEASE_ID))
+            .next()
+            .map(|s| s.to_string())
+    } else {
+        id3::Tag::read_from_path(path)
+            .ok()?
+            .extended_texts()
+            .find(|t| t.description == TAG_RELEASE_ID)
+            .map(|t| t.value.clone())
+    };
+    id.map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+}
+
+impl MusicBrainzIds {
+    fn tags(&self) -> [(&'static str, &String); 6] {
+        [
+            (TAG_RECORDING_ID, &self.recording),
+            (TAG_RELEASE_TRACK_ID, &self.release_track),
+            (TAG_RELEASE_ID, &self.release),
+            (TAG_RELEASE_GROUP_ID, &self.release_group),
+            (TAG_ARTIST_ID, &self.artist),
+            (TAG_ALBUM_ARTIST_ID, &self.album_artist),
+        ]
+    }
+
+    /// Stores the ids in the file, the iTunesDB has no place for them.
+    pub fn write_tags(&self, path: &Path) {
+        if is_mp4(path) {
+            if let Ok(mut tag) = mp4ameta::Tag::read_from_path(path) {
+                for (name, id) in self.tags().into_iter().filter(|(_, id)| !id.is_empty()) {
+                    tag.set_data(
+                        FreeformIdent::new("com.apple.iTunes", name),
+                        mp4ameta::Data::Utf8(id.clone()),
+                    );
+                }
+                let _ = tag.write_to_path(path);
+            }
+            return;
+        }
+
+        if let Ok(mut tag) = id3::Tag::read_from_path(path) {
+            for (name, id) in self.tags().into_iter().filter(|(_, id)| !id.is_empty()) {
+                tag.remove_extended_text(Some(name), None);
+                tag.add_frame(id3::frame::ExtendedText {
+                    description: name.to_string(),
+                    value: id.clone(),
+                });
+            }
+            if !self.recording.is_empty() {
+                tag.remove_unique_file_identifier_by_owner_identifier("http://musicbrainz.org");
+                tag.add_frame(id3::frame::UniqueFileIdentifier {
+                    owner_identifier: "http://musicbrainz.org".to_string(),
+                    identifier: self.recording.as_bytes().to_vec(),
+                });
+            }
+            let _ = tag.write_to_path(path, id3::Version::Id3v24);
+        }
+    }
+}
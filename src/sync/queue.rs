@@ -0,0 +1,394 @@
+use crate::config::{
+    get_configs_dir, get_temp_dl_dir, load_config, DownloadFormat, TranscodeProfile,
+};
+use crate::dlp::{self, MediaInfo};
+use crate::sync::sync_util::{AppEvent, ImportSummary, YTPlaylist};
+use serde::{Deserialize, Serialize};
+use soundcloud::sobjects::{CloudPlaylist, CloudTrack};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use tokio::sync::mpsc::{self, Sender, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use youtube_api::objects::YoutubeVideo;
+
+#[derive(Clone)]
+pub enum QueueJob {
+    SoundCloudPlaylist(CloudPlaylist),
+    SoundCloudTrack(CloudTrack),
+    YouTubePlaylist(YTPlaylist),
+    YouTubeVideo(YoutubeVideo),
+    // pasted into the app, resolved by the worker before it downloads
+    Url(String, Option<Box<MediaInfo>>),
+}
+
+impl QueueJob {
+    pub fn title(&self) -> String {
+        match self {
+            QueueJob::SoundCloudPlaylist(playlist) => playlist.title.clone(),
+            QueueJob::SoundCloudTrack(track) => track.title.clone().unwrap_or_default(),
+            QueueJob::YouTubePlaylist(playlist) => playlist.title.clone(),
+            QueueJob::YouTubeVideo(video) => video.title.clone(),
+            QueueJob::Url(url, info) => info
+                .as_ref()
+                .and_then(|i| i.title.clone())
+                .unwrap_or(url.clone()),
+        }
+    }
+
+    /// What yt-dlp is given to download.
+    pub fn url(&self) -> Option<String> {
+        match self {
+            QueueJob::SoundCloudPlaylist(playlist) => Some(playlist.permalink_url.clone()),
+            QueueJob::SoundCloudTrack(track) => track.permalink_url.clone(),
+            QueueJob::YouTubePlaylist(playlist) => {
+                Some(["https://youtube.com", &playlist.url].concat())
+            }
+            QueueJob::YouTubeVideo(video) => {
+                Some(["https://youtube.com/watch?v=", &video.videoId].concat())
+            }
+            QueueJob::Url(url, _) => Some(url.clone()),
+        }
+    }
+
+    pub fn source(&self) -> String {
+        match self {
+            QueueJob::SoundCloudPlaylist(_) => "SoundCloud playlist".to_string(),
+            QueueJob::SoundCloudTrack(_) => "SoundCloud track".to_string(),
+            QueueJob::YouTubePlaylist(_) => "YouTube playlist".to_string(),
+            QueueJob::YouTubeVideo(_) => "YouTube video".to_string(),
+            QueueJob::Url(_, None) => "URL".to_string(),
+            QueueJob::Url(_, Some(info)) => {
+                let site = info.extractor_key.clone().unwrap_or("URL".to_string());
+                match info.entries {
+                    Some(_) => [&site, " playlist"].concat(),
+                    None => site,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum QueueStatus {
+    Queued,
+    // what yt-dlp reported last
+    Downloading(String),
+    Importing,
+    Done(ImportSummary),
+    Failed(String),
+}
+
+#[derive(Clone)]
+pub struct QueueEntry {
+    pub id: u32,
+    pub job: QueueJob,
+    pub profile: TranscodeProfile,
+    // what yt-dlp extracts to, fixed when the entry is queued so a config change doesn't strand it
+    pub format: DownloadFormat,
+    pub status: QueueStatus,
+}
+
+/// Downloads waiting for, or done with, yt-dlp. It lives in the sync service, which imports
+/// every finished download itself so the database is only ever touched from one place.
+pub struct DownloadQueue {
+    entries: Vec<QueueEntry>,
+    next_id: u32,
+    // one for every running download
+    running: HashMap<u32, CancellationToken>,
+    concurrency: usize,
+}
+
+/// An unfinished entry as it is kept in ~/.lyrica/queue.json. Everything is downloaded again
+/// by its URL, yt-dlp resumes what is left in the entry's directory.
+#[derive(Serialize, Deserialize)]
+struct SavedEntry {
+    id: u32,
+    url: String,
+    profile: TranscodeProfile,
+    // missing in queues saved before it was kept, those take the configured one
+    #[serde(default)]
+    format: Option<DownloadFormat>,
+    // why it failed, a failed entry comes back failed
+    failed: Option<String>,
+}
+
+fn queue_file() -> PathBuf {
+    let mut p = get_configs_dir();
+    p.push("queue.json");
+    p
+}
+
+/// Where yt-dlp puts the files of one entry, several entries download side by side.
+pub fn download_dir(id: u32) -> PathBuf {
+    let mut dir = get_temp_dl_dir();
+    dir.push("queue");
+    dir.push(id.to_string());
+    dir
+}
+
+impl DownloadQueue {
+    /// The queue as the last run left it, every unfinished entry is queued again.
+    pub fn restore() -> Self {
+        let saved: Vec<SavedEntry> = std::fs::read(queue_file())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        let config = load_config();
+        let entries: Vec<QueueEntry> = saved
+            .into_iter()
+            .map(|e| QueueEntry {
+                id: e.id,
+                job: QueueJob::Url(e.url, None),
+                profile: e.profile,
+                format: e.format.unwrap_or(config.get_downloader().get_format()),
+                status: match e.failed {
+                    Some(reason) => QueueStatus::Failed(reason),
+                    None => QueueStatus::Queued,
+                },
+            })
+            .collect();
+        Self {
+            next_id: entries.iter().map(|e| e.id).max().unwrap_or(0),
+            entries,
+            running: HashMap::new(),
+            concurrency: config.get_downloader().concurrency.max(1),
+        }
+    }
+
+    /// The directories of the entries, their partial downloads are kept.
+    pub fn download_dirs(&self) -> Vec<PathBuf> {
+        self.entries.iter().map(|e| download_dir(e.id)).collect()
+    }
+
+    fn save(&self) {
+        let saved: Vec<SavedEntry> = self
+            .entries
+            .iter()
+            .filter_map(|e| {
+                let failed = match &e.status {
+                    QueueStatus::Done(_) => return None,
+                    QueueStatus::Failed(reason) => Some(reason.clone()),
+                    _ => None,
+                };
+                Some(SavedEntry {
+                    id: e.id,
+                    url: e.job.url()?,
+                    profile: e.profile,
+                    format: Some(e.format),
+                    failed,
+                })
+            })
+            .collect();
+        if let Ok(data) = serde_json::to_vec(&saved) {
+            let _ = std::fs::write(queue_file(), data);
+        }
+    }
+
+    pub fn push(&mut self, job: QueueJob, profile: TranscodeProfile) {
+        self.next_id += 1;
+        self.entries.push(QueueEntry {
+            id: self.next_id,
+            job,
+            profile,
+            format: load_config().get_downloader().get_format(),
+            status: QueueStatus::Queued,
+        });
+        self.save();
+    }
+
+    pub fn get(&self, id: u32) -> Option<&QueueEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    pub fn set_status(&mut self, id: u32, status: QueueStatus) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+            if !matches!(status, QueueStatus::Downloading(_)) {
+                self.running.remove(&id);
+            }
+            entry.status = status;
+        }
+        self.save();
+    }
+
+    pub fn set_info(&mut self, id: u32, info: MediaInfo) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+            if let QueueJob::Url(url, _) = &entry.job {
+                entry.job = QueueJob::Url(url.clone(), Some(Box::new(info)));
+            }
+        }
+        self.save();
+    }
+
+    pub fn set_progress(&mut self, id: u32, progress: String) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+            if matches!(entry.status, QueueStatus::Downloading(_)) {
+                entry.status = QueueStatus::Downloading(progress);
+            }
+        }
+    }
+
+    pub fn retry(&mut self, id: u32) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.id == id) {
+            if matches!(entry.status, QueueStatus::Failed(_)) {
+                entry.status = QueueStatus::Queued;
+            }
+        }
+        self.save();
+    }
+
+    /// A running download is cancelled instead and comes back as failed, ready to be removed.
+    /// Entries being imported stay, the database is already being written.
+    pub fn remove(&mut self, id: u32) {
+        if let Some(token) = self.running.get(&id) {
+            token.cancel();
+            return;
+        }
+        self.entries.retain(|e| {
+            e.id != id
+                || matches!(
+                    e.status,
+                    QueueStatus::Downloading(_) | QueueStatus::Importing
+                )
+        });
+        self.save();
+    }
+
+    /// Starts waiting entries while fewer than the configured number of downloads run,
+    /// then shows the queue as it is now.
+    pub async fn refresh(
+        &mut self,
+        sender: &Sender<AppEvent>,
+        requests: &UnboundedSender<AppEvent>,
+    ) {
+        let mut running = self
+            .entries
+            .iter()
+            .filter(|e| matches!(e.status, QueueStatus::Downloading(_)))
+            .count();
+
+        for entry in self.entries.iter_mut() {
+            if running >= self.concurrency {
+                break;
+            }
+            if matches!(entry.status, QueueStatus::Queued) {
+                entry.status = QueueStatus::Downloading(String::new());
+                running += 1;
+                let token = CancellationToken::new();
+                self.running.insert(entry.id, token.clone());
+                tokio::spawn(download(
+                    entry.id,
+                    entry.job.clone(),
+                    entry.format,
+                    requests.clone(),
+                    token,
+                ));
+            }
+        }
+
+        let _ = sender
+            .send(AppEvent::QueueChanged(self.entries.clone()))
+            .await;
+    }
+}
+
+async fn run(job: &QueueJob, dir: &DownloadDir, progress: Sender<AppEvent>) -> io::Result<()> {
+    let url = job.url().ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "the track has no SoundCloud link",
+    ))?;
+    dlp::download(&url, &dir.path, dir.format, progress).await
+}
+
+/// The directory of a queue entry together with the format its audio files were extracted in.
+pub struct DownloadDir {
+    pub path: PathBuf,
+    pub format: DownloadFormat,
+}
+
+impl DownloadDir {
+    /// The audio file yt-dlp made of the item with this id.
+    pub fn track_path(&self, id: &str) -> PathBuf {
+        // ids may contain dots, set_extension would cut them off
+        self.path
+            .join(format!("{}.{}", id, self.format.extension()))
+    }
+
+    /// Whether yt-dlp got at least one audio file into the directory.
+    pub fn has_downloads(&self) -> bool {
+        std::fs::read_dir(&self.path)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .any(|e| {
+                e.path()
+                    .extension()
+                    .is_some_and(|e| e == self.format.extension())
+            })
+    }
+}
+
+/// Runs yt-dlp for one entry and reports back to the sync service through its own request channel.
+async fn download(
+    id: u32,
+    job: QueueJob,
+    format: DownloadFormat,
+    requests: UnboundedSender<AppEvent>,
+    token: CancellationToken,
+) {
+    let dir = DownloadDir {
+        path: download_dir(id),
+        format,
+    };
+    let (tx, mut rx) = mpsc::channel(16);
+
+    // the import needs to know what the URL turned out to be
+    if let QueueJob::Url(url, None) = &job {
+        let resolved = tokio::select! {
+            r = dlp::resolve(url) => r.map_err(|e| e.to_string()),
+            _ = token.cancelled() => Err("cancelled".to_string()),
+        };
+        match resolved {
+            Ok(info) => {
+                let _ = requests.send(AppEvent::QueueResolved((id, info)));
+            }
+            Err(reason) => {
+                let _ = requests.send(AppEvent::QueueDownloaded((id, Err(reason))));
+                return;
+            }
+        }
+    }
+
+    let mut download = Box::pin(run(&job, &dir, tx));
+
+    // "3/12 45.2% of 4.10MiB, ETA 00:12"
+    let mut item = String::new();
+    let result = loop {
+        tokio::select! {
+            r = &mut download => break r.map_err(|e| ["yt-dlp failed: ", &e.to_string()].concat()),
+            // dropping the download kills yt-dlp, the half done files go with the directory
+            _ = token.cancelled() => break Err("cancelled".to_string()),
+            Some(event) = rx.recv() => {
+                let progress = match event {
+                    AppEvent::OverallProgress((c, max, _)) if max > 1 => {
+                        item = format!("{}/{} ", c, max);
+                        continue;
+                    }
+                    AppEvent::CurrentProgress(p) => format!(
+                        "{}{} of {}, ETA {}",
+                        item,
+                        p.progress_percentage.trim(),
+                        p.progress_total.trim(),
+                        p.eta.trim()
+                    ),
+                    _ => continue,
+                };
+                let _ = requests.send(AppEvent::QueueProgress((id, progress)));
+            }
+        }
+    };
+    // yt-dlp is gone before the directory is cleaned up
+    drop(download);
+
+    let _ = requests.send(AppEvent::QueueDownloaded((id, result)));
+}
@@ -0,0 +1,332 @@
+use crate::config::SoundCheckConfiguration;
+use mp4ameta::FreeformIdent;
+use std::f64::consts::PI;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+const ITUNNORM: FreeformIdent = FreeformIdent::new("com.apple.iTunes", "iTunNORM");
+const REPLAYGAIN: FreeformIdent = FreeformIdent::new("com.apple.iTunes", "replaygain_track_gain");
+
+// ReplayGain 2.0 normalises to -18 LUFS
+const REFERENCE_LOUDNESS: f64 = -18.0;
+// the R128 tags of Opus files to -23 LUFS
+const R128_LOUDNESS: f64 = -23.0;
+
+/// Sound Check is the gain as a power ratio in 1/1000ths, 1000 leaves the volume alone.
+pub fn gain_to_soundcheck(gain_db: f64) -> u32 {
+    (1000.0 * 10f64.powf(-gain_db / 10.0))
+        .round()
+        .clamp(1.0, u32::MAX as f64) as u32
+}
+
+// "-6.54 dB"
+fn parse_gain(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .trim_end_matches("dB")
+        .trim_end_matches("db")
+        .trim()
+        .parse()
+        .ok()
+}
+
+// " 000003E8 000003E8 ..." - the louder of the two 1/1000 W values
+fn parse_itunnorm(value: &str) -> Option<u32> {
+    let mut fields = value
+        .split_whitespace()
+        .map(|f| u32::from_str_radix(f, 16).ok());
+    let left = fields.next()??;
+    let right = fields.next()??;
+    Some(left.max(right)).filter(|s| *s != 0)
+}
+
+fn read_mp4(path: &Path) -> Option<u32> {
+    let tag = mp4ameta::Tag::read_from_path(path).ok()?;
+    let soundcheck = tag.strings_of(&ITUNNORM).find_map(parse_itunnorm);
+    soundcheck.or_else(|| {
+        tag.strings_of(&REPLAYGAIN)
+            .find_map(parse_gain)
+            .map(gain_to_soundcheck)
+    })
+}
+
+fn read_id3(path: &Path) -> Option<u32> {
+    let tag = id3::Tag::read_from_path(path).ok()?;
+    let soundcheck = tag
+        .comments()
+        .filter(|c| c.description == "iTunNORM")
+        .find_map(|c| parse_itunnorm(&c.text));
+    soundcheck.or_else(|| {
+        tag.extended_texts()
+            .filter(|t| t.description.eq_ignore_ascii_case("REPLAYGAIN_TRACK_GAIN"))
+            .find_map(|t| parse_gain(&t.value))
+            .map(gain_to_soundcheck)
+    })
+}
+
+// "-1234" - Q7.8 fixed point dB
+fn parse_r128(value: &str) -> Option<f64> {
+    let gain = value.trim().parse::<i16>().ok()? as f64 / 256.0;
+    Some(gain + REFERENCE_LOUDNESS - R128_LOUDNESS)
+}
+
+/// ReplayGain in the Vorbis comments of FLAC and Ogg files, or the R128 gain of Opus.
+/// The transcoded copies lose these, so this reads the original file.
+fn read_vorbis(path: &Path) -> Option<u32> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            MediaSourceStream::new(Box::new(file), Default::default()),
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+    let metadata = probed.format.metadata();
+    let tags = metadata.current()?.tags();
+    let tag = |key: &str| {
+        tags.iter()
+            .find(|t| t.key.eq_ignore_ascii_case(key))
+            .map(|t| t.value.to_string())
+    };
+    tag("REPLAYGAIN_TRACK_GAIN")
+        .and_then(|v| parse_gain(&v))
+        .or_else(|| tag("R128_TRACK_GAIN").and_then(|v| parse_r128(&v)))
+        .map(gain_to_soundcheck)
+}
+
+/// Sound Check from iTunNORM or ReplayGain tags.
+pub fn from_tags(path: &Path) -> Option<u32> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "m4a" | "m4b" | "mp4" => read_mp4(path),
+        "flac" | "ogg" | "oga" | "opus" => read_vorbis(path),
+        _ => read_id3(path),
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[1] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[2] * y;
+        y
+    }
+}
+
+// the two stage K-weighting filter from ITU-R BS.1770, worked out for any sample rate
+fn k_weighting(rate: f64) -> [Biquad; 2] {
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    [shelf, highpass]
+}
+
+struct LoudnessMeter {
+    filters: Vec<[Biquad; 2]>,
+    step: usize,
+    position: usize,
+    energy: f64,
+    // mean square of every 100ms step, summed over the channels
+    steps: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    fn new(rate: u32, channels: usize) -> Self {
+        Self {
+            filters: vec![k_weighting(rate as f64); channels],
+            step: (rate / 10) as usize,
+            position: 0,
+            energy: 0.0,
+            steps: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, interleaved: &[f32]) {
+        let channels = self.filters.len();
+        for frame in interleaved.chunks_exact(channels) {
+            for (sample, [shelf, highpass]) in frame.iter().zip(self.filters.iter_mut()) {
+                let y = highpass.process(shelf.process(*sample as f64));
+                self.energy += y * y;
+            }
+            self.position += 1;
+            if self.position == self.step {
+                self.steps.push(self.energy / self.step as f64);
+                self.position = 0;
+                self.energy = 0.0;
+            }
+        }
+    }
+
+    /// Gated integrated loudness in LUFS.
+    fn integrated(&self) -> Option<f64> {
+        let loudness = |energy: f64| -0.691 + 10.0 * energy.log10();
+        // 400ms blocks overlapping by 75%
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(4)
+            .map(|w| w.iter().sum::<f64>() / 4.0)
+            .filter(|e| loudness(*e) > -70.0)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+        let relative_gate = loudness(blocks.iter().sum::<f64>() / blocks.len() as f64) - 10.0;
+        let gated: Vec<&f64> = blocks
+            .iter()
+            .filter(|e| loudness(**e) > relative_gate)
+            .collect();
+        if gated.is_empty() {
+            return None;
+        }
+        Some(loudness(
+            gated.iter().copied().sum::<f64>() / gated.len() as f64,
+        ))
+    }
+}
+
+/// Decodes the whole track and measures its EBU R128 loudness.
+pub fn analyze(path: &Path) -> Option<u32> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            MediaSourceStream::new(Box::new(file), Default::default()),
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?
+        .format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .ok()?;
+
+    let mut meter: Option<LoudnessMeter> = None;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(Error::DecodeError(_)) => continue,
+            Err(_e) => break,
+        };
+        let spec = *decoded.spec();
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+        meter
+            .get_or_insert_with(|| LoudnessMeter::new(spec.rate, spec.channels.count()))
+            .push(samples.samples());
+    }
+
+    let loudness = meter?.integrated()?;
+    Some(gain_to_soundcheck(REFERENCE_LOUDNESS - loudness))
+}
+
+/// Tags of the file going onto the iPod come first, then the ones of the original it was transcoded from.
+/// Falls back to measuring the loudness when that's enabled in the config.
+pub fn read(source: &Path, original: &Path, config: &SoundCheckConfiguration) -> Option<u32> {
+    from_tags(source)
+        .or_else(|| from_tags(original))
+        .or_else(|| {
+            if config.analyze_loudness {
+                analyze(source)
+            } else {
+                None
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gain_as_power_ratio() {
+        assert_eq!(gain_to_soundcheck(0.0), 1000);
+        assert_eq!(gain_to_soundcheck(-6.0), 3981);
+        assert_eq!(gain_to_soundcheck(6.0), 251);
+        assert_eq!(gain_to_soundcheck(-10.0), 10000);
+        assert_eq!(gain_to_soundcheck(200.0), 1);
+    }
+
+    #[test]
+    fn replaygain_values() {
+        assert_eq!(parse_gain("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_gain("+2.10 dB"), Some(2.1));
+        assert_eq!(parse_gain("1.5db"), Some(1.5));
+        assert_eq!(parse_gain("loud"), None);
+    }
+
+    #[test]
+    fn itunnorm_takes_louder_channel() {
+        let value = " 000003E8 00000A00 00002B9C 00002B9C 00000000 00000000 00007FFF 00007FFF 00000000 00000000";
+        assert_eq!(parse_itunnorm(value), Some(0xA00));
+        assert_eq!(parse_itunnorm(" 00000000 00000000"), None);
+        assert_eq!(parse_itunnorm(" 000003E8"), None);
+        assert_eq!(parse_itunnorm(" 000003E8 0000ZZZZ"), None);
+    }
+
+    #[test]
+    fn r128_to_replaygain_reference() {
+        // Q7.8 gain towards -23 LUFS, 5 dB more for the -18 LUFS of ReplayGain
+        assert_eq!(parse_r128("0"), Some(5.0));
+        assert_eq!(parse_r128("-1280"), Some(0.0));
+        assert_eq!(parse_r128(" 256 "), Some(6.0));
+        assert_eq!(parse_r128("40000"), None);
+        assert_eq!(parse_r128("0").map(gain_to_soundcheck), Some(316));
+    }
+}
@@ -1,6 +1,13 @@
-use crate::dlp::DownloadProgress;
+use crate::config::TranscodeProfile;
+use crate::dlp::{DownloadProgress, MediaInfo};
 use crate::screens::search_util::SearchEntry;
+use crate::sync::acoustid::Identification;
+use crate::sync::device::DryRunReport;
+use crate::sync::metadata::MetadataChanges;
+use crate::sync::musicbrainz::{MusicBrainzIds, ReleaseCandidate, ReleaseProposal};
+use crate::sync::queue::QueueEntry;
 use crate::AppState;
+use image::RgbaImage;
 use itunesdb::xobjects::XTrackItem;
 use soundcloud::sobjects::{CloudPlaylist, CloudPlaylists, CloudTrack};
 use std::path::PathBuf;
@@ -12,22 +19,72 @@ pub enum AppEvent {
     ITunesParsed(Vec<DBPlaylist>),
     YoutubeGot(Vec<YTPlaylist>),
     SoundcloudGot(CloudPlaylists),
-    DownloadPlaylist(CloudPlaylist),
+    DownloadPlaylist((CloudPlaylist, TranscodeProfile)),
     DownloadTrack(CloudTrack),
-    DownloadYTPlaylist(YTPlaylist),
+    DownloadYTPlaylist((YTPlaylist, TranscodeProfile)),
     DownloadYTTrack(YoutubeVideo),
+    DownloadUrl((String, TranscodeProfile)),
     CurrentProgress(DownloadProgress),
     OverallProgress((u32, u32, ratatui::style::Color)),
     ArtworkProgress((u32, u32)),
     SwitchScreen(AppState),
-    LoadFromFS(PathBuf),
-    LoadFromFSVec(Vec<PathBuf>),
-    LoadFromFSPL((Vec<PathBuf>, String)),
+    LoadFromFS((PathBuf, TranscodeProfile)),
+    LoadFromFSVec((Vec<PathBuf>, TranscodeProfile)),
+    LoadFromFSPL((Vec<PathBuf>, String, TranscodeProfile)),
     RemoveTrack(u32),
     RemovePlaylist((u64, bool)),
     RemoveTrackFromPlaylist((u32, u64)),
     SearchFor(String),
     SearchShow(Vec<SearchEntry>),
+    EditMetadata(Vec<u32>),
+    MetadataShow(Vec<XTrackItem>),
+    UpdateMetadata((Vec<u32>, MetadataChanges, bool)),
+    UpdateTracksMetadata((Vec<(u32, MetadataChanges)>, bool)),
+    IdentifyTracks(Vec<u32>),
+    IdentifyShow(Vec<Identification>),
+    MusicBrainzLookup(Vec<u32>),
+    MusicBrainzCandidates((Vec<u32>, Result<Vec<ReleaseCandidate>, String>)),
+    MusicBrainzRelease((Vec<u32>, ReleaseCandidate)),
+    MusicBrainzProposal(Result<ReleaseProposal, String>),
+    MusicBrainzApply((Vec<(u32, MetadataChanges, MusicBrainzIds)>, bool)),
+    PickArtwork(Vec<u32>),
+    // an image file, or None to look the cover up online
+    SetArtwork((Vec<u32>, Option<PathBuf>)),
+    ShowLyrics(u32),
+    LyricsGot((u32, Option<String>)),
+    ShowCover(u32),
+    CoverGot((u32, Option<RgbaImage>)),
+    ExportCovers(Vec<u32>),
+    CoversExported((usize, PathBuf)),
+    DryRunReport(DryRunReport),
+    ImportSummary(ImportSummary),
+    QueueChanged(Vec<QueueEntry>),
+    // sent by the download workers back to the sync service
+    QueueResolved((u32, MediaInfo)),
+    QueueProgress((u32, String)),
+    QueueDownloaded((u32, Result<(), String>)),
+    QueueRetry(u32),
+    QueueRemove(u32),
+}
+
+impl AppEvent {
+    /// Requests which write to the iPod and are therefore only planned during a dry run.
+    pub fn changes_device(&self) -> bool {
+        matches!(
+            self,
+            AppEvent::QueueDownloaded(_)
+                | AppEvent::LoadFromFS(_)
+                | AppEvent::LoadFromFSVec(_)
+                | AppEvent::LoadFromFSPL(_)
+                | AppEvent::RemoveTrack(_)
+                | AppEvent::RemovePlaylist(_)
+                | AppEvent::RemoveTrackFromPlaylist(_)
+                | AppEvent::UpdateMetadata(_)
+                | AppEvent::UpdateTracksMetadata(_)
+                | AppEvent::MusicBrainzApply(_)
+                | AppEvent::SetArtwork(_)
+        )
+    }
 }
 
 pub struct DBPlaylist {
@@ -43,3 +100,66 @@ pub struct YTPlaylist {
     pub url: String,
     pub videos: Vec<YoutubeVideo>,
 }
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportOutcome {
+    Imported,
+    Duplicate,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportResult {
+    pub item: String,
+    // local files can be retried, downloads can't
+    pub path: Option<PathBuf>,
+    pub outcome: ImportOutcome,
+    // something the user should know about an imported item
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ImportSummary {
+    pub imported: u32,
+    pub shrunk: u32,
+    pub bytes_saved: u64,
+    pub profile: TranscodeProfile,
+    // the title of the playlist the files were imported as
+    pub playlist: Option<String>,
+    pub results: Vec<ImportResult>,
+}
+
+impl ImportSummary {
+    pub fn new(profile: TranscodeProfile) -> Self {
+        Self {
+            profile,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, item: String, path: Option<PathBuf>, outcome: ImportOutcome) {
+        if outcome == ImportOutcome::Imported {
+            self.imported += 1;
+        }
+        self.results.push(ImportResult {
+            item,
+            path,
+            outcome,
+            note: None,
+        });
+    }
+
+    /// Attaches a note to the item added last.
+    pub fn note(&mut self, note: String) {
+        if let Some(result) = self.results.last_mut() {
+            result.note = Some(note);
+        }
+    }
+
+    pub fn failures(&self) -> Vec<&ImportResult> {
+        self.results
+            .iter()
+            .filter(|r| matches!(r.outcome, ImportOutcome::Failed(_)))
+            .collect()
+    }
+}
@@ -0,0 +1,238 @@
+use crate::config::{get_temp_dl_dir, get_transcode_cache_dir, TranscodeConfiguration};
+use crate::sync::audio_file_info::{self, AudioInfo, Codec};
+use crate::util;
+use id3::frame::{Picture, PictureType};
+use id3::TagLike;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, StandardVisualKey};
+use symphonia::core::probe::Hint;
+use tokio::process::Command;
+
+const LOSSLESS_FORMATS: [&str; 1] = ["flac"];
+const LOSSY_FORMATS: [&str; 4] = ["ogg", "oga", "opus", "wma"];
+// cover art lives in a METADATA_BLOCK_PICTURE comment, which ffmpeg doesn't map to the output
+const XIPH_FORMATS: [&str; 3] = ["ogg", "oga", "opus"];
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+pub fn needs_transcoding(path: &Path) -> bool {
+    let ext = extension(path);
+    LOSSLESS_FORMATS.contains(&ext.as_str()) || LOSSY_FORMATS.contains(&ext.as_str())
+}
+
+fn codec_args(format: &str, bitrate: u32) -> (Vec<String>, &'static str) {
+    let bitrate = [bitrate.to_string(), "k".to_string()].concat();
+    match format {
+        "alac" => (
+            ["-c:a", "alac", "-sample_fmt", "s16p"]
+                .map(String::from)
+                .to_vec(),
+            "m4a",
+        ),
+        "mp3" => (
+            [
+                "-c:a",
+                "libmp3lame",
+                "-b:a",
+                &bitrate,
+                "-id3v2_version",
+                "3",
+            ]
+            .map(String::from)
+            .to_vec(),
+            "mp3",
+        ),
+        _ => (
+            ["-c:a", "aac", "-b:a", &bitrate].map(String::from).to_vec(),
+            "m4a",
+        ),
+    }
+}
+
+async fn run_ffmpeg(
+    path: &Path,
+    dest: &Path,
+    codec: Vec<String>,
+    config: &TranscodeConfiguration,
+) -> Option<()> {
+    let mut command = Command::new(&config.ffmpeg_path);
+    // a cancelled import drops the future, ffmpeg shouldn't keep writing into the temp directory
+    command.kill_on_drop(true);
+    command.args(["-nostdin", "-y", "-loglevel", "error", "-i"]);
+    command.arg(path);
+    command.args([
+        "-map",
+        "0:a:0",
+        "-map",
+        "0:v?",
+        "-c:v",
+        "copy",
+        "-disposition:v",
+        "attached_pic",
+        "-map_metadata",
+        "0",
+    ]);
+    command.args(codec);
+
+    // the iPod tops out at 48kHz
+    if audio_file_info::from_path(path.to_str()?)
+        .await
+        .is_some_and(|i| i.get_nice_object().sample_rate > 48000)
+    {
+        command.args(["-ar", "44100"]);
+    }
+
+    command.arg(dest);
+    command.stdout(Stdio::null());
+    command.stderr(Stdio::null());
+
+    let status = command.status().await.ok()?;
+    if !status.success() || !dest.exists() {
+        let _ = std::fs::remove_file(dest);
+        return None;
+    }
+    Some(())
+}
+
+/// The cover of an Ogg file, front cover first.
+fn xiph_picture(path: &Path) -> Option<(String, Vec<u8>)> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut hint = Hint::new();
+    hint.with_extension("ogg");
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            MediaSourceStream::new(Box::new(file), Default::default()),
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+    let metadata = probed.format.metadata();
+    let visuals = metadata.current()?.visuals();
+    let visual = visuals
+        .iter()
+        .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+        .or(visuals.first())?;
+    Some((visual.media_type.clone(), visual.data.to_vec()))
+}
+
+/// Puts the picture into the converted file, unless ffmpeg already carried one over.
+/// `out_ext` is the format of the file, it's still named .part at this point.
+fn embed_picture(path: &Path, out_ext: &str, media_type: String, data: Vec<u8>) -> bool {
+    if out_ext == "mp3" {
+        let mut tag = id3::Tag::read_from_path(path).unwrap_or_default();
+        if tag.pictures().next().is_some() {
+            return true;
+        }
+        tag.add_frame(Picture {
+            mime_type: media_type,
+            picture_type: PictureType::CoverFront,
+            description: String::new(),
+            data,
+        });
+        // older iPod firmwares only read ID3v2.3 properly
+        return tag.write_to_path(path, id3::Version::Id3v23).is_ok();
+    }
+
+    match mp4ameta::Tag::read_from_path(path) {
+        Ok(mut tag) => {
+            if tag.artwork().is_some() {
+                return true;
+            }
+            let fmt = match media_type.as_str() {
+                "image/png" => mp4ameta::ImgFmt::Png,
+                "image/bmp" => mp4ameta::ImgFmt::Bmp,
+                _ => mp4ameta::ImgFmt::Jpeg,
+            };
+            tag.set_artwork(mp4ameta::Img { fmt, data });
+            tag.write_to_path(path).is_ok()
+        }
+        Err(_e) => false,
+    }
+}
+
+/// Converts through the cache in ~/.lyrica, so syncing the same file again doesn't re-encode it.
+/// Returns a copy inside the temp directory, the caller is free to modify or delete it.
+async fn convert(
+    path: &Path,
+    format: &str,
+    bitrate: u32,
+    config: &TranscodeConfiguration,
+) -> Option<PathBuf> {
+    let (mut codec, out_ext) = codec_args(format, bitrate);
+    // ffmpeg can't tell the container from the .part extension
+    let muxer = if out_ext == "mp3" { "mp3" } else { "ipod" };
+    codec.extend(["-f".to_string(), muxer.to_string()]);
+
+    let mut cached = get_transcode_cache_dir();
+    let _ = std::fs::create_dir_all(&cached);
+    cached.push(
+        [
+            format!("{:016x}", util::hash_from_path(path.to_path_buf())?),
+            format.to_string(),
+            bitrate.to_string(),
+        ]
+        .join("-"),
+    );
+    cached.set_extension(out_ext);
+
+    if !cached.exists() {
+        // written under another name first, an interrupted encode must not end up in the cache,
+        // and a unique one, two imports may convert the same file at once
+        let mut partial = cached.clone();
+        partial.set_extension(format!("{}.{:08x}.part", out_ext, rand::random::<u32>()));
+        run_ffmpeg(path, &partial, codec, config).await?;
+        if XIPH_FORMATS.contains(&extension(path).as_str()) {
+            if let Some((media_type, data)) = xiph_picture(path) {
+                embed_picture(&partial, out_ext, media_type, data);
+            }
+        }
+        std::fs::rename(&partial, &cached).ok()?;
+    }
+
+    let mut dest = get_temp_dl_dir();
+    dest.push("transcode");
+    let _ = std::fs::create_dir_all(&dest);
+    // named after the cache entry, files with the same name can be converted side by side
+    dest.push(cached.file_name()?);
+    std::fs::copy(&cached, &dest).ok()?;
+    Some(dest)
+}
+
+/// Converts a file the iPod can't play into the configured target format.
+/// Returns the path of the converted copy inside the temp directory.
+pub async fn transcode(path: &Path, config: &TranscodeConfiguration) -> Option<PathBuf> {
+    let ext = extension(path);
+    let format = if LOSSLESS_FORMATS.contains(&ext.as_str()) {
+        config.lossless_format.as_str()
+    } else if config.lossy_format == "mp3" {
+        "mp3"
+    } else {
+        "aac"
+    };
+    convert(path, format, config.bitrate, config).await
+}
+
+/// Whether the space saving profile re-encodes this track: lossless audio and anything above the threshold.
+pub fn exceeds_threshold(audio_file: &AudioInfo, config: &TranscodeConfiguration) -> bool {
+    let lossless = matches!(audio_file.codec(), Codec::Alac | Codec::Aiff | Codec::Wav);
+    lossless || audio_file.get_nice_object().bit_rate / 1000 > config.space_saving_threshold as u64
+}
+
+/// Re-encodes into the smaller space saving format, the copy lands in the temp directory.
+pub async fn shrink(path: &Path, config: &TranscodeConfiguration) -> Option<PathBuf> {
+    let format = if config.space_saving_format == "mp3" {
+        "mp3"
+    } else {
+        "aac"
+    };
+    convert(path, format, config.space_saving_bitrate, config).await
+}
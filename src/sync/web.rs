@@ -0,0 +1,46 @@
+use serde::de::DeserializeOwned;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+const USER_AGENT: &str = concat!("lyrica/", env!("CARGO_PKG_VERSION"));
+
+// shared, so the connections to the same service are reused
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap_or_default()
+});
+
+/// Sends the request and reads the body, an error status such as 404 or 503 counts as a failure.
+async fn send(request: reqwest::RequestBuilder) -> Result<Vec<u8>, String> {
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(["the server answered ", &status.to_string()].concat());
+    }
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    Ok(body.to_vec())
+}
+
+/// Asks a web service for JSON, the parameters go into the query or, with `post`, a form body.
+pub async fn request<T: DeserializeOwned>(
+    url: &str,
+    params: &[(&str, String)],
+    post: bool,
+) -> Result<T, String> {
+    let request = if post {
+        CLIENT.post(url).form(params)
+    } else {
+        CLIENT.get(url).query(params)
+    };
+
+    serde_json::from_slice(&send(request).await?)
+        .map_err(|e| ["unexpected response: ", &e.to_string()].concat())
+}
+
+/// Fetches a file, following redirects.
+pub async fn download(url: &str) -> Result<Vec<u8>, String> {
+    send(CLIENT.get(url)).await
+}
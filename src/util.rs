@@ -1,6 +1,7 @@
-use image::DynamicImage;
+use crate::config::Dither;
+use crate::sync::ipod_model::{ArtworkFormat, PixelFormat};
+use image::{DynamicImage, Rgba, RgbaImage};
 use regex::Regex;
-use std::io::Write;
 use std::path::PathBuf;
 use std::{error::Error, process::Command, str, str::FromStr};
 use twox_hash::XxHash3_64;
@@ -116,58 +117,317 @@ pub fn hash(data: &[u8]) -> u64 {
     XxHash3_64::oneshot(data)
 }
 
-pub fn hash_from_path(path: PathBuf) -> u64 {
-    hash(&std::fs::read(path).unwrap())
+pub fn hash_from_path(path: PathBuf) -> Option<u64> {
+    std::fs::read(path).ok().map(|data| hash(&data))
 }
 
 pub struct IPodImage {
-    pixels: Vec<u16>,
+    data: Vec<u8>,
 }
 
 impl IPodImage {
-    pub fn write(&self, p: PathBuf) {
-        let mut file = std::fs::File::create(p).unwrap();
-        let _ = file.write(&self.convert_to_u8());
+    /// Encodes an image already scaled to the format's size, dithered as configured.
+    pub fn encode(value: &DynamicImage, format: &ArtworkFormat, dither: Dither) -> Self {
+        let img_rgba = value.to_rgba8();
+        let (width, height) = img_rgba.dimensions();
+
+        let rows: Vec<Vec<u8>> = match format.pixel_format {
+            PixelFormat::Rgb565Le => quantize_rgb565(&img_rgba, dither)
+                .chunks(width.max(1) as usize)
+                .map(|row| row.iter().flat_map(|pixel| pixel.to_le_bytes()).collect())
+                .collect(),
+            // two pixels share their chroma, dithering is for the 16-bit colour of covers
+            PixelFormat::Uyvy => row_order(format, height)
+                .map(|y| {
+                    (0..width)
+                        .step_by(2)
+                        .flat_map(|x| {
+                            let a = img_rgba.get_pixel(x, y).0;
+                            let b = img_rgba.get_pixel((x + 1).min(width - 1), y).0;
+                            let (y0, u0, v0) = rgb_to_yuv(a[0], a[1], a[2]);
+                            let (y1, u1, v1) = rgb_to_yuv(b[0], b[1], b[2]);
+                            let u = ((u0 as u16 + u1 as u16) / 2) as u8;
+                            let v = ((v0 as u16 + v1 as u16) / 2) as u8;
+                            [u, y0, v, y1]
+                        })
+                        .collect()
+                })
+                .collect(),
+        };
+
+        let mut data: Vec<u8> = Vec::with_capacity(format.image_size() as usize);
+        for row in rows {
+            data.extend(row);
+            // two bytes per padding pixel
+            data.resize(data.len() + format.row_padding as usize * 2, 0);
+        }
+
+        Self { data }
     }
 
-    fn convert_to_u8(&self) -> Vec<u8> {
-        self.pixels
-            .iter()
-            .flat_map(|f| [*f as u8, (*f >> 8) as u8])
-            .collect()
+    /// Turns a thumbnail read from an ithmb file back into pixels, None if there isn't enough data.
+    pub fn decode(data: &[u8], format: &ArtworkFormat) -> Option<RgbaImage> {
+        if data.len() < format.image_size() as usize {
+            return None;
+        }
+
+        let (width, height) = (format.width, format.height);
+        let stride = format.stride() as usize * 2;
+        let mut img = RgbaImage::new(width, height);
+
+        for (row, y) in row_order(format, height).enumerate() {
+            let line = &data[row * stride..(row + 1) * stride];
+            for x in 0..width {
+                let (r, g, b) = match format.pixel_format {
+                    PixelFormat::Rgb565Le => {
+                        let i = x as usize * 2;
+                        rgb565_to_rgb(u16::from_le_bytes([line[i], line[i + 1]]))
+                    }
+                    PixelFormat::Uyvy => {
+                        let i = (x as usize / 2) * 4;
+                        let luma = if x % 2 == 0 { line[i + 1] } else { line[i + 3] };
+                        yuv_to_rgb(luma, line[i], line[i + 2])
+                    }
+                };
+                img.put_pixel(x, y, Rgba([r, g, b, 255]));
+            }
+        }
+
+        Some(img)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
     }
 }
 
-impl From<DynamicImage> for IPodImage {
-    fn from(value: DynamicImage) -> Self {
-        let img_rgba = value.to_rgba8();
+// 4x4 Bayer matrix, thresholds in sixteenths
+const BAYER: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
 
-        let (width, height) = img_rgba.dimensions();
+// bits per channel of RGB565
+const RGB565_BITS: [u32; 3] = [5, 6, 5];
+
+/// Rounds a channel to the nearest of the levels `bits` can hold.
+fn quantize(value: f32, bits: u32) -> u16 {
+    let max = ((1 << bits) - 1) as f32;
+    (value.clamp(0.0, 255.0) * max / 255.0).round() as u16
+}
 
-        let mut rgb565_data: Vec<u16> = Vec::new();
+// the 8 bit value a quantized channel stands for
+fn expand(level: u16, bits: u32) -> f32 {
+    level as f32 * 255.0 / ((1 << bits) - 1) as f32
+}
 
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = img_rgba.get_pixel(x, y).0;
+fn pack_rgb565(levels: [u16; 3]) -> u16 {
+    (levels[0] << 11) | (levels[1] << 5) | levels[2]
+}
 
-                let r = pixel[0];
-                let g = pixel[1];
-                let b = pixel[2];
+/// The pixels of the image in RGB565, row by row.
+fn quantize_rgb565(img: &RgbaImage, dither: Dither) -> Vec<u16> {
+    let (width, height) = img.dimensions();
 
-                rgb565_data.push(rgb_to_rgb565(r, g, b));
+    match dither {
+        Dither::None => img
+            .pixels()
+            .map(|p| pack_rgb565([0, 1, 2].map(|c| quantize(p.0[c] as f32, RGB565_BITS[c]))))
+            .collect(),
+        Dither::Ordered => img
+            .enumerate_pixels()
+            .map(|(x, y, p)| {
+                let threshold = (BAYER[y as usize % 4][x as usize % 4] + 0.5) / 16.0 - 0.5;
+                pack_rgb565([0, 1, 2].map(|c| {
+                    // one step between two levels of the channel
+                    let step = 255.0 / ((1 << RGB565_BITS[c]) - 1) as f32;
+                    quantize(p.0[c] as f32 + threshold * step, RGB565_BITS[c])
+                }))
+            })
+            .collect(),
+        Dither::FloydSteinberg => {
+            let mut pixels: Vec<[f32; 3]> = img
+                .pixels()
+                .map(|p| [p.0[0] as f32, p.0[1] as f32, p.0[2] as f32])
+                .collect();
+            let mut out = Vec::with_capacity(pixels.len());
+            let (w, h) = (width as usize, height as usize);
+
+            for y in 0..h {
+                for x in 0..w {
+                    let old = pixels[y * w + x];
+                    let levels = [0, 1, 2].map(|c| quantize(old[c], RGB565_BITS[c]));
+                    out.push(pack_rgb565(levels));
+
+                    for c in 0..3 {
+                        let error = old[c] - expand(levels[c], RGB565_BITS[c]);
+                        let mut spread = |x: usize, y: usize, weight: f32| {
+                            if x < w && y < h {
+                                pixels[y * w + x][c] += error * weight;
+                            }
+                        };
+                        spread(x + 1, y, 7.0 / 16.0);
+                        if x > 0 {
+                            spread(x - 1, y + 1, 3.0 / 16.0);
+                        }
+                        spread(x, y + 1, 5.0 / 16.0);
+                        spread(x + 1, y + 1, 1.0 / 16.0);
+                    }
+                }
             }
+            out
         }
+    }
+}
 
-        Self {
-            pixels: rgb565_data,
-        }
+// UYVY images are interlaced, all even rows come first and then all odd ones
+fn row_order(format: &ArtworkFormat, height: u32) -> Box<dyn Iterator<Item = u32>> {
+    match format.pixel_format {
+        PixelFormat::Rgb565Le => Box::new(0..height),
+        PixelFormat::Uyvy => Box::new((0..height).step_by(2).chain((1..height).step_by(2))),
+    }
+}
+
+fn rgb565_to_rgb(rgb565: u16) -> (u8, u8, u8) {
+    let r = ((rgb565 >> 11) & 0x1F) as u8;
+    let g = ((rgb565 >> 5) & 0x3F) as u8;
+    let b = (rgb565 & 0x1F) as u8;
+
+    // repeat the top bits so white stays white
+    (
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    )
+}
+
+// BT.601, studio range
+fn rgb_to_yuv(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+    let u = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+    let v = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+    (y.round() as u8, u.round() as u8, v.round() as u8)
+}
+
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    let y = 1.164 * (y as f32 - 16.0);
+    let (u, v) = (u as f32 - 128.0, v as f32 - 128.0);
+    let r = y + 1.596 * v;
+    let g = y - 0.392 * u - 0.813 * v;
+    let b = y + 2.017 * u;
+    (
+        r.clamp(0.0, 255.0) as u8,
+        g.clamp(0.0, 255.0) as u8,
+        b.clamp(0.0, 255.0) as u8,
+    )
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
     }
+    format!("{:.1} {}", size, units[unit])
 }
 
-fn rgb_to_rgb565(r: u8, g: u8, b: u8) -> u16 {
-    let r_565 = (r >> 3) & 0x1F; // Extract top 5 bits
-    let g_565 = (g >> 2) & 0x3F; // Extract top 6 bits
-    let b_565 = (b >> 3) & 0x1F; // Extract top 5 bits
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(value: u8, size: u32) -> RgbaImage {
+        RgbaImage::from_pixel(size, size, Rgba([value, value, value, 255]))
+    }
+
+    // mean of the red channel, back in 8 bit
+    fn mean_red(pixels: &[u16]) -> f32 {
+        pixels.iter().map(|p| expand(p >> 11, 5)).sum::<f32>() / pixels.len() as f32
+    }
+
+    #[test]
+    fn rgb565_extremes() {
+        for dither in [Dither::None, Dither::Ordered, Dither::FloydSteinberg] {
+            assert!(quantize_rgb565(&flat(255, 4), dither)
+                .iter()
+                .all(|p| *p == 0xFFFF));
+            assert!(quantize_rgb565(&flat(0, 4), dither).iter().all(|p| *p == 0));
+        }
+    }
+
+    #[test]
+    fn rgb565_grey_without_dither() {
+        let pixels = quantize_rgb565(&flat(128, 3), Dither::None);
+        assert_eq!(pixels.len(), 9);
+        assert!(pixels.iter().all(|p| *p == 0x8410));
+    }
+
+    #[test]
+    fn rgb565_dither_keeps_the_shade() {
+        // 100 falls between two red levels, rounding alone ends up on 98.7
+        let plain = mean_red(&quantize_rgb565(&flat(100, 16), Dither::None));
+        assert!((plain - 100.0).abs() > 1.0);
+        for dither in [Dither::Ordered, Dither::FloydSteinberg] {
+            let mean = mean_red(&quantize_rgb565(&flat(100, 16), dither));
+            assert!((mean - 100.0).abs() < 1.0, "{:?} gave {}", dither, mean);
+        }
+    }
+
+    fn format(pixel_format: PixelFormat, row_padding: u32) -> ArtworkFormat {
+        ArtworkFormat {
+            id: 1,
+            width: 4,
+            height: 4,
+            pixel_format,
+            row_padding,
+        }
+    }
+
+    // every row a different shade, so swapped rows show
+    fn rows() -> DynamicImage {
+        RgbaImage::from_fn(4, 4, |_, y| {
+            Rgba([y as u8 * 80, 255 - y as u8 * 80, 0, 255])
+        })
+        .into()
+    }
+
+    #[test]
+    fn rgb565_padded_rows_decode() {
+        let format = format(PixelFormat::Rgb565Le, 2);
+        let image = IPodImage::encode(&rows(), &format, Dither::None);
+        assert_eq!(image.data().len(), format.image_size() as usize);
+        let decoded = IPodImage::decode(image.data(), &format).unwrap();
+        for y in 0..4 {
+            let expected = rows().to_rgba8().get_pixel(0, y).0;
+            let got = decoded.get_pixel(3, y).0;
+            assert!(expected.iter().zip(got).all(|(e, g)| e.abs_diff(g) < 8));
+        }
+    }
+
+    #[test]
+    fn uyvy_is_interlaced() {
+        let format = format(PixelFormat::Uyvy, 0);
+        let image = IPodImage::encode(&rows(), &format, Dither::None);
+        // row 2 is stored second, right after row 0
+        let (luma0, luma2) = (rgb_to_yuv(0, 255, 0).0, rgb_to_yuv(160, 95, 0).0);
+        assert_eq!(image.data()[1], luma0);
+        assert_eq!(image.data()[8 + 1], luma2);
 
-    ((r_565 as u16) << 11) | ((g_565 as u16) << 5) | (b_565 as u16) // Combine to RGB565
+        let decoded = IPodImage::decode(image.data(), &format).unwrap();
+        for y in 0..4 {
+            let expected = rows().to_rgba8().get_pixel(1, y).0;
+            let got = decoded.get_pixel(1, y).0;
+            assert!(expected.iter().zip(got).all(|(e, g)| e.abs_diff(g) < 8));
+        }
+    }
+
+    #[test]
+    fn short_data_is_not_decoded() {
+        let format = format(PixelFormat::Rgb565Le, 0);
+        assert!(IPodImage::decode(&[0; 31], &format).is_none());
+    }
 }